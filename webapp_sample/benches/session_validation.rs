@@ -0,0 +1,49 @@
+// Compares what `Authenticate` spends on one request before and after the
+// session digest moved off bcrypt. Run with `cargo bench --bench session_validation`.
+//
+// The numbers leave out the database: the old path read the user row on
+// every request, a cache miss reads one column, and a cache hit none.
+extern crate bcrypt;
+extern crate webapp_sample;
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use webapp_sample::models::{UserSession};
+use webapp_sample::sessions::cache::{SessionCache, ValidSession};
+use webapp_sample::sessions::digest::{session_digest, verify_session_digest};
+use webapp_sample::time_zones;
+
+const SESSION_ID: &str = "k3J9x0QmZt7RwLp2VbN8cYs5Hd1FgA";
+// The cost users_message used for session digests.
+const LEGACY_COST: u32 = 5;
+
+fn bench<F: FnMut() -> bool>(name: &str, iterations: u32, mut f: F) {
+    let started = Instant::now();
+    for _ in 0..iterations {
+        assert!(black_box(f()));
+    }
+    let per_op = started.elapsed() / iterations;
+
+    println!(
+        "{:<32} {:>12.3} us/op {:>14.0} ops/s",
+        name,
+        per_op.as_secs_f64() * 1_000_000.0,
+        1.0 / per_op.as_secs_f64(),
+    );
+}
+
+fn main() {
+    let legacy = bcrypt::hash(SESSION_ID, LEGACY_COST).unwrap();
+    let digest = session_digest(SESSION_ID);
+
+    let cache = SessionCache::new(10_000, Duration::from_secs(60));
+    for user_id in 0..10_000 {
+        cache.insert(&UserSession{user_id, session_id: format!("{:030}", user_id)}, ValidSession{admin: false, time_zone: time_zones::DEFAULT});
+    }
+    let user_session = UserSession{user_id: 42, session_id: format!("{:030}", 42)};
+
+    bench("bcrypt verify (before)", 50, || verify_session_digest(black_box(SESSION_ID), &legacy));
+    bench("blake2b verify (cache miss)", 100_000, || verify_session_digest(black_box(SESSION_ID), &digest));
+    bench("cache lookup (cache hit)", 1_000_000, || cache.get(black_box(&user_session)).is_some());
+}
@@ -0,0 +1,52 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// With the `embed-assets` feature, lists every file under src/public for
+// `include_bytes!`, so the binary carries its own assets (see src/assets).
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    if env::var_os("CARGO_FEATURE_EMBED_ASSETS").is_none() {
+        return;
+    }
+
+    let public = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("src/public");
+    println!("cargo:rerun-if-changed={}", public.display());
+
+    let mut files = Vec::new();
+    collect(&public, "", &mut files).expect("failed to read src/public");
+    files.sort();
+
+    let entries: String = files
+        .iter()
+        .map(|(path, file)| {
+            println!("cargo:rerun-if-changed={}", file.display());
+            format!("    ({:?}, include_bytes!({:?})),\n", path, file)
+        })
+        .collect();
+
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("embedded_assets.rs");
+    fs::write(out, format!("pub static EMBEDDED: &[(&str, &[u8])] = &[\n{}];\n", entries))
+        .expect("failed to write embedded_assets.rs");
+}
+
+fn collect(dir: &Path, prefix: &str, files: &mut Vec<(String, PathBuf)>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+
+        let path = format!("{}{}", prefix, name);
+        if entry.file_type()?.is_dir() {
+            println!("cargo:rerun-if-changed={}", entry.path().display());
+            collect(&entry.path(), &format!("{}/", path), files)?;
+        } else {
+            files.push((path, entry.path()));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,2 @@
+DROP INDEX index_microposts_on_user_id_and_created_at;
+DROP TABLE microposts
@@ -0,0 +1,8 @@
+CREATE TABLE microposts (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  content VARCHAR NOT NULL,
+  created_at TIMESTAMP DEFAULT (DATETIME('now','localtime')) NOT NULL,
+  updated_at TIMESTAMP DEFAULT (DATETIME('now','localtime')) NOT NULL
+);
+CREATE INDEX index_microposts_on_user_id_and_created_at ON microposts (user_id, created_at)
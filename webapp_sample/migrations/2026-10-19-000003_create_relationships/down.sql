@@ -0,0 +1,2 @@
+DROP INDEX index_relationships_on_followed_id;
+DROP TABLE relationships
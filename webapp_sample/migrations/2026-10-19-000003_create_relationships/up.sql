@@ -0,0 +1,9 @@
+CREATE TABLE relationships (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  follower_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  followed_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  created_at TIMESTAMP DEFAULT (DATETIME('now','localtime')) NOT NULL,
+  updated_at TIMESTAMP DEFAULT (DATETIME('now','localtime')) NOT NULL,
+  UNIQUE (follower_id, followed_id)
+);
+CREATE INDEX index_relationships_on_followed_id ON relationships (followed_id)
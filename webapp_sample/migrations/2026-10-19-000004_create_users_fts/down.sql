@@ -0,0 +1,4 @@
+DROP TRIGGER users_fts_after_update;
+DROP TRIGGER users_fts_after_delete;
+DROP TRIGGER users_fts_after_insert;
+DROP TABLE users_fts
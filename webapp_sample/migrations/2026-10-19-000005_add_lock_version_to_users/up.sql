@@ -0,0 +1 @@
+ALTER TABLE users ADD COLUMN lock_version INTEGER NOT NULL DEFAULT 0
\ No newline at end of file
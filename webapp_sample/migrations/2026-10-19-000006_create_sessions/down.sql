@@ -0,0 +1,3 @@
+DROP INDEX index_sessions_on_last_seen_at;
+DROP INDEX index_sessions_on_user_id;
+DROP TABLE sessions
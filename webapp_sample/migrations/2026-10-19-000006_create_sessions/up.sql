@@ -0,0 +1,9 @@
+CREATE TABLE sessions (
+  id VARCHAR NOT NULL PRIMARY KEY,
+  user_id INTEGER REFERENCES users (id) ON DELETE CASCADE,
+  data TEXT NOT NULL,
+  created_at TIMESTAMP DEFAULT (DATETIME('now','localtime')) NOT NULL,
+  last_seen_at TIMESTAMP DEFAULT (DATETIME('now','localtime')) NOT NULL
+);
+CREATE INDEX index_sessions_on_user_id ON sessions (user_id);
+CREATE INDEX index_sessions_on_last_seen_at ON sessions (last_seen_at)
@@ -0,0 +1,4 @@
+-- DROP COLUMN needs SQLite 3.35 or later; `webapp_admin db check` reports the version.
+ALTER TABLE users DROP COLUMN deletion_scheduled_at;
+DROP INDEX index_data_exports_on_user_id;
+DROP TABLE data_exports
@@ -0,0 +1,10 @@
+CREATE TABLE data_exports (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  status VARCHAR NOT NULL DEFAULT 'pending',
+  storage_key VARCHAR,
+  created_at TIMESTAMP DEFAULT (DATETIME('now','localtime')) NOT NULL,
+  completed_at TIMESTAMP
+);
+CREATE INDEX index_data_exports_on_user_id ON data_exports (user_id);
+ALTER TABLE users ADD COLUMN deletion_scheduled_at TIMESTAMP
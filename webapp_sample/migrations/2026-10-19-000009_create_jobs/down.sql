@@ -0,0 +1,2 @@
+DROP INDEX index_jobs_on_status_and_run_at;
+DROP TABLE jobs
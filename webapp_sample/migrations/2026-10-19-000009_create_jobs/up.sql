@@ -0,0 +1,14 @@
+CREATE TABLE jobs (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  kind VARCHAR NOT NULL,
+  payload TEXT NOT NULL,
+  status VARCHAR NOT NULL DEFAULT 'queued',
+  attempts INTEGER NOT NULL DEFAULT 0,
+  max_attempts INTEGER NOT NULL,
+  run_at TIMESTAMP NOT NULL,
+  locked_at TIMESTAMP,
+  last_error TEXT,
+  created_at TIMESTAMP DEFAULT (DATETIME('now','localtime')) NOT NULL,
+  updated_at TIMESTAMP DEFAULT (DATETIME('now','localtime')) NOT NULL
+);
+CREATE INDEX index_jobs_on_status_and_run_at ON jobs (status, run_at)
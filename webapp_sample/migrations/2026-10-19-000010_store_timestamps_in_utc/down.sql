@@ -0,0 +1,23 @@
+-- Back to the server's local time; see up.sql.
+
+UPDATE users SET
+  created_at = DATETIME(created_at, 'localtime') || SUBSTR(created_at, 20),
+  updated_at = DATETIME(updated_at, 'localtime') || SUBSTR(updated_at, 20),
+  deletion_scheduled_at = DATETIME(deletion_scheduled_at, 'localtime') || SUBSTR(deletion_scheduled_at, 20);
+UPDATE microposts SET
+  created_at = DATETIME(created_at, 'localtime') || SUBSTR(created_at, 20),
+  updated_at = DATETIME(updated_at, 'localtime') || SUBSTR(updated_at, 20);
+UPDATE relationships SET
+  created_at = DATETIME(created_at, 'localtime') || SUBSTR(created_at, 20),
+  updated_at = DATETIME(updated_at, 'localtime') || SUBSTR(updated_at, 20);
+UPDATE sessions SET
+  created_at = DATETIME(created_at, 'localtime') || SUBSTR(created_at, 20),
+  last_seen_at = DATETIME(last_seen_at, 'localtime') || SUBSTR(last_seen_at, 20);
+UPDATE data_exports SET
+  created_at = DATETIME(created_at, 'localtime') || SUBSTR(created_at, 20),
+  completed_at = DATETIME(completed_at, 'localtime') || SUBSTR(completed_at, 20);
+UPDATE jobs SET
+  run_at = DATETIME(run_at, 'localtime') || SUBSTR(run_at, 20),
+  locked_at = DATETIME(locked_at, 'localtime') || SUBSTR(locked_at, 20),
+  created_at = DATETIME(created_at, 'localtime') || SUBSTR(created_at, 20),
+  updated_at = DATETIME(updated_at, 'localtime') || SUBSTR(updated_at, 20);
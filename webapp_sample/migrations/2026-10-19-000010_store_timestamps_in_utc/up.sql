@@ -0,0 +1,28 @@
+-- Timestamps were written in the server's local time. They are converted
+-- with that time zone, so run this on the server (or with the same TZ) that
+-- wrote them. The rows are updated in place: rebuilding a table to change its
+-- column defaults would cascade deletes into its children when foreign keys
+-- are on, and every insert sets its timestamps anyway, so the old
+-- DATETIME('now','localtime') defaults are never used.
+
+UPDATE users SET
+  created_at = DATETIME(created_at, 'utc') || SUBSTR(created_at, 20),
+  updated_at = DATETIME(updated_at, 'utc') || SUBSTR(updated_at, 20),
+  deletion_scheduled_at = DATETIME(deletion_scheduled_at, 'utc') || SUBSTR(deletion_scheduled_at, 20);
+UPDATE microposts SET
+  created_at = DATETIME(created_at, 'utc') || SUBSTR(created_at, 20),
+  updated_at = DATETIME(updated_at, 'utc') || SUBSTR(updated_at, 20);
+UPDATE relationships SET
+  created_at = DATETIME(created_at, 'utc') || SUBSTR(created_at, 20),
+  updated_at = DATETIME(updated_at, 'utc') || SUBSTR(updated_at, 20);
+UPDATE sessions SET
+  created_at = DATETIME(created_at, 'utc') || SUBSTR(created_at, 20),
+  last_seen_at = DATETIME(last_seen_at, 'utc') || SUBSTR(last_seen_at, 20);
+UPDATE data_exports SET
+  created_at = DATETIME(created_at, 'utc') || SUBSTR(created_at, 20),
+  completed_at = DATETIME(completed_at, 'utc') || SUBSTR(completed_at, 20);
+UPDATE jobs SET
+  run_at = DATETIME(run_at, 'utc') || SUBSTR(run_at, 20),
+  locked_at = DATETIME(locked_at, 'utc') || SUBSTR(locked_at, 20),
+  created_at = DATETIME(created_at, 'utc') || SUBSTR(created_at, 20),
+  updated_at = DATETIME(updated_at, 'utc') || SUBSTR(updated_at, 20);
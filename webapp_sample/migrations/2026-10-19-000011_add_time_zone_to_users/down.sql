@@ -0,0 +1,2 @@
+-- DROP COLUMN needs SQLite 3.35 or later; `webapp_admin db check` reports the version.
+ALTER TABLE users DROP COLUMN time_zone
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE users ADD COLUMN time_zone VARCHAR NOT NULL DEFAULT 'UTC'
\ No newline at end of file
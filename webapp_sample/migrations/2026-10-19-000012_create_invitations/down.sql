@@ -0,0 +1,2 @@
+DROP INDEX index_invitations_on_email;
+DROP TABLE invitations
\ No newline at end of file
@@ -0,0 +1,14 @@
+CREATE TABLE invitations (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  email VARCHAR NOT NULL,
+  role VARCHAR NOT NULL DEFAULT 'member',
+  token_digest VARCHAR NOT NULL UNIQUE,
+  invited_by INTEGER REFERENCES users (id) ON DELETE SET NULL,
+  user_id INTEGER REFERENCES users (id) ON DELETE SET NULL,
+  expires_at TIMESTAMP NOT NULL,
+  accepted_at TIMESTAMP,
+  revoked_at TIMESTAMP,
+  created_at TIMESTAMP DEFAULT (DATETIME('now')) NOT NULL,
+  updated_at TIMESTAMP DEFAULT (DATETIME('now')) NOT NULL
+);
+CREATE INDEX index_invitations_on_email ON invitations (email)
@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use uuid::Uuid;
+
+use actix::prelude::*;
+
+use db::{DbExecutor, users_message};
+
+// Every `interval`, purges accounts whose deletion grace period has ended.
+// Their stored avatars and data exports are removed by jobs queued in the same transaction.
+pub struct AccountPurger {
+    pub db: Addr<DbExecutor>,
+    pub interval: Duration,
+}
+
+impl Actor for AccountPurger {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(self.interval, |act, ctx| act.purge(ctx));
+    }
+}
+
+impl AccountPurger {
+    fn purge(&self, ctx: &mut Context<Self>) {
+        let request_id = Uuid::new_v4().to_string();
+
+        self.db
+            .send(users_message::PurgeDeletedUsers{
+                request_id: request_id.clone(),
+                now: Utc::now().naive_utc(),
+            })
+            .into_actor(self)
+            .map(move |res, _, _| {
+                if let Ok(users) = res {
+                    if !users.is_empty() {
+                        info!("{}", json!({
+                            "event":      "accounts_purged",
+                            "request_id": request_id,
+                            "user_ids":   users.iter().map(|user| user.id).collect::<Vec<_>>(),
+                        }));
+                    }
+                }
+            })
+            .map_err(|_, _, _| ())
+            .spawn(ctx);
+    }
+}
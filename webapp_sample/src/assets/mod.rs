@@ -0,0 +1,412 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path};
+use std::sync::{Arc, Mutex};
+
+use blake2::{Blake2b512, Digest};
+use brotli2::write::BrotliEncoder;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use handlebars::{Context, Handlebars, Helper, HelperDef, HelperResult, Output, RenderContext, RenderError};
+
+use actix_web::{fs as actix_fs, HttpRequest, HttpResponse};
+use actix_web::dev::{Handler};
+use actix_web::http::{header, ContentEncoding, Method};
+
+use middleware::{STATIC_PREFIX};
+
+// Hashed URLs change whenever the content does, so they can be cached for a year.
+const CACHE_FOREVER: &str = "public, max-age=31536000, immutable";
+// Plain URLs may point at different content after a deploy.
+const REVALIDATE: &str = "no-cache";
+
+const DIGEST_LEN: usize = 16;
+// Compressing happens at start; 11 saves under a tenth more on the Swagger UI
+// bundle but takes seconds.
+const BROTLI_LEVEL: u32 = 9;
+
+// Worth compressing; images and archives are compressed already.
+const COMPRESSIBLE: [&str; 7] = ["css", "js", "json", "map", "svg", "txt", "html"];
+
+// What vendor_assets.sh downloads for the layout and the API docs.
+pub const VENDORED: [&str; 6] = [
+    "css/bootstrap-4.1.3.min.css",
+    "js/jquery-3.3.1.slim.min.js",
+    "js/popper-1.14.3.min.js",
+    "js/bootstrap-4.1.3.min.js",
+    "swagger-ui/swagger-ui-bundle.js",
+    "swagger-ui/swagger-ui.css",
+];
+
+#[cfg(feature = "embed-assets")]
+mod embedded {
+    // `EMBEDDED: &[(&str, &[u8])]`, written by build.rs from src/public.
+    include!(concat!(env!("OUT_DIR"), "/embedded_assets.rs"));
+}
+
+struct Asset {
+    content_type: String,
+    digest: String,
+    identity: Vec<u8>,
+    gzip: Option<Vec<u8>>,
+    brotli: Option<Vec<u8>>,
+}
+
+impl Asset {
+    fn new(path: &str, data: Vec<u8>) -> io::Result<Self> {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("");
+        let digest = hex(&Blake2b512::digest(&data)[..DIGEST_LEN / 2]);
+
+        let (gzip, brotli) = if COMPRESSIBLE.contains(&extension) {
+            (smaller(gzip(&data)?, &data), smaller(brotli(&data)?, &data))
+        } else {
+            (None, None)
+        };
+
+        Ok(Asset {
+            content_type: actix_fs::file_extension_to_mime(extension).to_string(),
+            digest,
+            identity: data,
+            gzip,
+            brotli,
+        })
+    }
+
+    // The smallest variant the client takes, with its `Content-Encoding`.
+    fn encoded(&self, accept_encoding: &str) -> (Option<&'static str>, &[u8]) {
+        match (&self.brotli, &self.gzip) {
+            (Some(body), _) if accepts(accept_encoding, "br")   => (Some("br"), body),
+            (_, Some(body)) if accepts(accept_encoding, "gzip") => (Some("gzip"), body),
+            _                                                   => (None, &self.identity),
+        }
+    }
+}
+
+// The files under /public, read once at start together with their digests
+// and compressed variants. Templates link them through the `asset` helper,
+// which puts the digest into the file name: `css/layout.css` becomes
+// `/public/css/layout.<digest>.css`.
+pub struct Assets {
+    files: HashMap<String, Asset>,
+    // Hashed path to plain path.
+    hashed: HashMap<String, String>,
+    // Paths the `asset` helper asked for and did not find, logged once each.
+    missing: Mutex<HashSet<String>>,
+}
+
+impl Assets {
+    // Embedded in the binary when built with `--features embed-assets`,
+    // read from ASSETS_DIR (./src/public, like the views, by default) otherwise.
+    // Vendored files that are not there are logged.
+    pub fn from_env() -> io::Result<Self> {
+        let assets = Self::read_from_env()?;
+
+        for path in assets.missing_vendored() {
+            warn!("{}", json!({
+                "event": "asset_not_vendored",
+                "path":  path,
+                "hint":  "run ./vendor_assets.sh and commit the files",
+            }));
+        }
+        Ok(assets)
+    }
+
+    fn read_from_env() -> io::Result<Self> {
+        #[cfg(feature = "embed-assets")]
+        {
+            if env::var("ASSETS_DIR").is_err() {
+                return Self::from_files(embedded::EMBEDDED.iter().map(|&(path, data)| (path.to_string(), data.to_vec())));
+            }
+        }
+
+        let dir = env::var("ASSETS_DIR")
+            .unwrap_or_else(|_| "./src/public".to_string());
+        Self::load(&dir)
+            .map_err(|e| io::Error::new(e.kind(), format!("cannot read assets from {}: {}", dir, e)))
+    }
+
+    pub fn load<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let mut files = Vec::new();
+        read_dir(dir.as_ref(), "", &mut files)?;
+        Self::from_files(files)
+    }
+
+    pub fn from_files<I: IntoIterator<Item = (String, Vec<u8>)>>(files: I) -> io::Result<Self> {
+        let mut assets = Assets {
+            files: HashMap::new(),
+            hashed: HashMap::new(),
+            missing: Mutex::new(HashSet::new()),
+        };
+
+        for (path, data) in files {
+            let asset = Asset::new(&path, data)?;
+            assets.hashed.insert(hashed_path(&path, &asset.digest), path.clone());
+            assets.files.insert(path, asset);
+        }
+        Ok(assets)
+    }
+
+    // The cache-busting URL of `path`, relative to /public.
+    pub fn url(&self, path: &str) -> Option<String> {
+        self.files
+            .get(path)
+            .map(|asset| format!("{}{}", STATIC_PREFIX, hashed_path(path, &asset.digest)))
+    }
+
+    pub fn missing_vendored(&self) -> Vec<&'static str> {
+        VENDORED
+            .iter()
+            .cloned()
+            .filter(|path| !self.files.contains_key(*path))
+            .collect()
+    }
+
+    // True the first time `path` is reported.
+    fn report_missing(&self, path: &str) -> bool {
+        self.missing.lock().unwrap().insert(path.to_string())
+    }
+
+    // Hashed paths are looked up first, so a file whose name merely looks
+    // hashed is still found under its own name.
+    fn find(&self, path: &str) -> Option<(&Asset, bool)> {
+        match self.hashed.get(path) {
+            Some(plain) => self.files.get(plain).map(|asset| (asset, true)),
+            None        => self.files.get(path).map(|asset| (asset, false)),
+        }
+    }
+}
+
+fn read_dir(dir: &Path, prefix: &str, files: &mut Vec<(String, Vec<u8>)>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+
+        let path = format!("{}{}", prefix, name);
+        if entry.file_type()?.is_dir() {
+            read_dir(&entry.path(), &format!("{}/", path), files)?;
+        } else {
+            files.push((path, fs::read(entry.path())?));
+        }
+    }
+    Ok(())
+}
+
+// `css/layout.css` with digest `abc` becomes `css/layout.abc.css`.
+fn hashed_path(path: &str, digest: &str) -> String {
+    let name_start = path.rfind('/').map_or(0, |i| i + 1);
+    match path[name_start..].rfind('.') {
+        Some(i) if i > 0 => format!("{}.{}{}", &path[..name_start + i], digest, &path[name_start + i..]),
+        _                => format!("{}.{}", path, digest),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn gzip(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+fn brotli(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = BrotliEncoder::new(Vec::new(), BROTLI_LEVEL);
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+fn smaller(compressed: Vec<u8>, original: &[u8]) -> Option<Vec<u8>> {
+    if compressed.len() < original.len() {
+        Some(compressed)
+    } else {
+        None
+    }
+}
+
+fn accepts(accept_encoding: &str, coding: &str) -> bool {
+    accept_encoding
+        .split(',')
+        .any(|range| {
+            let mut params = range.split(';').map(str::trim);
+            let name = params.next().unwrap_or("");
+            let quality = params
+                .filter_map(|param| param.strip_prefix("q="))
+                .filter_map(|q| q.parse::<f32>().ok())
+                .next()
+                .unwrap_or(1.0);
+
+            (name.eq_ignore_ascii_case(coding) || name == "*") && quality > 0.0
+        })
+}
+
+// Serves `Assets` under /public. Only known files are answered, so there
+// is no directory listing.
+pub struct AssetFiles(pub Arc<Assets>);
+
+impl<S> Handler<S> for AssetFiles {
+    type Result = HttpResponse;
+
+    fn handle(&self, req: &HttpRequest<S>) -> HttpResponse {
+        if *req.method() != Method::GET && *req.method() != Method::HEAD {
+            return HttpResponse::MethodNotAllowed().finish();
+        }
+
+        let tail: String = req.match_info().query("tail").unwrap_or_default();
+        let (asset, hashed) = match self.0.find(tail.trim_start_matches('/')) {
+            Some(found) => found,
+            None        => return HttpResponse::NotFound().finish(),
+        };
+
+        let accept_encoding = req
+            .headers()
+            .get(header::ACCEPT_ENCODING)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("");
+        let (encoding, body) = asset.encoded(accept_encoding);
+
+        // Each encoding is a different representation, so caches must not
+        // answer a request for one with another.
+        let etag = match encoding {
+            Some("br")   => format!("\"{}-br\"", asset.digest),
+            Some("gzip") => format!("\"{}-gz\"", asset.digest),
+            _            => format!("\"{}\"", asset.digest),
+        };
+        let cache_control = if hashed { CACHE_FOREVER } else { REVALIDATE };
+
+        let not_modified = req
+            .headers()
+            .get(header::IF_NONE_MATCH)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*"));
+        if not_modified {
+            return HttpResponse::NotModified()
+                .header(header::ETAG, etag)
+                .header(header::CACHE_CONTROL, cache_control)
+                .header(header::VARY, "Accept-Encoding")
+                .finish();
+        }
+
+        // Already compressed, so actix-web must not encode it again.
+        let mut resp = HttpResponse::Ok();
+        resp.content_type(asset.content_type.as_str())
+            .content_encoding(ContentEncoding::Identity)
+            .header(header::ETAG, etag)
+            .header(header::CACHE_CONTROL, cache_control)
+            .header(header::VARY, "Accept-Encoding");
+        if let Some(encoding) = encoding {
+            resp.header(header::CONTENT_ENCODING, encoding);
+        }
+        resp.body(body.to_vec())
+    }
+}
+
+// `{{asset "css/layout.css"}}`. A file that is not there, e.g. before
+// vendor_assets.sh has run, is logged once and linked by its plain URL,
+// which answers 404 like any missing file.
+pub struct AssetHelper(pub Arc<Assets>);
+
+impl HelperDef for AssetHelper {
+    fn call<'reg: 'rc, 'rc>(&self, h: &Helper<'reg, 'rc>, _: &'reg Handlebars, _: &'rc Context, _: &mut RenderContext<'reg>, out: &mut dyn Output) -> HelperResult {
+        let path = h.param(0)
+            .and_then(|param| param.value().as_str())
+            .ok_or_else(|| RenderError::new("asset: expected a path"))?;
+
+        let url = self.0.url(path).unwrap_or_else(|| {
+            if self.0.report_missing(path) {
+                warn!("{}", json!({
+                    "event": "asset_missing",
+                    "path":  path,
+                }));
+            }
+            format!("{}{}", STATIC_PREFIX, path)
+        });
+
+        out.write(&url)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use actix_web::http::{StatusCode};
+    use actix_web::test::{TestRequest};
+
+    #[test]
+    fn urls_carry_the_content_digest() {
+        let css = "body { color: black; }\n".repeat(20);
+        let assets = Assets::from_files(vec![
+            ("css/layout.css".to_string(), css.clone().into_bytes()),
+            ("LICENSE".to_string(), b"text".to_vec()),
+        ]).unwrap();
+
+        let url = assets.url("css/layout.css").unwrap();
+        let digest = &assets.files["css/layout.css"].digest;
+        assert_eq!(url, format!("/public/css/layout.{}.css", digest));
+        assert_eq!(assets.url("LICENSE").unwrap(), format!("/public/LICENSE.{}", assets.files["LICENSE"].digest));
+        assert_eq!(assets.url("css/missing.css"), None);
+        assert!(assets.report_missing("css/missing.css"));
+        assert!(!assets.report_missing("css/missing.css"));
+        assert_eq!(assets.missing_vendored().len(), VENDORED.len());
+
+        let (asset, hashed) = assets.find(&url["/public/".len()..]).unwrap();
+        assert!(hashed);
+        assert_eq!(asset.identity, css.as_bytes());
+        assert!(asset.brotli.as_ref().unwrap().len() < css.len());
+        assert!(!assets.find("css/layout.css").unwrap().1);
+    }
+
+    #[test]
+    fn encoded_prefers_brotli_then_gzip() {
+        let assets = Assets::from_files(vec![
+            ("js/app.js".to_string(), "console.log(1);\n".repeat(50).into_bytes()),
+            ("img/logo.png".to_string(), vec![0; 100]),
+        ]).unwrap();
+        let js = &assets.files["js/app.js"];
+
+        assert_eq!(js.encoded("gzip, deflate, br").0, Some("br"));
+        assert_eq!(js.encoded("gzip, br;q=0").0, Some("gzip"));
+        assert_eq!(js.encoded("br;q=0, gzip;q=0").0, None);
+        assert_eq!(js.encoded("*").0, Some("br"));
+        assert_eq!(js.encoded("").1, &js.identity[..]);
+        assert_eq!(assets.files["img/logo.png"].encoded("br").0, None);
+    }
+
+    #[test]
+    fn each_encoding_has_its_own_etag() {
+        let files = AssetFiles(Arc::new(Assets::from_files(vec![
+            ("js/app.js".to_string(), "console.log(1);\n".repeat(50).into_bytes()),
+        ]).unwrap()));
+        let get = |accept_encoding: &str, if_none_match: Option<&str>| {
+            let mut req = TestRequest::with_header(header::ACCEPT_ENCODING, accept_encoding).param("tail", "js/app.js");
+            if let Some(etag) = if_none_match {
+                req = req.header(header::IF_NONE_MATCH, etag);
+            }
+            req.run(&files).unwrap()
+        };
+
+        let etags: Vec<String> = ["br", "gzip", "identity"].iter().map(|coding| {
+            let resp = get(coding, None);
+            assert_eq!(resp.headers()[header::VARY], "Accept-Encoding");
+            resp.headers()[header::ETAG].to_str().unwrap().to_string()
+        }).collect();
+        assert!(etags[0].ends_with("-br\""));
+        assert!(etags[1].ends_with("-gz\""));
+        assert_ne!(etags[0], etags[2]);
+
+        let revalidated = get("br", Some(&etags[0]));
+        assert_eq!(revalidated.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(revalidated.headers()[header::VARY], "Accept-Encoding");
+        assert_eq!(get("gzip", Some(&etags[0])).status(), StatusCode::OK);
+    }
+}
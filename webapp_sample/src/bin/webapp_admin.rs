@@ -0,0 +1,356 @@
+// Administration commands that work on the application database directly,
+// for tasks the web UI cannot do, such as creating the first account.
+extern crate dotenv;
+extern crate regex;
+#[macro_use]
+extern crate serde_json;
+extern crate uuid;
+
+extern crate actix;
+extern crate actix_web;
+
+extern crate diesel;
+extern crate r2d2;
+extern crate r2d2_diesel;
+
+extern crate webapp_sample;
+
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::process;
+use std::sync::Arc;
+use std::time::Duration;
+
+use dotenv::dotenv;
+use regex::{Regex};
+use serde_json::{Map, Value};
+use uuid::Uuid;
+
+use actix::prelude::*;
+use actix::SystemRunner;
+use actix_web::{Error};
+
+use diesel::prelude::*;
+use r2d2_diesel::ConnectionManager;
+
+use webapp_sample::logging;
+use webapp_sample::db::{DbExecutor, SqlitePragmas, database_message, sessions_message, users_message};
+use webapp_sample::events::{Broadcaster};
+use webapp_sample::models::{User};
+use webapp_sample::sessions::cache::{SessionCache};
+use webapp_sample::settings::{Settings, SharedSettings, env_or};
+
+const USAGE: &str = "\
+usage: webapp_admin [--json] <command>
+
+commands:
+  user list
+  user create <name> <email>         reads the password from stdin
+  user set-password <id|email>       reads the password from stdin, signs the user out
+  user delete <id|email> --yes
+  user set-admin <id|email> [--revoke]
+  session revoke-all [--user <id|email>]
+  db check                           exits with 1 when a check fails
+
+DATABASE_URL selects the database (.env is read). MIGRATION_DIR (default
+\"migrations\") is compared with the applied migrations by `db check`.";
+
+// Rows printed as an aligned table, or as a JSON array of objects with --json.
+struct Table {
+    columns: Vec<&'static str>,
+    rows: Vec<Vec<Value>>,
+}
+
+impl Table {
+    fn new(columns: Vec<&'static str>) -> Self {
+        Table{columns, rows: Vec::new()}
+    }
+
+    fn user(user: &User) -> Self {
+        let mut table = Table::new(vec!["id", "name", "email", "admin", "created_at"]);
+        table.push_user(user);
+        table
+    }
+
+    fn push_user(&mut self, user: &User) {
+        self.rows.push(vec![
+            json!(user.id),
+            json!(user.name),
+            json!(user.email),
+            json!(user.admin),
+            json!(user.created_at.to_string()),
+        ]);
+    }
+
+    fn print_json(&self) {
+        let objects: Vec<Value> = self.rows
+            .iter()
+            .map(|row| {
+                let object: Map<String, Value> = self.columns
+                    .iter()
+                    .map(|column| column.to_string())
+                    .zip(row.iter().cloned())
+                    .collect();
+                Value::Object(object)
+            })
+            .collect();
+
+        println!("{}", serde_json::to_string_pretty(&objects).unwrap());
+    }
+
+    fn print_table(&self) {
+        let cells: Vec<Vec<String>> = self.rows
+            .iter()
+            .map(|row| row.iter().map(|value| match value {
+                Value::String(s) => s.clone(),
+                other            => other.to_string(),
+            }).collect())
+            .collect();
+
+        let widths: Vec<usize> = self.columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| {
+                cells.iter().map(|row| row[i].chars().count()).fold(column.len(), usize::max)
+            })
+            .collect();
+
+        let line = |values: Vec<&str>| {
+            let padded: Vec<String> = values
+                .iter()
+                .zip(&widths)
+                .map(|(value, width)| format!("{:<width$}", value, width = width))
+                .collect();
+            println!("{}", padded.join("  ").trim_end());
+        };
+
+        line(self.columns.clone());
+        line(widths.iter().map(|width| "-".repeat(*width)).collect::<Vec<_>>().iter().map(String::as_str).collect());
+        for row in &cells {
+            line(row.iter().map(String::as_str).collect());
+        }
+    }
+}
+
+struct Admin {
+    sys: SystemRunner,
+    db: Addr<DbExecutor>,
+}
+
+impl Admin {
+    fn call<M, T>(&mut self, msg: M) -> Result<T, String>
+    where
+        M: Message<Result = Result<T, Error>> + Send + 'static,
+        T: Send + 'static,
+        DbExecutor: Handler<M>,
+    {
+        match self.sys.block_on(self.db.send(msg)) {
+            Ok(Ok(value)) => Ok(value),
+            Ok(Err(e))    => Err(e.to_string()),
+            Err(e)        => Err(e.to_string()),
+        }
+    }
+
+    // A user given by id, or by email when the argument is not a number.
+    fn find_user(&mut self, key: &str) -> Result<User, String> {
+        let result = match key.parse::<i32>() {
+            Ok(id) => self.call(users_message::ReadUser{request_id: request_id(), id}),
+            Err(_) => self.call(users_message::ReadUserByEmail{request_id: request_id(), email: key.to_string()}),
+        };
+
+        result.map_err(|e| if e == "NotFound" { format!("no such user: {}", key) } else { e })
+    }
+
+    fn run(&mut self, args: &[String]) -> Result<(Table, bool), String> {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+        match args.as_slice() {
+            ["user", "list"] => {
+                let users = self.call(users_message::ReadUsers{request_id: request_id()})?;
+                let mut table = Table::new(vec!["id", "name", "email", "admin", "created_at"]);
+                for user in &users {
+                    table.push_user(user);
+                }
+                Ok((table, true))
+            },
+            ["user", "create", name, email] => {
+                validate_email(email)?;
+                if name.trim().is_empty() {
+                    return Err("name must not be empty".to_string());
+                }
+                let password = read_password()?;
+
+                let user = self.call(users_message::CreateUser{
+                    request_id: request_id(),
+                    name: name.to_string(),
+                    email: email.to_string(),
+                    password,
+                })?;
+                Ok((Table::user(&user), true))
+            },
+            ["user", "set-password", key] => {
+                let user = self.find_user(key)?;
+                let password = read_password()?;
+
+                self.call(users_message::SetUserPassword{request_id: request_id(), id: user.id, password})?;
+                Ok((Table::user(&user), true))
+            },
+            ["user", "delete", key, "--yes"] => {
+                let user = self.find_user(key)?;
+
+                self.call(users_message::DeleteUser{request_id: request_id(), id: user.id})?;
+                Ok((Table::user(&user), true))
+            },
+            ["user", "set-admin", key] | ["user", "set-admin", key, "--revoke"] => {
+                let user = self.find_user(key)?;
+                let admin = args.len() == 3;
+
+                let user = self.call(users_message::SetUserAdmin{request_id: request_id(), id: user.id, admin})?;
+                Ok((Table::user(&user), true))
+            },
+            ["user", "delete", _] => {
+                Err("deleting a user removes their microposts and relationships; add --yes to confirm".to_string())
+            },
+            ["session", "revoke-all"] => {
+                let revoked = self.call(sessions_message::RevokeSessions{request_id: request_id(), user_id: None})?;
+                let mut table = Table::new(vec!["user", "revoked_sessions"]);
+                table.rows.push(vec![json!("all"), json!(revoked)]);
+                Ok((table, true))
+            },
+            ["session", "revoke-all", "--user", key] => {
+                let user = self.find_user(key)?;
+                let revoked = self.call(sessions_message::RevokeSessions{request_id: request_id(), user_id: Some(user.id)})?;
+                let mut table = Table::new(vec!["user", "revoked_sessions"]);
+                table.rows.push(vec![json!(user.email), json!(revoked)]);
+                Ok((table, true))
+            },
+            ["db", "check"] => {
+                let checks = self.call(database_message::CheckDatabase{
+                    request_id: request_id(),
+                    expected_migrations: migration_versions(&env_or("MIGRATION_DIR", "migrations".to_string()))?,
+                })?;
+                let ok = checks.iter().all(|check| check.ok);
+                let mut table = Table::new(vec!["check", "ok", "detail"]);
+                for check in checks {
+                    table.rows.push(vec![json!(check.name), json!(check.ok), json!(check.detail)]);
+                }
+                Ok((table, ok))
+            },
+            _ => Err(USAGE.to_string()),
+        }
+    }
+}
+
+fn request_id() -> String {
+    format!("admin-{}", Uuid::new_v4())
+}
+
+fn validate_email(email: &str) -> Result<(), String> {
+    let re_email = Regex::new(r"^[a-zA-Z0-9.!#$%&'*+/=?^_`{|}~-]+@[a-zA-Z0-9-]+(?:\.[a-zA-Z0-9-]+)*$").unwrap();
+    if re_email.is_match(email) {
+        Ok(())
+    } else {
+        Err(format!("not an email address: {}", email))
+    }
+}
+
+// The first line of stdin, so passwords stay out of the shell history and process list.
+fn read_password() -> Result<String, String> {
+    eprint!("password: ");
+    io::stderr().flush().ok();
+
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line).map_err(|e| e.to_string())?;
+    let password = line.trim_end_matches(|c| c == '\n' || c == '\r').to_string();
+
+    let re_password = Regex::new(r"^[a-zA-Z\d]{8,30}$").unwrap();
+    if re_password.is_match(&password) {
+        Ok(password)
+    } else {
+        Err("password must be 8 to 30 letters or digits".to_string())
+    }
+}
+
+// Diesel records a migration as its directory name up to the first `_`, without dashes.
+// A directory that cannot be read or holds no migrations is an error, so
+// `db check` does not pass for want of anything to compare.
+fn migration_versions(dir: &str) -> Result<Vec<String>, String> {
+    let mut versions: Vec<String> = fs::read_dir(dir)
+        .map_err(|e| format!("cannot read migrations in {}: {}", dir, e))?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter_map(|name| name.split('_').next().map(|version| version.replace('-', "")))
+        .collect();
+    if versions.is_empty() {
+        return Err(format!("no migrations in {}", dir));
+    }
+    versions.sort();
+    Ok(versions)
+}
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let json = args.iter().any(|arg| arg == "--json");
+    args.retain(|arg| arg != "--json");
+
+    if args.is_empty() || args[0] == "help" || args[0] == "--help" {
+        println!("{}", USAGE);
+        return;
+    }
+
+    dotenv().ok();
+    logging::init();
+
+    let database_url = env::var("DATABASE_URL")
+        .expect("DATABASE_URL must be set");
+    let manager = ConnectionManager::<SqliteConnection>::new(database_url);
+    let pool = r2d2::Pool::builder()
+        .max_size(1)
+        .connection_customizer(Box::new(SqlitePragmas::from_env()))
+        .build(manager)
+        .expect("Failed to create pool.");
+    let settings = SharedSettings::new(Settings::from_env());
+
+    let sys = System::new("webapp_admin");
+    // Nothing subscribes to events here; browsers only hear about changes
+    // made through the web app.
+    let events = Broadcaster::default().start();
+    // Likewise the web app's session cache is not reachable from here; its
+    // entries expire after SESSION_CACHE_TTL_SECS.
+    let session_cache = Arc::new(SessionCache::new(0, Duration::from_secs(0)));
+    let db = SyncArbiter::start(1, move || DbExecutor(pool.clone(), settings.clone(), events.clone(), session_cache.clone()));
+    let mut admin = Admin{sys, db};
+
+    match admin.run(&args) {
+        Ok((table, ok)) => {
+            if json {
+                table.print_json();
+            } else {
+                table.print_table();
+            }
+            if !ok {
+                process::exit(1);
+            }
+        },
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(2);
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migration_versions_need_a_readable_directory() {
+        let versions = migration_versions(concat!(env!("CARGO_MANIFEST_DIR"), "/migrations")).unwrap();
+        assert_eq!(versions.first().map(String::as_str), Some("20180923072747"));
+
+        assert!(migration_versions("/nonexistent/migrations").is_err());
+        assert!(migration_versions(concat!(env!("CARGO_MANIFEST_DIR"), "/src/views")).is_err());
+    }
+}
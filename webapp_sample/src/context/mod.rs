@@ -4,27 +4,54 @@ use handlebars::{Handlebars};
 
 use actix::prelude::*;
 
+use assets::{AssetHelper, Assets};
 use db::{DbExecutor}; 
+use events::{Broadcaster};
+use exports::{ExportExecutor};
+use images::{ImageExecutor};
+use middleware::rate_limit::{RateLimitStore, MemoryStore};
+use sessions::cache::{SessionCache};
+use time_zones::{DateHelper};
+use settings::{SharedSettings};
 
 #[derive(Clone)]
 pub struct Context {
     pub templates: Arc<Handlebars>,
+    pub assets: Arc<Assets>,
     pub db:    Addr<DbExecutor>,
+    pub images: Addr<ImageExecutor>,
+    pub exports: Addr<ExportExecutor>,
+    pub events: Addr<Broadcaster>,
+    pub rate_limit_store: Arc<dyn RateLimitStore>,
+    pub session_cache: Arc<SessionCache>,
+    pub settings: SharedSettings,
 }
 
 impl Context {
-    pub fn new(db: Addr<DbExecutor>) -> Self {
+    pub fn new(db: Addr<DbExecutor>, images: Addr<ImageExecutor>, exports: Addr<ExportExecutor>, events: Addr<Broadcaster>, session_cache: Arc<SessionCache>, assets: Arc<Assets>, settings: SharedSettings) -> Self {
         let mut templates = Handlebars::new();
+        templates.register_helper("asset", Box::new(AssetHelper(assets.clone())));
+        templates.register_helper("date", Box::new(DateHelper));
         
         for (name, path) in vec![
             ("layout",          "./src/views/layout.hbs"),
             ("index",           "./src/views/index.hbs"),
+            ("account_show",    "./src/views/account_show.hbs"),
+            ("jobs_index",      "./src/views/jobs_index.hbs"),
+            ("invitations_index", "./src/views/invitations_index.hbs"),
+            ("invitations_show", "./src/views/invitations_show.hbs"),
             ("users_index",     "./src/views/users_index.hbs"),
             ("users_new",       "./src/views/users_new.hbs"),
             ("users_show",      "./src/views/users_show.hbs"),
             ("users_edit",      "./src/views/users_edit.hbs"),
+            ("users_follow",    "./src/views/users_follow.hbs"),
+            ("users_password",  "./src/views/users_password.hbs"),
+            ("users_search",    "./src/views/users_search.hbs"),
             ("sessions_new",    "./src/views/sessions_new.hbs"),
             ("sessions_delete", "./src/views/sessions_delete.hbs"),
+            ("microposts_list", "./src/views/microposts_list.hbs"),
+            ("pagination",      "./src/views/pagination.hbs"),
+            ("api_docs",        "./src/views/api_docs.hbs"),
         ] {
             templates
                 .register_template_file(name, path)
@@ -33,7 +60,14 @@ impl Context {
 
         Self {
             templates: Arc::new(templates),
+            assets,
             db:        db,
+            images,
+            exports,
+            events,
+            rate_limit_store: Arc::new(MemoryStore::new()),
+            session_cache,
+            settings,
         }
     }
 }
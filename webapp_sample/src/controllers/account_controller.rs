@@ -0,0 +1,203 @@
+use chrono::Utc;
+use handlebars::{to_json};
+use serde_json::value::{Map};
+
+use actix_web::{State, Path, Form, HttpResponse, FutureResponse, AsyncResponder};
+use actix_web::http::{Method};
+use actix_web::middleware::session::{Session};
+use futures::Future;
+
+use db::{data_exports_message, users_message};
+use db::users_message::{DeletionRequest};
+use exports::archives_message;
+use context::{Context};
+use controllers;
+use helpers::{sessions_helper};
+use middleware::format::{Format};
+use middleware::request_id::{RequestId};
+use time_zones::{ViewerTimeZone};
+
+#[derive(Deserialize)]
+pub struct AccountExportPath{
+    pub id: i32,
+}
+
+#[derive(Deserialize)]
+pub struct AccountDeletionParam {
+    #[serde(default)]
+    method:   String,
+    #[serde(default)]
+    password: String,
+}
+
+fn signed_in_user_id(session: &Session) -> Option<i32> {
+    match sessions_helper::user_session(session) {
+        Ok(Some(user_session)) => Some(user_session.user_id),
+        _                      => None,
+    }
+}
+
+pub fn handle_show((state, request_id, format, zone, session): (State<Context>, RequestId, Format, ViewerTimeZone, Session)) -> FutureResponse<HttpResponse> {
+    use futures::future::ok;
+
+    let user_id = match signed_in_user_id(&session) {
+        Some(user_id) => user_id,
+        None          => return Box::new(ok(controllers::signin_required(format))),
+    };
+    let templates = state.templates.clone();
+    let grace_days = state.settings.current().deletion_grace.num_days();
+    let db = state.db.clone();
+
+    state
+        .db
+        .send(users_message::ReadUser{request_id: request_id.0.clone(), id: user_id})
+        .from_err()
+        .and_then(|res| res)
+        .and_then(move |user| {
+            db
+                .send(data_exports_message::ReadDataExports{request_id: request_id.0, user_id})
+                .from_err()
+                .and_then(|res| res)
+                .map(move |exports| (user, exports))
+        })
+        .and_then(move |(user, exports)| {
+            let mut data = Map::new();
+            data.insert("user".to_string(), to_json(&user));
+            data.insert("exports".to_string(), to_json(&exports));
+            data.insert("grace_days".to_string(), to_json(grace_days));
+            data.insert("time_zone".to_string(), to_json(zone.name()));
+            data.insert("flash_message".to_string(), to_json(sessions_helper::get_flash_message(&session)));
+            Ok(controllers::render(format, templates, "account_show", Some(data)))
+        })
+        .responder()
+}
+
+pub fn handle_export_create((state, request_id, format, session): (State<Context>, RequestId, Format, Session)) -> FutureResponse<HttpResponse> {
+    use futures::future::ok;
+
+    let user_id = match signed_in_user_id(&session) {
+        Some(user_id) => user_id,
+        None          => return Box::new(ok(controllers::signin_required(format))),
+    };
+
+    state
+        .db
+        .send(data_exports_message::CreateDataExport{request_id: request_id.0, user_id})
+        .from_err()
+        .and_then(|res| res)
+        .and_then(move |export| {
+            Ok(controllers::created(format, "/account", &format!("/account/exports/{}", export.id), to_json(&export)))
+        })
+        .responder()
+}
+
+pub fn handle_export_show((state, request_id, format, session, path): (State<Context>, RequestId, Format, Session, Path<AccountExportPath>)) -> FutureResponse<HttpResponse> {
+    use futures::future::{ok, Either};
+
+    let user_id = match signed_in_user_id(&session) {
+        Some(user_id) => user_id,
+        None          => return Box::new(ok(controllers::signin_required(format))),
+    };
+    let RequestId(request_id) = request_id;
+    let exports = state.exports.clone();
+
+    state
+        .db
+        .send(data_exports_message::ReadDataExport{request_id: request_id.clone(), id: path.id, user_id})
+        .from_err()
+        .and_then(|res| res)
+        .and_then(move |export| {
+            let id = export.id;
+
+            match export.storage_key {
+                Some(key) => Either::A(
+                    exports
+                        .send(archives_message::ReadArchive{request_id, key})
+                        .from_err()
+                        .and_then(|res| res)
+                        .map(move |zip| {
+                            HttpResponse::Ok()
+                                .content_type("application/zip")
+                                .header("Content-Disposition", format!("attachment; filename=\"webapp_sample-export-{}.zip\"", id))
+                                .header("Cache-Control", "no-store")
+                                .body(zip)
+                        })
+                ),
+                None => Either::B(ok(controllers::http_status(404))),
+            }
+        })
+        .responder()
+}
+
+pub fn handle_deletion_post((state, request_id, format, zone, session, params): (State<Context>, RequestId, Format, ViewerTimeZone, Session, Form<AccountDeletionParam>)) -> FutureResponse<HttpResponse> {
+    match Method::from_bytes(params.method.as_bytes()) {
+        Ok(Method::DELETE) => handle_deletion_destroy((state, request_id, format, session, params)),
+        _                  => handle_deletion_create((state, request_id, format, zone, session, params)),
+    }
+}
+
+pub fn handle_deletion_create((state, request_id, format, zone, session, params): (State<Context>, RequestId, Format, ViewerTimeZone, Session, Form<AccountDeletionParam>)) -> FutureResponse<HttpResponse> {
+    use futures::future::ok;
+
+    let user_id = match signed_in_user_id(&session) {
+        Some(user_id) => user_id,
+        None          => return Box::new(ok(controllers::signin_required(format))),
+    };
+
+    if params.password.is_empty() {
+        let error_messages = vec!["パスワードを入力してください".to_string()];
+        return Box::new(ok(controllers::failed(format, &session, "/account", 422, error_messages)));
+    }
+
+    let purge_at = Utc::now().naive_utc() + state.settings.current().deletion_grace;
+
+    state
+        .db
+        .send(users_message::ScheduleUserDeletion{
+            request_id: request_id.0,
+            id: user_id,
+            password: params.into_inner().password,
+            purge_at,
+        })
+        .from_err()
+        .and_then(|res| res)
+        .and_then(move |request| {
+            match request {
+                DeletionRequest::Scheduled     => {
+                    sessions_helper::signout(&session);
+                    sessions_helper::set_flash_message(
+                        &session,
+                        sessions_helper::FlashMessage{
+                            error_messages: vec![format!(
+                                "アカウントの削除を受け付けました。{}までにサインインすると取り消せます。",
+                                zone.format(purge_at),
+                            )],
+                        },
+                    );
+                    Ok(controllers::created(format, "/signin", "/account", json!({"purge_at": purge_at})))
+                },
+                DeletionRequest::WrongPassword => {
+                    let error_messages = vec!["パスワードが間違っています".to_string()];
+                    Ok(controllers::failed(format, &session, "/account", 422, error_messages))
+                },
+            }
+        })
+        .responder()
+}
+
+pub fn handle_deletion_destroy((state, request_id, format, session, _params): (State<Context>, RequestId, Format, Session, Form<AccountDeletionParam>)) -> FutureResponse<HttpResponse> {
+    use futures::future::ok;
+
+    let user_id = match signed_in_user_id(&session) {
+        Some(user_id) => user_id,
+        None          => return Box::new(ok(controllers::signin_required(format))),
+    };
+
+    state
+        .db
+        .send(users_message::CancelUserDeletion{request_id: request_id.0, id: user_id})
+        .from_err()
+        .and_then(|res| res)
+        .and_then(move |_| Ok(controllers::deleted(format, "/account")))
+        .responder()
+}
@@ -0,0 +1,15 @@
+use actix_web::{State, HttpResponse};
+
+use context::{Context};
+use controllers;
+use middleware::format::{Format};
+use openapi;
+
+pub fn handle_openapi(_: State<Context>) -> HttpResponse {
+    HttpResponse::Ok().json(openapi::document())
+}
+
+// Swagger UI on /openapi.json, served from /public/swagger-ui so it works offline.
+pub fn handle_api_docs(state: State<Context>) -> HttpResponse {
+    controllers::render(Format::Html, state.templates.clone(), "api_docs", None)
+}
@@ -0,0 +1,16 @@
+use actix_web::{ws, HttpRequest, HttpResponse, Result};
+use actix_web::middleware::session::{RequestSession};
+
+use context::{Context};
+use controllers;
+use events::socket::{EventSocket};
+use helpers::{sessions_helper};
+
+// Upgrades to a WebSocket carrying live events. `Authenticate` has already
+// checked the session; this only refuses requests that have none.
+pub fn handle_socket(req: HttpRequest<Context>) -> Result<HttpResponse> {
+    match sessions_helper::user_session(&req.session()) {
+        Ok(Some(_)) => ws::start(&req, EventSocket::new(req.state().events.clone())),
+        _           => Ok(controllers::http_status(401)),
+    }
+}
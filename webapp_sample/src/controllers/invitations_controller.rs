@@ -0,0 +1,169 @@
+use std::sync::Arc;
+
+use chrono::Utc;
+use handlebars::{to_json, Handlebars};
+use regex::{Regex};
+use serde_json::value::{Map, Value};
+
+use actix_web::{State, Path, Form, HttpRequest, HttpResponse, FutureResponse, AsyncResponder};
+use actix_web::http::{header, StatusCode};
+use actix_web::middleware::session::{Session};
+use futures::Future;
+
+use db::{invitations_message};
+use db::invitations_message::{InvitationCreate};
+use context::{Context};
+use controllers;
+use helpers::{sessions_helper};
+use invitations::{self, Status};
+use middleware::format::{Format};
+use middleware::request_id::{RequestId};
+use models;
+use time_zones::{ViewerTimeZone};
+
+#[derive(Deserialize)]
+pub struct InvitationsCreateParam {
+    email: String,
+    role:  String,
+}
+
+#[derive(Deserialize)]
+pub struct InvitationsReadPath {
+    pub id: i32,
+}
+
+// Only admins get here; see the access rules in `app()`.
+pub fn handle_index((state, request_id, format, zone, session): (State<Context>, RequestId, Format, ViewerTimeZone, Session)) -> FutureResponse<HttpResponse> {
+    let templates = state.templates.clone();
+
+    state
+        .db
+        .send(invitations_message::ReadInvitations{request_id: request_id.0})
+        .from_err()
+        .and_then(|res| res)
+        .and_then(move |list| {
+            // `eq` only compares numbers, so the status is spelled out as flags too.
+            let now = Utc::now().naive_utc();
+            let invitations: Vec<Value> = list
+                .iter()
+                .map(|invitation| {
+                    let status = Status::of(invitation, now);
+                    let mut value = to_json(invitation);
+                    value["status"] = to_json(status);
+                    value["pending"] = to_json(status == Status::Pending);
+                    value["resendable"] = to_json(status != Status::Used);
+                    value
+                })
+                .collect();
+
+            let mut data = Map::new();
+            data.insert("invitations".to_string(), to_json(&invitations));
+            data.insert("roles".to_string(), to_json(invitations::ROLES));
+            data.insert("time_zone".to_string(), to_json(zone.name()));
+            data.insert("flash_message".to_string(), to_json(sessions_helper::get_flash_message(&session)));
+            Ok(controllers::render(format, templates, "invitations_index", Some(data)))
+        })
+        .responder()
+}
+
+pub fn handle_create((req, state, request_id, format, zone, session, params): (HttpRequest<Context>, State<Context>, RequestId, Format, ViewerTimeZone, Session, Form<InvitationsCreateParam>)) -> FutureResponse<HttpResponse> {
+    use futures::future::ok;
+
+    let InvitationsCreateParam{email, role} = params.into_inner();
+
+    let invited_by = match sessions_helper::user_session(&session) {
+        Ok(Some(user_session)) => user_session.user_id,
+        _                      => return Box::new(ok(controllers::signin_required(format))),
+    };
+
+    let mut error_messages = Vec::new();
+    let re_email = Regex::new(r"^[a-zA-Z0-9.!#$%&'*+/=?^_`{|}~-]+@[a-zA-Z0-9-]+(?:\.[a-zA-Z0-9-]+)*$").unwrap();
+    if !re_email.is_match(&email) {
+        error_messages.push("メールアドレスはxxx@xxxの形式で入力してください".to_string());
+    }
+    if !invitations::ROLES.contains(&role.as_str()) {
+        error_messages.push("権限が正しくありません".to_string());
+    }
+    if !error_messages.is_empty() {
+        return Box::new(ok(controllers::failed(format, &session, "/admin/invitations", 422, error_messages)));
+    }
+
+    let token = invitations::create_token();
+    let templates = state.templates.clone();
+
+    state
+        .db
+        .send(invitations_message::CreateInvitation{
+            request_id: request_id.0,
+            email,
+            role,
+            invited_by,
+            token_digest: invitations::token_digest(&token),
+            expires_at: Utc::now().naive_utc() + state.settings.current().invitation_ttl,
+        })
+        .from_err()
+        .and_then(|res| res)
+        .and_then(move |created| {
+            Ok(match created {
+                InvitationCreate::Created(invitation) => {
+                    let mut resp = link(&req, format, templates, zone, &invitation, &token);
+                    *resp.status_mut() = StatusCode::CREATED;
+                    resp
+                },
+                InvitationCreate::EmailTaken          => {
+                    let error_messages = vec!["このメールアドレスは登録済みです".to_string()];
+                    controllers::failed(format, &session, "/admin/invitations", 422, error_messages)
+                },
+            })
+        })
+        .responder()
+}
+
+pub fn handle_revoke((state, request_id, format, path): (State<Context>, RequestId, Format, Path<InvitationsReadPath>)) -> FutureResponse<HttpResponse> {
+    state
+        .db
+        .send(invitations_message::RevokeInvitation{request_id: request_id.0, id: path.id})
+        .from_err()
+        .and_then(|res| res)
+        .and_then(move |invitation| {
+            Ok(controllers::updated(format, "/admin/invitations", to_json(&invitation)))
+        })
+        .responder()
+}
+
+// There is no mail delivery; the admin passes the new link on.
+pub fn handle_resend((req, state, request_id, format, zone, path): (HttpRequest<Context>, State<Context>, RequestId, Format, ViewerTimeZone, Path<InvitationsReadPath>)) -> FutureResponse<HttpResponse> {
+    let token = invitations::create_token();
+    let templates = state.templates.clone();
+
+    state
+        .db
+        .send(invitations_message::ResendInvitation{
+            request_id: request_id.0,
+            id: path.id,
+            token_digest: invitations::token_digest(&token),
+            expires_at: Utc::now().naive_utc() + state.settings.current().invitation_ttl,
+        })
+        .from_err()
+        .and_then(|res| res)
+        .and_then(move |invitation| {
+            Ok(link(&req, format, templates, zone, &invitation, &token))
+        })
+        .responder()
+}
+
+// The token is not stored, so the page with the link is rendered in place of
+// the usual redirect; it cannot be shown again.
+fn link(req: &HttpRequest<Context>, format: Format, templates: Arc<Handlebars>, zone: ViewerTimeZone, invitation: &models::Invitation, token: &str) -> HttpResponse {
+    let info = req.connection_info();
+    let url = invitations::url(info.scheme(), info.host(), token);
+
+    let mut data = Map::new();
+    data.insert("invitation".to_string(), to_json(invitation));
+    data.insert("url".to_string(), to_json(&url));
+    data.insert("time_zone".to_string(), to_json(zone.name()));
+
+    let mut resp = controllers::render(format, templates, "invitations_show", Some(data));
+    resp.headers_mut().insert(header::CACHE_CONTROL, header::HeaderValue::from_static("no-store"));
+    resp
+}
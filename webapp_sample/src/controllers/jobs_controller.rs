@@ -0,0 +1,69 @@
+use handlebars::{to_json};
+use serde_json::value::{Map, Value};
+
+use actix_web::{State, Path, Query, HttpResponse, FutureResponse, AsyncResponder};
+use actix_web::middleware::session::{Session};
+use futures::Future;
+
+use db::{jobs_message};
+use context::{Context};
+use controllers;
+use helpers::{sessions_helper};
+use middleware::format::{Format};
+use middleware::request_id::{RequestId};
+use time_zones::{ViewerTimeZone};
+
+#[derive(Deserialize)]
+pub struct JobsIndexParam {
+    status: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct JobsReadPath {
+    pub id: i32,
+}
+
+// Only admins get here; see the access rules in `app()`.
+pub fn handle_index((state, request_id, format, zone, session, query): (State<Context>, RequestId, Format, ViewerTimeZone, Session, Query<JobsIndexParam>)) -> FutureResponse<HttpResponse> {
+    let templates = state.templates.clone();
+    let RequestId(request_id) = request_id;
+    let status = query.into_inner().status.filter(|status| jobs_message::STATUSES.contains(&status.as_str()));
+
+    state
+        .db
+        .send(jobs_message::ReadJobs{request_id, status: status.clone()})
+        .from_err()
+        .and_then(|res| res)
+        .and_then(move |list| {
+            // Dead jobs get a retry button.
+            let jobs: Vec<Value> = list.jobs
+                .iter()
+                .map(|job| {
+                    let mut value = to_json(job);
+                    value["dead"] = to_json(job.status == jobs_message::STATUS_DEAD);
+                    value
+                })
+                .collect();
+
+            let mut data = Map::new();
+            data.insert("counts".to_string(), to_json(&list.counts));
+            data.insert("jobs".to_string(), to_json(&jobs));
+            data.insert("status".to_string(), to_json(&status));
+            data.insert("time_zone".to_string(), to_json(zone.name()));
+            data.insert("flash_message".to_string(), to_json(sessions_helper::get_flash_message(&session)));
+            Ok(controllers::render(format, templates, "jobs_index", Some(data)))
+        })
+        .responder()
+}
+
+pub fn handle_retry((state, request_id, format, path): (State<Context>, RequestId, Format, Path<JobsReadPath>)) -> FutureResponse<HttpResponse> {
+    state
+        .db
+        .send(jobs_message::RetryJob{request_id: request_id.0, id: path.id})
+        .from_err()
+        .and_then(|res| res)
+        .and_then(move |job| {
+            Ok(controllers::updated(format, "/admin/jobs", to_json(&job)))
+        })
+        .responder()
+}
@@ -0,0 +1,101 @@
+use handlebars::{to_json};
+
+use actix_web::{State, Path, Form, HttpResponse, FutureResponse, AsyncResponder};
+use actix_web::http::{Method};
+use actix_web::middleware::session::{Session};
+use futures::Future;
+
+use db::{microposts_message};
+use context::{Context};
+use controllers;
+use helpers::{sessions_helper};
+use middleware::format::{Format};
+use middleware::request_id::{RequestId};
+
+pub const MAX_CONTENT_LEN: usize = 140;
+
+#[derive(Deserialize)]
+pub struct MicropostsReadPath{
+    pub id: i32,
+}
+
+#[derive(Deserialize)]
+pub struct MicropostsCreateParam {
+    micropost_content: String,
+}
+
+#[derive(Deserialize)]
+pub struct MicropostsPostParam {
+    method: String,
+}
+
+pub fn handle_create((state, request_id, format, session, params): (State<Context>, RequestId, Format, Session, Form<MicropostsCreateParam>)) -> FutureResponse<HttpResponse> {
+    use futures::future::ok;
+
+    let user_id = match sessions_helper::user_session(&session) {
+        Ok(Some(user_session)) => user_session.user_id,
+        _                      => return Box::new(ok(controllers::signin_required(format))),
+    };
+
+    let content = params.micropost_content.trim().to_string();
+
+    let mut error_messages = Vec::new();
+
+    let content_len = content.chars().count();
+    if content_len == 0 {
+        error_messages.push("投稿内容を入力してください".to_string());
+    }
+    if content_len > MAX_CONTENT_LEN {
+        error_messages.push(format!("投稿内容は{}文字以下で入力してください", MAX_CONTENT_LEN));
+    }
+
+    if !error_messages.is_empty() {
+        return Box::new(ok(controllers::failed(format, &session, "/", 422, error_messages)));
+    }
+
+    state
+        .db
+        .send(microposts_message::CreateMicropost{
+            request_id: request_id.0,
+            user_id,
+            content,
+        })
+        .from_err()
+        .and_then(|res| res)
+        .and_then(move |micropost| {
+            Ok(controllers::created(format, "/", &format!("/microposts/{}", micropost.id), to_json(&micropost)))
+        })
+        .responder()
+}
+
+pub fn handle_post((state, request_id, format, session, path, params): (State<Context>, RequestId, Format, Session, Path<MicropostsReadPath>, Form<MicropostsPostParam>)) -> FutureResponse<HttpResponse> {
+    use futures::future::ok;
+
+    match Method::from_bytes(params.method.as_bytes()) {
+        Ok(Method::DELETE) => handle_destroy((state, request_id, format, session, path)),
+        _                  => Box::new(ok(controllers::http_internal_server_error())),
+    }
+}
+
+pub fn handle_destroy((state, request_id, format, session, path): (State<Context>, RequestId, Format, Session, Path<MicropostsReadPath>)) -> FutureResponse<HttpResponse> {
+    use futures::future::ok;
+
+    let user_id = match sessions_helper::user_session(&session) {
+        Ok(Some(user_session)) => user_session.user_id,
+        _                      => return Box::new(ok(controllers::signin_required(format))),
+    };
+
+    state
+        .db
+        .send(microposts_message::DeleteMicropost{
+            request_id: request_id.0,
+            id: path.id,
+            user_id,
+        })
+        .from_err()
+        .and_then(|res| res)
+        .and_then(move |_| {
+            Ok(controllers::deleted(format, "/"))
+        })
+        .responder()
+}
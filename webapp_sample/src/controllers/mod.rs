@@ -1,6 +1,13 @@
 pub mod root_controller;
 pub mod users_controller;
 pub mod sessions_controller;
+pub mod microposts_controller;
+pub mod relationships_controller;
+pub mod account_controller;
+pub mod events_controller;
+pub mod jobs_controller;
+pub mod invitations_controller;
+pub mod docs_controller;
 
 use std::sync::Arc;
 
@@ -9,19 +16,78 @@ use serde_json::value::{Map, Value};
 
 use actix_web::{HttpResponse};
 use actix_web::http::{StatusCode};
+use actix_web::middleware::session::{Session};
 
-pub fn render(templates: Arc<Handlebars>, name: &str, data: Option<Map<String, Value>>) -> HttpResponse {
+use helpers::{sessions_helper};
+use middleware::format::{Format};
+
+// JSON clients get the data map itself instead of the page rendered from it.
+pub fn render(format: Format, templates: Arc<Handlebars>, name: &str, data: Option<Map<String, Value>>) -> HttpResponse {
     let params = match data {
         Some(d) => d,
         None    => Map::new(),
     };
 
+    if format.is_json() {
+        return HttpResponse::Ok().json(Value::Object(params));
+    }
+
     match templates.render(name, &params) {
         Ok(body) => HttpResponse::Ok().body(body),
         Err(_)   => HttpResponse::InternalServerError().finish(),
     }
 }
 
+// After a successful create, HTML clients are redirected to `redirect_to`;
+// JSON clients get 201 with the new resource and its `location`.
+pub fn created(format: Format, redirect_to: &str, location: &str, body: Value) -> HttpResponse {
+    match format {
+        Format::Json => HttpResponse::Created().header("Location", location).json(body),
+        Format::Html => http_redirect(redirect_to, 303),
+    }
+}
+
+// After a successful update: 200 with the resource for JSON clients.
+pub fn updated(format: Format, redirect_to: &str, body: Value) -> HttpResponse {
+    match format {
+        Format::Json => HttpResponse::Ok().json(body),
+        Format::Html => http_redirect(redirect_to, 303),
+    }
+}
+
+// After a successful delete: 204 for JSON clients.
+pub fn deleted(format: Format, redirect_to: &str) -> HttpResponse {
+    match format {
+        Format::Json => http_status(204),
+        Format::Html => http_redirect(redirect_to, 303),
+    }
+}
+
+// Reports errors the user can fix: HTML clients see them as a flash message
+// on `redirect_to`, JSON clients get `{"errors": [...]}` with `status`.
+pub fn failed(format: Format, session: &Session, redirect_to: &str, status: u16, error_messages: Vec<String>) -> HttpResponse {
+    match format {
+        Format::Json => {
+            let status = StatusCode::from_u16(status)
+                .expect("invalide status given");
+
+            HttpResponse::build(status).json(json!({"errors": error_messages}))
+        },
+        Format::Html => {
+            sessions_helper::set_flash_message(session, sessions_helper::FlashMessage{error_messages});
+            http_redirect(redirect_to, 303)
+        },
+    }
+}
+
+// For handlers reached without a signed-in user.
+pub fn signin_required(format: Format) -> HttpResponse {
+    match format {
+        Format::Json => http_status(401),
+        Format::Html => http_redirect("/signin", 303),
+    }
+}
+
 pub fn http_redirect(path: &str, code: u16) -> HttpResponse {
     let status = StatusCode::from_u16(code)
         .expect("invalide status given");
@@ -0,0 +1,167 @@
+use handlebars::{to_json};
+use serde_json::value::{Map};
+
+use actix_web::{State, Path, Form, Query, HttpResponse, FutureResponse, AsyncResponder};
+use actix_web::http::{Method};
+use actix_web::middleware::session::{Session};
+use futures::Future;
+
+use db::{relationships_message, users_message};
+use context::{Context};
+use controllers;
+use helpers::{sessions_helper};
+use helpers::pagination_helper::{self, PageParam, Pagination};
+use middleware::format::{Format};
+use middleware::request_id::{RequestId};
+
+#[derive(Deserialize)]
+pub struct RelationshipsReadPath{
+    pub followed_id: i32,
+}
+
+#[derive(Deserialize)]
+pub struct RelationshipsUsersPath{
+    pub id: i32,
+}
+
+#[derive(Deserialize)]
+pub struct RelationshipsCreateParam {
+    followed_id: i32,
+}
+
+#[derive(Deserialize)]
+pub struct RelationshipsPostParam {
+    method: String,
+}
+
+pub fn handle_create((state, request_id, format, session, params): (State<Context>, RequestId, Format, Session, Form<RelationshipsCreateParam>)) -> FutureResponse<HttpResponse> {
+    use futures::future::ok;
+
+    let follower_id = match sessions_helper::user_session(&session) {
+        Ok(Some(user_session)) => user_session.user_id,
+        _                      => return Box::new(ok(controllers::signin_required(format))),
+    };
+    let followed_id = params.followed_id;
+
+    state
+        .db
+        .send(relationships_message::Follow{
+            request_id: request_id.0,
+            follower_id,
+            followed_id,
+        })
+        .from_err()
+        .and_then(|res| res)
+        .and_then(move |_| {
+            let body = json!({"follower_id": follower_id, "followed_id": followed_id});
+            Ok(controllers::created(format, &format!("/users/{}", followed_id), &format!("/relationships/{}", followed_id), body))
+        })
+        .responder()
+}
+
+pub fn handle_post((state, request_id, format, session, path, params): (State<Context>, RequestId, Format, Session, Path<RelationshipsReadPath>, Form<RelationshipsPostParam>)) -> FutureResponse<HttpResponse> {
+    use futures::future::ok;
+
+    match Method::from_bytes(params.method.as_bytes()) {
+        Ok(Method::DELETE) => handle_destroy((state, request_id, format, session, path)),
+        _                  => Box::new(ok(controllers::http_internal_server_error())),
+    }
+}
+
+pub fn handle_destroy((state, request_id, format, session, path): (State<Context>, RequestId, Format, Session, Path<RelationshipsReadPath>)) -> FutureResponse<HttpResponse> {
+    use futures::future::ok;
+
+    let follower_id = match sessions_helper::user_session(&session) {
+        Ok(Some(user_session)) => user_session.user_id,
+        _                      => return Box::new(ok(controllers::signin_required(format))),
+    };
+    let followed_id = path.followed_id;
+
+    state
+        .db
+        .send(relationships_message::Unfollow{
+            request_id: request_id.0,
+            follower_id,
+            followed_id,
+        })
+        .from_err()
+        .and_then(|res| res)
+        .and_then(move |_| {
+            Ok(controllers::deleted(format, &format!("/users/{}", followed_id)))
+        })
+        .responder()
+}
+
+pub fn handle_following((state, request_id, format, path, query): (State<Context>, RequestId, Format, Path<RelationshipsUsersPath>, Query<PageParam>)) -> FutureResponse<HttpResponse> {
+    let templates = state.templates.clone();
+    let page = query.page();
+    let db = state.db.clone();
+
+    state
+        .db
+        .send(users_message::ReadUser{request_id: request_id.0.clone(), id: path.id})
+        .from_err()
+        .and_then(|res| res)
+        .and_then(move |user| {
+            db
+                .send(relationships_message::ReadFollowing{
+                    request_id: request_id.0,
+                    user_id: user.id,
+                    page,
+                    per_page: pagination_helper::PER_PAGE,
+                })
+                .from_err()
+                .and_then(move |res| {
+                    res.map(move |(users, total)| {
+                        let mut data = Map::new();
+                        data.insert("user".to_string(), to_json(&user));
+                        data.insert("title".to_string(), to_json("フォロー中"));
+                        data.insert("users".to_string(), to_json(&users));
+                        data.insert("total".to_string(), to_json(total));
+                        data.insert("pagination".to_string(), to_json(Pagination::new(page, pagination_helper::PER_PAGE, total)));
+                        data
+                    })
+                })
+        })
+        .and_then(move |data| {
+            Ok(controllers::render(format, templates, "users_follow", Some(data)))
+        })
+        .responder()
+}
+
+pub fn handle_followers((state, request_id, format, path, query): (State<Context>, RequestId, Format, Path<RelationshipsUsersPath>, Query<PageParam>)) -> FutureResponse<HttpResponse> {
+    let templates = state.templates.clone();
+    let page = query.page();
+    let db = state.db.clone();
+
+    state
+        .db
+        .send(users_message::ReadUser{request_id: request_id.0.clone(), id: path.id})
+        .from_err()
+        .and_then(|res| res)
+        .and_then(move |user| {
+            db
+                .send(relationships_message::ReadFollowers{
+                    request_id: request_id.0,
+                    user_id: user.id,
+                    page,
+                    per_page: pagination_helper::PER_PAGE,
+                })
+                .from_err()
+                .and_then(move |res| {
+                    res.map(move |(users, total)| {
+                        let mut data = Map::new();
+                        data.insert("user".to_string(), to_json(&user));
+                        data.insert("title".to_string(), to_json("フォロワー"));
+                        data.insert("users".to_string(), to_json(&users));
+                        data.insert("total".to_string(), to_json(total));
+                        data.insert("pagination".to_string(), to_json(Pagination::new(page, pagination_helper::PER_PAGE, total)));
+                        data
+                    })
+                })
+        })
+        .and_then(move |data| {
+            Ok(controllers::render(format, templates, "users_follow", Some(data)))
+        })
+        .responder()
+}
@@ -1,10 +1,62 @@
-use actix_web::{State, HttpResponse, FutureResponse};
+use handlebars::{to_json};
+use serde_json::value::{Map};
 
-use controllers;
+use actix_web::{State, Query, HttpResponse, FutureResponse, AsyncResponder};
+use actix_web::middleware::session::{Session};
+use futures::Future;
+
+use db::{microposts_message, users_message};
 use context::{Context};
+use controllers;
+use helpers::{sessions_helper};
+use helpers::pagination_helper::{self, PageParam, Pagination};
+use middleware::format::{Format};
+use middleware::request_id::{RequestId};
+use time_zones::{ViewerTimeZone};
 
-pub fn handle_index(state: State<Context>) -> FutureResponse<HttpResponse> {
+pub fn handle_index((state, request_id, format, zone, session, query): (State<Context>, RequestId, Format, ViewerTimeZone, Session, Query<PageParam>)) -> FutureResponse<HttpResponse> {
     use futures::future::ok;
 
-    Box::new(ok(controllers::render(state.templates.clone(), "index", None)))
+    let templates = state.templates.clone();
+    let RequestId(request_id) = request_id;
+    let page = query.page();
+    let flash_message = sessions_helper::get_flash_message(&session);
+
+    let user_id = match sessions_helper::user_session(&session) {
+        Ok(Some(user_session)) => user_session.user_id,
+        _                      => return Box::new(ok(controllers::signin_required(format))),
+    };
+
+    let db = state.db.clone();
+
+    state
+        .db
+        .send(users_message::ReadUser{request_id: request_id.clone(), id: user_id})
+        .from_err()
+        .and_then(|res| res)
+        .and_then(move |user| {
+            db
+                .send(microposts_message::ReadFeed{
+                    request_id,
+                    user_id: user.id,
+                    page,
+                    per_page: pagination_helper::PER_PAGE,
+                })
+                .from_err()
+                .and_then(|res| res)
+                .map(move |(feed_items, total)| {
+                    let mut data = Map::new();
+                    data.insert("current_user".to_string(), to_json(&user));
+                    data.insert("current_user_id".to_string(), to_json(user.id));
+                    data.insert("time_zone".to_string(), to_json(zone.name()));
+                    data.insert("feed_items".to_string(), to_json(&feed_items));
+                    data.insert("pagination".to_string(), to_json(Pagination::new(page, pagination_helper::PER_PAGE, total)));
+                    data.insert("flash_message".to_string(), to_json(flash_message));
+                    data
+                })
+        })
+        .and_then(move |data| {
+            Ok(controllers::render(format, templates, "index", Some(data)))
+        })
+        .responder()
 }
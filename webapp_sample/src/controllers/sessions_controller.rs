@@ -11,6 +11,10 @@ use db::{users_message};
 use context::{Context};
 use controllers;
 use helpers::{sessions_helper};
+use models::{UserSession};
+use sessions::digest::{verify_session_digest};
+use middleware::format::{Format};
+use middleware::request_id::{RequestId};
 
 #[derive(Deserialize)]
 pub struct SessionsCreateParam {
@@ -23,7 +27,7 @@ pub struct SessionsDeleteParam {
     method: String,
 }
 
-pub fn handle_new((state, session): (State<Context>, Session)) -> FutureResponse<HttpResponse> {
+pub fn handle_new((state, request_id, format, session): (State<Context>, RequestId, Format, Session)) -> FutureResponse<HttpResponse> {
     use futures::future::ok;
     
     let templates = state.templates.clone();
@@ -32,23 +36,21 @@ pub fn handle_new((state, session): (State<Context>, Session)) -> FutureResponse
         Ok(Some(user_session)) => {
             state
                 .db
-                .send(users_message::ReadUser{id: user_session.user_id})
+                .send(users_message::ReadUser{request_id: request_id.0, id: user_session.user_id})
                 .from_err()
                 .and_then(move |res| match res {
                     Ok(user) => {
-                        match sessions_helper::valid_session_id(
-                            &user_session,
-                            &user.session_digest.clone().unwrap(),
-                        ) {
-                            Ok(_) => {
-                                let mut data = Map::new();
-                                data.insert("user".to_string(), to_json(&user));
-
-                                Ok(controllers::render(templates, "sessions_delete", Some(data)))
-                            },
-                            Err(_) => {
-                                Ok(controllers::http_internal_server_error())
-                            }
+                        let valid = user.session_digest
+                            .as_ref()
+                            .is_some_and(|digest| verify_session_digest(&user_session.session_id, digest));
+
+                        if valid {
+                            let mut data = Map::new();
+                            data.insert("user".to_string(), to_json(&user));
+
+                            Ok(controllers::render(format, templates, "sessions_delete", Some(data)))
+                        } else {
+                            Ok(controllers::http_internal_server_error())
                         }
                     },
                     Err(_) => {
@@ -68,13 +70,13 @@ pub fn handle_new((state, session): (State<Context>, Session)) -> FutureResponse
                 )
             );
         
-            Box::new(ok(controllers::render(templates, "sessions_new", Some(data))))
+            Box::new(ok(controllers::render(format, templates, "sessions_new", Some(data))))
         },
     }
 }
 
-pub fn handle_create((state, session, params): (State<Context>, Session, Form<SessionsCreateParam>)) -> FutureResponse<HttpResponse> {
-    use futures::future::{ok, Either};
+pub fn handle_create((state, request_id, format, session, params): (State<Context>, RequestId, Format, Session, Form<SessionsCreateParam>)) -> FutureResponse<HttpResponse> {
+    use futures::future::ok;
    
     let mut error_messages =  Vec::new();
 
@@ -100,68 +102,54 @@ pub fn handle_create((state, session, params): (State<Context>, Session, Form<Se
     }
 
     if error_messages.len() > 0 {
-        let flash_message = sessions_helper::FlashMessage{error_messages: error_messages};
-        sessions_helper::set_flash_message(
-            &session,
-            flash_message,
-        );
-        return Box::new(ok(controllers::http_redirect("/signin", 303)));
+        return Box::new(ok(controllers::failed(format, &session, "/signin", 422, error_messages)));
     }
 
+    let session_id = sessions_helper::create_session_id();
+
     state
         .db
-        .send(users_message::ReadUserByEmail{email: params.user_email.clone()})
-        .from_err()
-        .and_then(move |res| {
-            // TODO Process missing user error
-            res.map(move |user| user)
+        .send(users_message::SignInUser{
+            request_id: request_id.0,
+            email: params.user_email.clone(),
+            password: params.user_password.clone(),
+            session_id: session_id.clone(),
         })
+        .from_err()
+        .and_then(|res| res)
         .and_then(move |user| {
-            match sessions_helper::signin(&user, &params.user_password, &session) {
-                Ok(user_session) => {
-                    Either::A(
-                        state
-                            .db
-                            .send(users_message::UpdateUserSession{
-                                id: user_session.user_id,
-                                session_id: user_session.session_id,
-                            })
-                            .from_err()
-                            .and_then(move |res| {
-                                res.map(move |_user| ())
-                            })
-                        )
+            match user {
+                Some(user) => {
+                    sessions_helper::set_user_session(&session, UserSession{user_id: user.id, session_id});
+
+                    // An account pending deletion goes to the page where it can be restored.
+                    let redirect_to = if user.deletion_scheduled_at.is_some() { "/account" } else { "/signin" };
+                    Ok(controllers::created(format, redirect_to, "/signin", to_json(&user)))
+                },
+                None => {
+                    let error_messages = vec!["メールアドレスもしくはパスワードが間違っています。".to_string()];
+                    Ok(controllers::failed(format, &session, "/signin", 401, error_messages))
                 },
-                Err(_e) => {
-                    Either::B({
-                        let flash_message = sessions_helper::FlashMessage{
-                            error_messages: vec!["メールアドレスもしくはパスワードが間違っています。".to_string()]
-                        };
-
-                        sessions_helper::set_flash_message(&session, flash_message);
-                        ok(())
-                    })
-                }
             }
         })
-        .and_then(move |_| {
-            Ok(controllers::http_redirect("/signin", 303))
-        })
         .responder()
 }
 
-pub fn handle_post((state, session, params): (State<Context>, Session, Form<SessionsDeleteParam>)) -> FutureResponse<HttpResponse> {
+pub fn handle_post((state, format, session, params): (State<Context>, Format, Session, Form<SessionsDeleteParam>)) -> FutureResponse<HttpResponse> {
     use futures::future::ok;
    
      match Method::from_bytes(params.method.as_bytes()) {
-         Ok(Method::DELETE) => handle_destroy((state, session, params)),
+         Ok(Method::DELETE) => handle_destroy((state, format, session)),
          _                  => Box::new(ok(controllers::http_internal_server_error())),
      }
 }
 
-pub fn handle_destroy((_state, session, _params): (State<Context>, Session, Form<SessionsDeleteParam>)) -> FutureResponse<HttpResponse> {
+pub fn handle_destroy((state, format, session): (State<Context>, Format, Session)) -> FutureResponse<HttpResponse> {
     use futures::future::ok;
    
+    if let Ok(Some(user_session)) = sessions_helper::user_session(&session) {
+        state.session_cache.forget(&user_session);
+    }
     sessions_helper::signout(&session);
-    Box::new(ok(controllers::http_redirect("/signin", 303)))
+    Box::new(ok(controllers::deleted(format, "/signin")))
 }
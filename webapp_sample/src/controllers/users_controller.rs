@@ -1,40 +1,74 @@
 use handlebars::{to_json};
-use serde_json::value::{Map};
+use regex::{Regex};
+use serde_json::value::{Map, Value};
 
-use actix_web::{State, Path, Form, HttpResponse, FutureResponse, AsyncResponder};
-use actix_web::http::{Method};
+use actix_web::{State, Path, Form, Query, HttpRequest, HttpResponse, FutureResponse, AsyncResponder};
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::session::{Session};
 use futures::Future;
 
-use db::{users_message};
+use db::{invitations_message, microposts_message, relationships_message, users_message};
+use db::invitations_message::{Acceptance};
+use db::users_message::{PasswordChange, UserUpdate};
+use models;
+use images::{self, avatars_message};
+use invitations;
 use context::{Context};
 use controllers;
+use helpers::{search_helper, sessions_helper};
+use helpers::search_helper::{SearchParam};
+use helpers::pagination_helper::{self, PageParam, Pagination};
+use helpers::uploads_helper::{self, UploadError};
+use middleware::format::{Format};
+use middleware::request_id::{RequestId};
+use time_zones::{self, ViewerTimeZone};
 
 #[derive(Deserialize)]
 pub struct UsersReadPath{
     pub id: i32,
 }
 
+#[derive(Deserialize)]
+pub struct UsersAvatarPath{
+    pub id:   i32,
+    pub size: u32,
+}
+
+#[derive(Deserialize)]
+pub struct UsersNewParam {
+    invitation: Option<String>,
+}
+
+// The address comes from the invitation.
 #[derive(Deserialize)]
 pub struct UsersCreateParam {
-    user_name:     String,
-    user_email:    String,
-    user_password: String,
+    invitation_token: String,
+    user_name:        String,
+    user_password:    String,
 }
 
 #[derive(Deserialize)]
 pub struct UsersPostParam {
-    method:        String,
-    user_name:     Option<String>,
-    user_email:    Option<String>,
-    user_password: Option<String>,
+    method:            String,
+    user_lock_version: Option<i32>,
+    user_name:         Option<String>,
+    user_email:        Option<String>,
+    user_time_zone:    Option<String>,
 }
 
-pub fn handle_index(state: State<Context>) -> FutureResponse<HttpResponse> {
+#[derive(Deserialize)]
+pub struct UsersPasswordParam {
+    current_password:      String,
+    password:              String,
+    password_confirmation: String,
+}
+
+pub fn handle_index((state, request_id, format): (State<Context>, RequestId, Format)) -> FutureResponse<HttpResponse> {
     let templates = state.templates.clone();
     
     state
         .db
-        .send(users_message::ReadUsers{})
+        .send(users_message::ReadUsers{request_id: request_id.0})
         .from_err()
         .and_then(move |res| {
             res.map(move |users| {
@@ -44,61 +78,206 @@ pub fn handle_index(state: State<Context>) -> FutureResponse<HttpResponse> {
             })
         })
         .and_then(move |data| {
-            Ok(controllers::render(templates, "users_index", Some(data)))
+            Ok(controllers::render(format, templates, "users_index", Some(data)))
         })
         .responder()
 }
 
-pub fn handle_new(state: State<Context>) -> FutureResponse<HttpResponse> {
-    use futures::future::ok;
+// Sign-up is by invitation only: the form is shown for a link that can
+// still be used, filled in with the invited address.
+pub fn handle_new((state, request_id, format, session, query): (State<Context>, RequestId, Format, Session, Query<UsersNewParam>)) -> FutureResponse<HttpResponse> {
+    use futures::future::{ok, Either};
+
+    let templates = state.templates.clone();
+    let flash_message = sessions_helper::get_flash_message(&session);
+    let token = query.into_inner().invitation.unwrap_or_default();
+
+    let invitation = if invitations::valid_token(&token) {
+        Either::A(
+            state
+                .db
+                .send(invitations_message::ReadPendingInvitation{
+                    request_id: request_id.0,
+                    token_digest: invitations::token_digest(&token),
+                })
+                .from_err()
+                .and_then(|res| res)
+        )
+    } else {
+        Either::B(ok(None))
+    };
+
+    invitation
+        .map(move |invitation| {
+            let found = invitation.is_some();
+
+            let mut data = Map::new();
+            data.insert("invitation".to_string(), to_json(&invitation));
+            data.insert("invitation_token".to_string(), to_json(if found { Some(token) } else { None }));
+            data.insert("flash_message".to_string(), to_json(flash_message));
 
-    Box::new(ok(controllers::render(state.templates.clone(), "users_new", None)))
+            let mut resp = controllers::render(format, templates, "users_new", Some(data));
+            if !found {
+                *resp.status_mut() = StatusCode::FORBIDDEN;
+            }
+            resp
+        })
+        .responder()
 }
 
-pub fn handle_create((state, params): (State<Context>, Form<UsersCreateParam>)) -> FutureResponse<HttpResponse> {
+pub fn handle_search((state, request_id, format, query, search): (State<Context>, RequestId, Format, Query<PageParam>, Query<SearchParam>)) -> FutureResponse<HttpResponse> {
+    use futures::future::ok;
+
+    let templates = state.templates.clone();
+    let page = query.page();
+    let q = search.q();
+
+    let mut data = Map::new();
+    data.insert("q".to_string(), to_json(&q));
+    data.insert("pagination_query".to_string(), to_json(search_helper::query_string(&q)));
+
+    let fts_query = match search_helper::fts_query(&q) {
+        Some(fts_query) => fts_query,
+        None            => {
+            if !q.is_empty() {
+                data.insert("notice".to_string(), to_json(format!("{}文字以上の語句で検索してください", search_helper::MIN_TERM_CHARS)));
+            }
+            return Box::new(ok(controllers::render(format, templates, "users_search", Some(data))));
+        },
+    };
+
     state
         .db
-        .send(users_message::CreateUser{
-            name: params.user_name.clone(),
-            email: params.user_email.clone(),
-            password: params.user_password.clone()
+        .send(users_message::SearchUsers{
+            request_id: request_id.0,
+            query: fts_query,
+            page,
+            per_page: pagination_helper::PER_PAGE,
         })
         .from_err()
         .and_then(move |res| {
-            res.map(move |user| user)
+            res.map(move |(mut results, total)| {
+                for result in &mut results {
+                    result.name_snippet = search_helper::highlight_html(&result.name_snippet);
+                    result.email_snippet = search_helper::highlight_html(&result.email_snippet);
+                }
+
+                data.insert("results".to_string(), to_json(&results));
+                data.insert("total".to_string(), to_json(total));
+                data.insert("pagination".to_string(), to_json(Pagination::new(page, pagination_helper::PER_PAGE, total)));
+                data
+            })
         })
-        .and_then(move |_| {
-            Ok(controllers::http_redirect("/users", 303))
+        .and_then(move |data| {
+            Ok(controllers::render(format, templates, "users_search", Some(data)))
+        })
+        .responder()
+}
+
+pub fn handle_create((state, request_id, format, session, params): (State<Context>, RequestId, Format, Session, Form<UsersCreateParam>)) -> FutureResponse<HttpResponse> {
+    use futures::future::ok;
+
+    let UsersCreateParam{
+        invitation_token,
+        user_name,
+        user_password,
+    } = params.into_inner();
+
+    if !invitations::valid_token(&invitation_token) {
+        let error_messages = vec!["招待リンクが無効です".to_string()];
+        return Box::new(ok(controllers::failed(format, &session, "/users/new", 422, error_messages)));
+    }
+    // Only letters and digits from here on, so the token can go into the URL as is.
+    let new_path = format!("/users/new?invitation={}", invitation_token);
+
+    let mut error_messages = Vec::new();
+    if user_name.trim().is_empty() {
+        error_messages.push("名前を入力してください".to_string());
+    }
+    let re_password = Regex::new(r"^[a-zA-Z\d]{8,30}$").unwrap();
+    if !re_password.is_match(&user_password) {
+        error_messages.push("パスワードは英数字8文字以上、30文字以下を入力してください".to_string());
+    }
+    if !error_messages.is_empty() {
+        return Box::new(ok(controllers::failed(format, &session, &new_path, 422, error_messages)));
+    }
+
+    state
+        .db
+        .send(invitations_message::AcceptInvitation{
+            request_id: request_id.0,
+            token_digest: invitations::token_digest(&invitation_token),
+            name: user_name,
+            password: user_password,
+        })
+        .from_err()
+        .and_then(|res| res)
+        .and_then(move |acceptance| {
+            Ok(match acceptance {
+                Acceptance::Accepted(user) => controllers::created(format, "/signin", &format!("/users/{}", user.id), to_json(&user)),
+                Acceptance::Invalid        => controllers::failed(format, &session, &new_path, 422, vec!["招待リンクが無効か、期限が切れています".to_string()]),
+                Acceptance::EmailTaken     => controllers::failed(format, &session, &new_path, 422, vec!["このメールアドレスは登録済みです".to_string()]),
+            })
         })
         .responder()
 }
 
-pub fn handle_show((state, path): (State<Context>, Path<UsersReadPath>)) -> FutureResponse<HttpResponse> {
+pub fn handle_show((state, request_id, format, zone, session, path, query): (State<Context>, RequestId, Format, ViewerTimeZone, Session, Path<UsersReadPath>, Query<PageParam>)) -> FutureResponse<HttpResponse> {
     let templates = state.templates.clone();
+    let current_user_id = current_user_id(&session);
+    let page = query.page();
+    let db = state.db.clone();
     
     state
         .db
-        .send(users_message::ReadUser{id: path.id})
+        .send(users_message::ReadUser{request_id: request_id.0.clone(), id: path.id})
         .from_err()
-        .and_then(move |res| {
-            res.map(move |user| {
-                let mut data = Map::new();
-                data.insert("user".to_string(), to_json(&user));
-                data
-            })
+        .and_then(|res| res)
+        .and_then(move |user| {
+            db
+                .send(relationships_message::ReadRelationshipSummary{
+                    request_id: request_id.0.clone(),
+                    user_id: user.id,
+                    viewer_id: current_user_id,
+                })
+                .from_err()
+                .and_then(|res| res)
+                .map(move |relationship| (user, relationship, db, request_id))
+        })
+        .and_then(move |(user, relationship, db, request_id)| {
+            db
+                .send(microposts_message::ReadMicroposts{
+                    request_id: request_id.0,
+                    user_id: user.id,
+                    page,
+                    per_page: pagination_helper::PER_PAGE,
+                })
+                .from_err()
+                .and_then(move |res| {
+                    res.map(move |(feed_items, total)| {
+                        let mut data = Map::new();
+                        data.insert("user".to_string(), to_json(&user));
+                        data.insert("relationship".to_string(), to_json(&relationship));
+                        data.insert("time_zone".to_string(), to_json(zone.name()));
+                        data.insert("feed_items".to_string(), to_json(&feed_items));
+                        data.insert("current_user_id".to_string(), to_json(current_user_id));
+                        data.insert("pagination".to_string(), to_json(Pagination::new(page, pagination_helper::PER_PAGE, total)));
+                        data
+                    })
+                })
         })
         .and_then(move |data| {
-            Ok(controllers::render(templates, "users_show", Some(data)))
+            Ok(controllers::render(format, templates, "users_show", Some(data)))
         })
         .responder()
 }
 
-pub fn handle_show_chain((state, path): (State<Context>, Path<UsersReadPath>)) -> FutureResponse<HttpResponse> {
+pub fn handle_show_chain((state, request_id, path): (State<Context>, RequestId, Path<UsersReadPath>)) -> FutureResponse<HttpResponse> {
     let templates = state.templates.clone();
 
     state
         .db
-        .send(users_message::ReadUser{id: path.id})
+        .send(users_message::ReadUser{request_id: request_id.0.clone(), id: path.id})
         .from_err()
         .and_then(move |res| {
             res.map(move |user| user)
@@ -106,7 +285,7 @@ pub fn handle_show_chain((state, path): (State<Context>, Path<UsersReadPath>)) -
         .and_then(move |user| {
             state
                 .db
-                .send(users_message::ReadUser{id: user.id})
+                .send(users_message::ReadUser{request_id: request_id.0.clone(), id: user.id})
                 .from_err()
                 .and_then(move |res| {
                     res.map(move |user| user)
@@ -114,7 +293,7 @@ pub fn handle_show_chain((state, path): (State<Context>, Path<UsersReadPath>)) -
                 .and_then(move |user| {
                     state
                         .db
-                        .send(users_message::ReadUser{id: user.id})
+                        .send(users_message::ReadUser{request_id: request_id.0.clone(), id: user.id})
                         .from_err()
                         .and_then(move |res| {
                             res.map(move |user| {
@@ -126,81 +305,307 @@ pub fn handle_show_chain((state, path): (State<Context>, Path<UsersReadPath>)) -
                 })
         }) 
         .and_then(move |data| {
-            Ok(controllers::render(templates, "users_show", Some(data)))
+            Ok(controllers::render(Format::Html, templates, "users_show", Some(data)))
         })
         .responder()
 }
 
-pub fn handle_edit((state, path): (State<Context>, Path<UsersReadPath>)) -> FutureResponse<HttpResponse> {
+pub fn handle_edit((state, request_id, format, zone, session, path): (State<Context>, RequestId, Format, ViewerTimeZone, Session, Path<UsersReadPath>)) -> FutureResponse<HttpResponse> {
     let templates = state.templates.clone();
+    let flash_message = sessions_helper::get_flash_message(&session);
+    let current_user_id = current_user_id(&session);
     
     state
         .db
-        .send(users_message::ReadUser{id: path.id})
+        .send(users_message::ReadUser{request_id: request_id.0.clone(), id: path.id})
         .from_err()
         .and_then(move |res| {
-            res.map(move |user| {
-                let mut data = Map::new();
-                data.insert("user".to_string(), to_json(&user));
-                data
-            })
+            res.map(move |user| edit_data(&user, current_user_id, zone, flash_message))
         })
         .and_then(move |data| {
-            Ok(controllers::render(templates, "users_edit", Some(data)))
+            Ok(controllers::render(format, templates, "users_edit", Some(data)))
         })
         .responder()
 }
 
-pub fn handle_post((state, path, params): (State<Context>, Path<UsersReadPath>, Form<UsersPostParam>)) -> FutureResponse<HttpResponse> {
+fn edit_data(user: &models::User, current_user_id: Option<i32>, zone: ViewerTimeZone, flash_message: sessions_helper::FlashMessage) -> Map<String, Value> {
+    // `eq` only compares numbers, so the selected zone is marked here.
+    let time_zone_options: Vec<Value> = time_zones::names()
+        .map(|name| json!({"name": name, "selected": name == user.time_zone}))
+        .collect();
+
+    let mut data = Map::new();
+    data.insert("user".to_string(), to_json(user));
+    data.insert("current_user_id".to_string(), to_json(current_user_id));
+    data.insert("time_zone".to_string(), to_json(zone.name()));
+    data.insert("time_zone_options".to_string(), to_json(time_zone_options));
+    data.insert("flash_message".to_string(), to_json(flash_message));
+    data.insert("avatar_max_bytes".to_string(), to_json(images::MAX_UPLOAD_BYTES));
+    data
+}
+
+pub fn handle_post((state, request_id, format, zone, session, path, params): (State<Context>, RequestId, Format, ViewerTimeZone, Session, Path<UsersReadPath>, Form<UsersPostParam>)) -> FutureResponse<HttpResponse> {
     use futures::future::ok;
    
      match Method::from_bytes(params.method.as_bytes()) {
-         Ok(Method::PATCH)  => handle_update((state, path, params)),
-         Ok(Method::DELETE) => handle_destroy((state, path, params)),
+         Ok(Method::PATCH)  => handle_update((state, request_id, format, zone, session, path, params)),
+         Ok(Method::DELETE) => handle_destroy((state, request_id, format, path)),
          _                  => Box::new(ok(controllers::http_internal_server_error())),
      }
 }
 
-pub fn handle_update((state, path, params): (State<Context>, Path<UsersReadPath>, Form<UsersPostParam>)) -> FutureResponse<HttpResponse> {
+pub fn handle_update((state, request_id, format, zone, session, path, params): (State<Context>, RequestId, Format, ViewerTimeZone, Session, Path<UsersReadPath>, Form<UsersPostParam>)) -> FutureResponse<HttpResponse> {
+    use futures::future::ok;
+
     let UsersPostParam{
         method:_,
+        user_lock_version,
         user_name,
         user_email,
-        user_password
+        user_time_zone,
     } = params.into_inner();
-   
-    let name = user_name.unwrap_or("".to_string());
-    let email = user_email.unwrap_or("".to_string());
-    let password = user_password.unwrap_or("".to_string());
+
+    let lock_version = match user_lock_version {
+        Some(lock_version) => lock_version,
+        None               => return Box::new(ok(controllers::http_status(400))),
+    };
+    // Stored by its canonical name.
+    let time_zone = match user_time_zone.as_ref().map(|name| time_zones::parse(name)) {
+        Some(Some(time_zone)) => Some(time_zone.name().to_string()),
+        Some(None)            => {
+            let error_messages = vec!["タイムゾーンが正しくありません".to_string()];
+            return Box::new(ok(controllers::failed(format, &session, &format!("/users/{}/edit", path.id), 422, error_messages)));
+        },
+        None                  => None,
+    };
+    let current_user_id = current_user_id(&session);
+    let templates = state.templates.clone();
     
     state
         .db
         .send(users_message::UpdateUser{
+            request_id: request_id.0,
             id: path.id,
-            name: name.clone(),
-            email: email.clone(),
-            password: password.clone()
+            lock_version,
+            name: user_name,
+            email: user_email,
+            time_zone,
         })
         .from_err()
+        .and_then(|res| res)
+        .and_then(move |update| {
+            match update {
+                UserUpdate::Updated(user)  => Ok(controllers::updated(format, "/users", to_json(&user))),
+                UserUpdate::Conflict(user) => {
+                    let flash_message = sessions_helper::FlashMessage{
+                        error_messages: vec!["他のユーザが先に更新しました。現在の内容を確認してから再度更新してください".to_string()],
+                    };
+                    let mut resp = controllers::render(format, templates, "users_edit", Some(edit_data(&user, current_user_id, zone, flash_message)));
+                    *resp.status_mut() = StatusCode::CONFLICT;
+                    Ok(resp)
+                },
+            }
+        })
+        .responder()
+}
+
+pub fn handle_destroy((state, request_id, format, path): (State<Context>, RequestId, Format, Path<UsersReadPath>)) -> FutureResponse<HttpResponse> {
+    state
+        .db
+        .send(users_message::DeleteUser{request_id: request_id.0, id: path.id})
+        .from_err()
         .and_then(move |res| {
             res.map(move |user| user)
         })
         .and_then(move |_| {
-            Ok(controllers::http_redirect("/users", 303))
+            Ok(controllers::deleted(format, "/users"))
+        })
+        .responder()
+}
+
+pub fn handle_avatar_update((req, state, request_id, format, session, path): (HttpRequest<Context>, State<Context>, RequestId, Format, Session, Path<UsersReadPath>)) -> FutureResponse<HttpResponse> {
+    use futures::future::{Either};
+
+    let RequestId(request_id) = request_id;
+    let edit_path = format!("/users/{}/edit", path.id);
+    let id = path.id;
+    let db = state.db.clone();
+    let image_executor = state.images.clone();
+
+    uploads_helper::read_file_field(&req, "avatar", images::MAX_UPLOAD_BYTES)
+        .and_then(|data| {
+            match images::sniff_format(&data) {
+                Some(_) => Ok(data),
+                None    => Err(UploadError::UnsupportedFormat),
+            }
+        })
+        .and_then({
+            let db = db.clone();
+            let request_id = request_id.clone();
+            move |data| {
+                db
+                    .send(users_message::ReadUser{request_id, id})
+                    .map_err(|_| UploadError::Internal)
+                    .and_then(|res| res.map_err(|_| UploadError::Internal))
+                    .map(move |user| (user, data))
+            }
+        })
+        .and_then({
+            let image_executor = image_executor.clone();
+            let request_id = request_id.clone();
+            move |(user, data)| {
+                image_executor
+                    .send(avatars_message::StoreAvatar{
+                        request_id,
+                        user_uuid: user.uuid.clone(),
+                        data,
+                    })
+                    .map_err(|_| UploadError::Internal)
+                    .and_then(|res| res)
+                    .map(move |key| (user, key))
+            }
+        })
+        .and_then({
+            let request_id = request_id.clone();
+            move |(user, key)| {
+                db
+                    .send(users_message::UpdateUserAvatar{
+                        request_id,
+                        id: user.id,
+                        avatar_key: Some(key),
+                    })
+                    .map_err(|_| UploadError::Internal)
+                    .and_then(|res| res.map_err(|_| UploadError::Internal))
+                    .map(move |_| user.avatar_key)
+            }
+        })
+        .and_then(move |old_key| {
+            match old_key {
+                Some(key) => Either::A(
+                    image_executor
+                        .send(avatars_message::DeleteAvatar{request_id, key})
+                        .map_err(|_| UploadError::Internal)
+                        .and_then(|res| res)
+                ),
+                None => Either::B(futures::future::ok(())),
+            }
+        })
+        .then(move |res| {
+            match res {
+                Ok(()) => Ok(controllers::updated(format, &edit_path, json!({"id": id}))),
+                Err(UploadError::Internal) => Ok(controllers::http_internal_server_error()),
+                Err(e) => Ok(controllers::failed(format, &session, &edit_path, 422, vec![e.message().to_string()])),
+            }
         })
         .responder()
 }
 
-pub fn handle_destroy((state, path, _params): (State<Context>, Path<UsersReadPath>, Form<UsersPostParam>)) -> FutureResponse<HttpResponse> {
+pub fn handle_avatar_show((state, request_id, path): (State<Context>, RequestId, Path<UsersAvatarPath>)) -> FutureResponse<HttpResponse> {
+    use futures::future::{ok, Either};
+
+    let RequestId(request_id) = request_id;
+    let image_executor = state.images.clone();
+    let size = path.size;
+
     state
         .db
-        .send(users_message::DeleteUser{id: path.id})
+        .send(users_message::ReadUser{request_id: request_id.clone(), id: path.id})
         .from_err()
-        .and_then(move |res| {
-            res.map(move |user| user)
+        .and_then(|res| res)
+        .and_then(move |user| {
+            match user.avatar_key {
+                Some(key) => Either::A(
+                    image_executor
+                        .send(avatars_message::ReadAvatar{request_id, key, size})
+                        .from_err()
+                ),
+                None => Either::B(ok(Ok(None))),
+            }
         })
-        .and_then(move |_| {
-            Ok(controllers::http_redirect("/users", 303))
+        .and_then(|res| {
+            match res {
+                Ok(Some(png)) => Ok(
+                    HttpResponse::Ok()
+                        .content_type("image/png")
+                        .header("Cache-Control", "no-cache")
+                        .body(png)
+                ),
+                Ok(None) => Ok(controllers::http_status(404)),
+                Err(_)   => Ok(controllers::http_internal_server_error()),
+            }
+        })
+        .responder()
+}
+
+pub fn handle_password_edit((state, format, session, path): (State<Context>, Format, Session, Path<UsersReadPath>)) -> FutureResponse<HttpResponse> {
+    use futures::future::ok;
+
+    if current_user_id(&session) != Some(path.id) {
+        return Box::new(ok(controllers::http_status(403)));
+    }
+
+    let mut data = Map::new();
+    data.insert("user_id".to_string(), to_json(path.id));
+    data.insert("flash_message".to_string(), to_json(sessions_helper::get_flash_message(&session)));
+
+    Box::new(ok(controllers::render(format, state.templates.clone(), "users_password", Some(data))))
+}
+
+pub fn handle_password_update((state, request_id, format, session, path, params): (State<Context>, RequestId, Format, Session, Path<UsersReadPath>, Form<UsersPasswordParam>)) -> FutureResponse<HttpResponse> {
+    use futures::future::ok;
+
+    let id = path.id;
+    if current_user_id(&session) != Some(id) {
+        return Box::new(ok(controllers::http_status(403)));
+    }
+
+    let password_path = format!("/users/{}/password", id);
+    let mut error_messages = Vec::new();
+
+    let re_password = Regex::new(r"^[a-zA-Z\d]{8,30}$").unwrap();
+    if !re_password.is_match(&params.password) {
+        error_messages.push("パスワードは英数字8文字以上、30文字以下を入力してください".to_string());
+    }
+    if params.password != params.password_confirmation {
+        error_messages.push("確認用パスワードが一致しません".to_string());
+    }
+
+    if !error_messages.is_empty() {
+        return Box::new(ok(controllers::failed(format, &session, &password_path, 422, error_messages)));
+    }
+
+    let UsersPasswordParam{current_password, password, ..} = params.into_inner();
+    let user_session = sessions_helper::new_user_session(id);
+
+    state
+        .db
+        .send(users_message::ChangeUserPassword{
+            request_id: request_id.0,
+            id,
+            current_password,
+            new_password: password,
+            session_id: user_session.session_id.clone(),
+        })
+        .from_err()
+        .and_then(|res| res)
+        .and_then(move |change| {
+            match change {
+                PasswordChange::Changed       => {
+                    sessions_helper::set_user_session(&session, user_session);
+                    Ok(controllers::updated(format, &format!("/users/{}", id), json!({"id": id})))
+                },
+                PasswordChange::WrongPassword => {
+                    let error_messages = vec!["現在のパスワードが間違っています".to_string()];
+                    Ok(controllers::failed(format, &session, &password_path, 422, error_messages))
+                },
+            }
         })
         .responder()
 }
+
+fn current_user_id(session: &Session) -> Option<i32> {
+    match sessions_helper::user_session(session) {
+        Ok(Some(user_session)) => Some(user_session.user_id),
+        _                      => None,
+    }
+}
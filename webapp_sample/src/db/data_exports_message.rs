@@ -0,0 +1,228 @@
+use chrono::*;
+
+use actix::prelude::*;
+use actix_web::*;
+
+use diesel;
+use diesel::prelude::*;
+
+use models;
+use schema;
+use db::{DbExecutor, db_error, transaction};
+use db::jobs_message;
+use jobs::{Task};
+
+pub const STATUS_PENDING: &str = "pending";
+pub const STATUS_READY: &str = "ready";
+pub const STATUS_FAILED: &str = "failed";
+
+// How many of a user's most recent exports are listed.
+const LISTED_EXPORTS: i64 = 10;
+
+pub struct CreateDataExport {
+    pub request_id: String,
+    pub user_id: i32,
+}
+
+impl Message for CreateDataExport {
+    type Result = Result<models::DataExport, Error>;
+}
+
+impl Handler<CreateDataExport> for DbExecutor {
+    type Result = Result<models::DataExport, Error>;
+
+    fn handle(&mut self, msg: CreateDataExport, _: &mut Self::Context) -> Self::Result {
+        use self::schema::data_exports::dsl::*;
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        let now = Utc::now().naive_utc();
+        let new_export = models::NewDataExport {
+            user_id: msg.user_id,
+            status: STATUS_PENDING,
+            created_at: now,
+        };
+
+        transaction(conn, || {
+            diesel::insert_into(data_exports)
+                .values(&new_export)
+                .execute(conn)?;
+
+            let export = data_exports
+                .filter(user_id.eq(msg.user_id))
+                .order(id.desc())
+                .first::<models::DataExport>(conn)?;
+
+            jobs_message::enqueue(conn, &Task::BuildDataExport{export_id: export.id, user_id: msg.user_id}, now)?;
+
+            Ok(export)
+        })
+        .map_err(|e| db_error(&msg.request_id, e))
+    }
+}
+
+pub struct ReadDataExports {
+    pub request_id: String,
+    pub user_id: i32,
+}
+
+impl Message for ReadDataExports {
+    type Result = Result<Vec<models::DataExport>, Error>;
+}
+
+impl Handler<ReadDataExports> for DbExecutor {
+    type Result = Result<Vec<models::DataExport>, Error>;
+
+    fn handle(&mut self, msg: ReadDataExports, _: &mut Self::Context) -> Self::Result {
+        use self::schema::data_exports::dsl::*;
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        let select_exports = data_exports
+            .filter(user_id.eq(msg.user_id))
+            .order(id.desc())
+            .limit(LISTED_EXPORTS)
+            .load::<models::DataExport>(conn)
+            .map_err(|e| db_error(&msg.request_id, e))?;
+
+        Ok(select_exports)
+    }
+}
+
+// Only found when it belongs to `user_id`.
+pub struct ReadDataExport {
+    pub request_id: String,
+    pub id: i32,
+    pub user_id: i32,
+}
+
+impl Message for ReadDataExport {
+    type Result = Result<models::DataExport, Error>;
+}
+
+impl Handler<ReadDataExport> for DbExecutor {
+    type Result = Result<models::DataExport, Error>;
+
+    fn handle(&mut self, msg: ReadDataExport, _: &mut Self::Context) -> Self::Result {
+        use self::schema::data_exports::dsl::*;
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        let select_export = data_exports
+            .filter(id.eq(msg.id))
+            .filter(user_id.eq(msg.user_id))
+            .first(conn)
+            .map_err(|e| db_error(&msg.request_id, e))?;
+
+        Ok(select_export)
+    }
+}
+
+// `storage_key` is where the archive was stored, or None when building it failed.
+pub struct FinishDataExport {
+    pub request_id: String,
+    pub id: i32,
+    pub storage_key: Option<String>,
+}
+
+impl Message for FinishDataExport {
+    type Result = Result<(), Error>;
+}
+
+impl Handler<FinishDataExport> for DbExecutor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: FinishDataExport, _: &mut Self::Context) -> Self::Result {
+        use self::schema::data_exports::dsl::*;
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        diesel::update(data_exports
+            .find(msg.id))
+            .set((
+                status.eq(if msg.storage_key.is_some() { STATUS_READY } else { STATUS_FAILED }),
+                storage_key.eq(&msg.storage_key),
+                completed_at.eq(Some(Utc::now().naive_utc())),
+            ))
+            .execute(conn)
+            .map_err(|e| db_error(&msg.request_id, e))?;
+
+        Ok(())
+    }
+}
+
+// Everything stored about one user, for their data export.
+pub struct PersonalData {
+    pub user: models::User,
+    pub microposts: Vec<models::Micropost>,
+    pub following: Vec<models::User>,
+    pub followers: Vec<models::User>,
+    // (created_at, last_seen_at) of each session.
+    pub sessions: Vec<(NaiveDateTime, NaiveDateTime)>,
+    pub data_exports: Vec<models::DataExport>,
+}
+
+pub struct ReadPersonalData {
+    pub request_id: String,
+    pub user_id: i32,
+}
+
+impl Message for ReadPersonalData {
+    type Result = Result<PersonalData, Error>;
+}
+
+impl Handler<ReadPersonalData> for DbExecutor {
+    type Result = Result<PersonalData, Error>;
+
+    fn handle(&mut self, msg: ReadPersonalData, _: &mut Self::Context) -> Self::Result {
+        use self::schema::{data_exports, microposts, relationships, sessions, users};
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        transaction(conn, || {
+            let user = users::table
+                .find(msg.user_id)
+                .first(conn)?;
+
+            let user_microposts = microposts::table
+                .filter(microposts::user_id.eq(msg.user_id))
+                .order(microposts::id.asc())
+                .load(conn)?;
+
+            let following = users::table
+                .filter(users::id.eq_any(relationships::table
+                    .select(relationships::followed_id)
+                    .filter(relationships::follower_id.eq(msg.user_id))))
+                .order(users::id.asc())
+                .load(conn)?;
+
+            let followers = users::table
+                .filter(users::id.eq_any(relationships::table
+                    .select(relationships::follower_id)
+                    .filter(relationships::followed_id.eq(msg.user_id))))
+                .order(users::id.asc())
+                .load(conn)?;
+
+            let user_sessions = sessions::table
+                .select((sessions::created_at, sessions::last_seen_at))
+                .filter(sessions::user_id.eq(msg.user_id))
+                .order(sessions::created_at.asc())
+                .load(conn)?;
+
+            let user_data_exports = data_exports::table
+                .filter(data_exports::user_id.eq(msg.user_id))
+                .order(data_exports::id.asc())
+                .load(conn)?;
+
+            Ok(PersonalData {
+                user,
+                microposts: user_microposts,
+                following,
+                followers,
+                sessions: user_sessions,
+                data_exports: user_data_exports,
+            })
+        })
+        .map_err(|e| db_error(&msg.request_id, e))
+    }
+}
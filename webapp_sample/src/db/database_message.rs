@@ -0,0 +1,145 @@
+use actix::prelude::*;
+use actix_web::*;
+
+use diesel;
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Text};
+
+use db::{DbExecutor, db_error};
+
+// The down migrations use `ALTER TABLE ... DROP COLUMN`, added in 3.35.
+pub const MIN_SQLITE_VERSION: (u32, u32) = (3, 35);
+
+// Runs consistency checks. `expected_migrations` are the versions found in the
+// migrations directory (e.g. "20261019000006"); any not yet applied are reported.
+pub struct CheckDatabase {
+    pub request_id: String,
+    pub expected_migrations: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct DatabaseCheck {
+    pub name: &'static str,
+    pub ok: bool,
+    pub detail: String,
+}
+
+impl Message for CheckDatabase {
+    type Result = Result<Vec<DatabaseCheck>, Error>;
+}
+
+#[derive(QueryableByName)]
+struct Line {
+    #[sql_type = "Text"]
+    line: String,
+}
+
+#[derive(QueryableByName)]
+struct Count {
+    #[sql_type = "BigInt"]
+    count: i64,
+}
+
+impl Handler<CheckDatabase> for DbExecutor {
+    type Result = Result<Vec<DatabaseCheck>, Error>;
+
+    fn handle(&mut self, msg: CheckDatabase, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+        let mut checks = Vec::new();
+
+        let version = diesel::sql_query("SELECT sqlite_version() AS line")
+            .get_result::<Line>(conn)
+            .map_err(|e| db_error(&msg.request_id, e))?
+            .line;
+        checks.push(DatabaseCheck{
+            name: "sqlite_version",
+            ok: supported(&version),
+            detail: format!("{} (at least {}.{} needed)", version, MIN_SQLITE_VERSION.0, MIN_SQLITE_VERSION.1),
+        });
+
+        let integrity = diesel::sql_query("SELECT integrity_check AS line FROM pragma_integrity_check")
+            .load::<Line>(conn)
+            .map_err(|e| db_error(&msg.request_id, e))?;
+        let integrity_ok = integrity.len() == 1 && integrity[0].line == "ok";
+        checks.push(DatabaseCheck{
+            name: "integrity",
+            ok: integrity_ok,
+            detail: integrity.into_iter().map(|row| row.line).collect::<Vec<_>>().join("; "),
+        });
+
+        let violations = diesel::sql_query("SELECT count(*) AS count FROM pragma_foreign_key_check")
+            .get_result::<Count>(conn)
+            .map_err(|e| db_error(&msg.request_id, e))?
+            .count;
+        checks.push(DatabaseCheck{
+            name: "foreign_keys",
+            ok: violations == 0,
+            detail: format!("{} violations", violations),
+        });
+
+        // Diesel records applied migrations here; a database built some other way has no such table.
+        let applied = diesel::sql_query("SELECT version AS line FROM __diesel_schema_migrations")
+            .load::<Line>(conn)
+            .map(|rows| rows.into_iter().map(|row| row.line).collect::<Vec<_>>());
+        checks.push(match applied {
+            Ok(applied) => {
+                let pending: Vec<&str> = msg.expected_migrations
+                    .iter()
+                    .filter(|version| !applied.contains(version))
+                    .map(String::as_str)
+                    .collect();
+
+                DatabaseCheck{
+                    name: "migrations",
+                    ok: pending.is_empty(),
+                    detail: if pending.is_empty() {
+                        format!("{} applied", applied.len())
+                    } else {
+                        format!("pending: {}", pending.join(", "))
+                    },
+                }
+            },
+            Err(e) => DatabaseCheck{
+                name: "migrations",
+                ok: false,
+                detail: e.to_string(),
+            },
+        });
+
+        // FTS5 compares the index with the users table and fails when they disagree.
+        let search_index = diesel::sql_query("INSERT INTO users_fts(users_fts, rank) VALUES ('integrity-check', 1)")
+            .execute(conn);
+        checks.push(DatabaseCheck{
+            name: "search_index",
+            ok: search_index.is_ok(),
+            detail: match search_index {
+                Ok(_)  => "in sync with users".to_string(),
+                Err(e) => e.to_string(),
+            },
+        });
+
+        Ok(checks)
+    }
+}
+
+fn supported(version: &str) -> bool {
+    let mut parts = version.split('.').map(|part| part.parse::<u32>().unwrap_or(0));
+    let major = parts.next().unwrap_or(0);
+    let minor = parts.next().unwrap_or(0);
+    (major, minor) >= MIN_SQLITE_VERSION
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn supported_compares_major_and_minor() {
+        assert!(supported("3.35.0"));
+        assert!(supported("3.51.2"));
+        assert!(supported("4.0"));
+        assert!(!supported("3.34.1"));
+        assert!(!supported("3.8.11"));
+        assert!(!supported(""));
+    }
+}
@@ -0,0 +1,445 @@
+use chrono::*;
+
+use actix::prelude::*;
+use actix_web::*;
+
+use diesel;
+use diesel::prelude::*;
+
+use models;
+use schema;
+use db::{DbExecutor, db_error, password_error, transaction};
+use db::users_message;
+use events::{Event, Publish};
+use invitations::{ROLE_ADMIN};
+
+// How many invitations the admin page lists.
+const LISTED_INVITATIONS: i64 = 100;
+
+// Earlier invitations to the same address that are still open are revoked,
+// so each address has at most one working link.
+pub struct CreateInvitation {
+    pub request_id: String,
+    pub email: String,
+    pub role: String,
+    pub invited_by: i32,
+    pub token_digest: String,
+    pub expires_at: NaiveDateTime,
+}
+
+pub enum InvitationCreate {
+    Created(Box<models::Invitation>),
+    EmailTaken,
+}
+
+impl Message for CreateInvitation {
+    type Result = Result<InvitationCreate, Error>;
+}
+
+impl Handler<CreateInvitation> for DbExecutor {
+    type Result = Result<InvitationCreate, Error>;
+
+    fn handle(&mut self, msg: CreateInvitation, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        create(conn, &msg).map_err(|e| db_error(&msg.request_id, e))
+    }
+}
+
+fn create(conn: &SqliteConnection, msg: &CreateInvitation) -> QueryResult<InvitationCreate> {
+    use self::schema::invitations::dsl::*;
+    use self::schema::users;
+
+    let now = Utc::now().naive_utc();
+
+    transaction(conn, || {
+        let registered = users::table
+            .filter(users::email.eq(&msg.email))
+            .count()
+            .get_result::<i64>(conn)?;
+        if registered > 0 {
+            return Ok(InvitationCreate::EmailTaken);
+        }
+
+        diesel::update(invitations
+            .filter(email.eq(&msg.email))
+            .filter(accepted_at.is_null())
+            .filter(revoked_at.is_null()))
+            .set((
+                revoked_at.eq(now),
+                updated_at.eq(now),
+            ))
+            .execute(conn)?;
+
+        diesel::insert_into(invitations)
+            .values(models::NewInvitation {
+                email: &msg.email,
+                role: &msg.role,
+                token_digest: &msg.token_digest,
+                invited_by: Some(msg.invited_by),
+                expires_at: msg.expires_at,
+                created_at: now,
+                updated_at: now,
+            })
+            .execute(conn)?;
+
+        invitations
+            .filter(token_digest.eq(&msg.token_digest))
+            .first(conn)
+            .map(|invitation| InvitationCreate::Created(Box::new(invitation)))
+    })
+}
+
+// Newest first.
+pub struct ReadInvitations {
+    pub request_id: String,
+}
+
+impl Message for ReadInvitations {
+    type Result = Result<Vec<models::Invitation>, Error>;
+}
+
+impl Handler<ReadInvitations> for DbExecutor {
+    type Result = Result<Vec<models::Invitation>, Error>;
+
+    fn handle(&mut self, msg: ReadInvitations, _: &mut Self::Context) -> Self::Result {
+        use self::schema::invitations::dsl::*;
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        invitations
+            .order(id.desc())
+            .limit(LISTED_INVITATIONS)
+            .load::<models::Invitation>(conn)
+            .map_err(|e| db_error(&msg.request_id, e))
+    }
+}
+
+// The invitation behind a link, if it can still be used.
+pub struct ReadPendingInvitation {
+    pub request_id: String,
+    pub token_digest: String,
+}
+
+impl Message for ReadPendingInvitation {
+    type Result = Result<Option<models::Invitation>, Error>;
+}
+
+fn pending(conn: &SqliteConnection, digest: &str) -> QueryResult<Option<models::Invitation>> {
+    use self::schema::invitations::dsl::*;
+
+    invitations
+        .filter(token_digest.eq(digest))
+        .filter(accepted_at.is_null())
+        .filter(revoked_at.is_null())
+        .filter(expires_at.gt(Utc::now().naive_utc()))
+        .first(conn)
+        .optional()
+}
+
+impl Handler<ReadPendingInvitation> for DbExecutor {
+    type Result = Result<Option<models::Invitation>, Error>;
+
+    fn handle(&mut self, msg: ReadPendingInvitation, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        pending(conn, &msg.token_digest).map_err(|e| db_error(&msg.request_id, e))
+    }
+}
+
+// Only invitations that are still open can be revoked.
+pub struct RevokeInvitation {
+    pub request_id: String,
+    pub id: i32,
+}
+
+impl Message for RevokeInvitation {
+    type Result = Result<models::Invitation, Error>;
+}
+
+impl Handler<RevokeInvitation> for DbExecutor {
+    type Result = Result<models::Invitation, Error>;
+
+    fn handle(&mut self, msg: RevokeInvitation, _: &mut Self::Context) -> Self::Result {
+        use self::schema::invitations::dsl::*;
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+        let now = Utc::now().naive_utc();
+
+        transaction(conn, || {
+            let revoked = diesel::update(invitations
+                .find(msg.id)
+                .filter(accepted_at.is_null())
+                .filter(revoked_at.is_null()))
+                .set((
+                    revoked_at.eq(now),
+                    updated_at.eq(now),
+                ))
+                .execute(conn)?;
+
+            if revoked == 0 {
+                return Err(diesel::result::Error::NotFound);
+            }
+
+            invitations
+                .find(msg.id)
+                .first(conn)
+        })
+        .map_err(|e| db_error(&msg.request_id, e))
+    }
+}
+
+// Replaces the token of an invitation that has not been used, so the old
+// link stops working, and opens it again until `expires_at`. Other open
+// invitations to the same address are revoked, as in `CreateInvitation`.
+pub struct ResendInvitation {
+    pub request_id: String,
+    pub id: i32,
+    pub token_digest: String,
+    pub expires_at: NaiveDateTime,
+}
+
+impl Message for ResendInvitation {
+    type Result = Result<models::Invitation, Error>;
+}
+
+impl Handler<ResendInvitation> for DbExecutor {
+    type Result = Result<models::Invitation, Error>;
+
+    fn handle(&mut self, msg: ResendInvitation, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        resend(conn, &msg).map_err(|e| db_error(&msg.request_id, e))
+    }
+}
+
+fn resend(conn: &SqliteConnection, msg: &ResendInvitation) -> QueryResult<models::Invitation> {
+    use self::schema::invitations::dsl::*;
+
+    let now = Utc::now().naive_utc();
+
+    transaction(conn, || {
+        let resent = diesel::update(invitations
+            .find(msg.id)
+            .filter(accepted_at.is_null()))
+            .set((
+                token_digest.eq(&msg.token_digest),
+                expires_at.eq(msg.expires_at),
+                revoked_at.eq(None::<NaiveDateTime>),
+                updated_at.eq(now),
+            ))
+            .execute(conn)?;
+
+        if resent == 0 {
+            return Err(diesel::result::Error::NotFound);
+        }
+
+        let invitation: models::Invitation = invitations
+            .find(msg.id)
+            .first(conn)?;
+
+        diesel::update(invitations
+            .filter(email.eq(&invitation.email))
+            .filter(id.ne(invitation.id))
+            .filter(accepted_at.is_null())
+            .filter(revoked_at.is_null()))
+            .set((
+                revoked_at.eq(now),
+                updated_at.eq(now),
+            ))
+            .execute(conn)?;
+
+        Ok(invitation)
+    })
+}
+
+// Signs up the invitee: the user gets the invited address and role, and the
+// invitation is used up in the same transaction.
+pub struct AcceptInvitation {
+    pub request_id: String,
+    pub token_digest: String,
+    pub name: String,
+    pub password: String,
+}
+
+pub enum Acceptance {
+    Accepted(Box<models::User>),
+    // Unknown, used, revoked or expired.
+    Invalid,
+    EmailTaken,
+}
+
+impl Message for AcceptInvitation {
+    type Result = Result<Acceptance, Error>;
+}
+
+impl Handler<AcceptInvitation> for DbExecutor {
+    type Result = Result<Acceptance, Error>;
+
+    fn handle(&mut self, msg: AcceptInvitation, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        // Checked before hashing, which is slow; checked again in `accept`.
+        if pending(conn, &msg.token_digest).map_err(|e| db_error(&msg.request_id, e))?.is_none() {
+            return Ok(Acceptance::Invalid);
+        }
+
+        let digest = self.1
+            .current()
+            .password_policy
+            .hash(&msg.password)
+            .map_err(|e| password_error(&msg.request_id, e))?;
+
+        let acceptance = accept(conn, &msg, &digest).map_err(|e| db_error(&msg.request_id, e))?;
+
+        if let Acceptance::Accepted(ref user) = acceptance {
+            self.2.do_send(Publish(Event::UserCreated{user: (&**user).into()}));
+        }
+
+        Ok(acceptance)
+    }
+}
+
+// Uses up the invitation behind `msg.token_digest` for a new user, if it is
+// still pending; the check and the insert share one transaction.
+fn accept(conn: &SqliteConnection, msg: &AcceptInvitation, password_digest: &str) -> QueryResult<Acceptance> {
+    use self::schema::invitations::dsl::*;
+    use self::schema::users;
+
+    transaction(conn, || {
+        let invitation = match pending(conn, &msg.token_digest)? {
+            Some(invitation) => invitation,
+            None             => return Ok(Acceptance::Invalid),
+        };
+
+        let registered = users::table
+            .filter(users::email.eq(&invitation.email))
+            .count()
+            .get_result::<i64>(conn)?;
+        if registered > 0 {
+            return Ok(Acceptance::EmailTaken);
+        }
+
+        let user = users_message::insert_user(conn, &msg.name, &invitation.email, password_digest, invitation.role == ROLE_ADMIN)?;
+
+        let now = Utc::now().naive_utc();
+        diesel::update(invitations.find(invitation.id))
+            .set((
+                accepted_at.eq(now),
+                user_id.eq(user.id),
+                updated_at.eq(now),
+            ))
+            .execute(conn)?;
+
+        Ok(Acceptance::Accepted(Box::new(user)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use db::tests::{migrated};
+    use invitations::{self, Status, ROLE_ADMIN, ROLE_MEMBER};
+
+    fn invite(conn: &SqliteConnection, address: &str) -> (models::Invitation, String) {
+        invite_as(conn, address, ROLE_MEMBER)
+    }
+
+    fn invite_as(conn: &SqliteConnection, address: &str, role: &str) -> (models::Invitation, String) {
+        let token = invitations::create_token();
+        let created = create(conn, &CreateInvitation{
+            request_id: String::new(),
+            email: address.to_string(),
+            role: role.to_string(),
+            invited_by: 1,
+            token_digest: invitations::token_digest(&token),
+            expires_at: Utc::now().naive_utc() + Duration::days(1),
+        }).unwrap();
+
+        match created {
+            InvitationCreate::Created(invitation) => (*invitation, token),
+            InvitationCreate::EmailTaken          => panic!("{} is registered", address),
+        }
+    }
+
+    fn status(conn: &SqliteConnection, invitation: &models::Invitation) -> Status {
+        use self::schema::invitations::dsl::*;
+
+        let invitation = invitations.find(invitation.id).first(conn).unwrap();
+        Status::of(&invitation, Utc::now().naive_utc())
+    }
+
+    #[test]
+    fn each_address_keeps_one_open_invitation() {
+        let (path, conn) = migrated(None);
+
+        let (first, _) = invite(&conn, "bob@example.com");
+        let (second, _) = invite(&conn, "bob@example.com");
+        assert_eq!(status(&conn, &first), Status::Revoked);
+        assert_eq!(status(&conn, &second), Status::Pending);
+
+        resend(&conn, &ResendInvitation{
+            request_id: String::new(),
+            id: first.id,
+            token_digest: invitations::token_digest(&invitations::create_token()),
+            expires_at: Utc::now().naive_utc() + Duration::days(1),
+        }).unwrap();
+        assert_eq!(status(&conn, &first), Status::Pending);
+        assert_eq!(status(&conn, &second), Status::Revoked);
+
+        let _ = fs::remove_file(&path);
+    }
+
+    fn sign_up(conn: &SqliteConnection, token: &str) -> Acceptance {
+        accept(conn, &AcceptInvitation{
+            request_id: String::new(),
+            token_digest: invitations::token_digest(token),
+            name: "bob".to_string(),
+            password: "password2".to_string(),
+        }, "digest").unwrap()
+    }
+
+    #[test]
+    fn only_a_pending_invitation_signs_up() {
+        use self::schema::invitations::dsl::{expires_at, revoked_at};
+
+        let (path, conn) = migrated(None);
+        let rows = || schema::invitations::table;
+
+        assert!(match sign_up(&conn, &invitations::create_token()) { Acceptance::Invalid => true, _ => false });
+
+        let (revoked, revoked_token) = invite(&conn, "revoked@example.com");
+        diesel::update(rows().find(revoked.id)).set(revoked_at.eq(Some(Utc::now().naive_utc()))).execute(&conn).unwrap();
+        assert!(match sign_up(&conn, &revoked_token) { Acceptance::Invalid => true, _ => false });
+
+        let (expired, expired_token) = invite(&conn, "expired@example.com");
+        diesel::update(rows().find(expired.id)).set(expires_at.eq(Utc::now().naive_utc() - Duration::seconds(1))).execute(&conn).unwrap();
+        assert!(match sign_up(&conn, &expired_token) { Acceptance::Invalid => true, _ => false });
+
+        let (invitation, token) = invite_as(&conn, "bob@example.com", ROLE_ADMIN);
+        let user = match sign_up(&conn, &token) {
+            Acceptance::Accepted(user) => user,
+            _                          => panic!("a pending invitation was refused"),
+        };
+        assert_eq!(user.email, "bob@example.com");
+        assert!(user.admin);
+
+        let accepted: models::Invitation = rows().find(invitation.id).first(&conn).unwrap();
+        assert_eq!(accepted.user_id, Some(user.id));
+        assert_eq!(Status::of(&accepted, Utc::now().naive_utc()), Status::Used);
+
+        // Used up, so the same link cannot sign up a second account.
+        assert!(match sign_up(&conn, &token) { Acceptance::Invalid => true, _ => false });
+
+        let (member, member_token) = invite(&conn, "carol@example.com");
+        match sign_up(&conn, &member_token) {
+            Acceptance::Accepted(user) => assert!(!user.admin && user.email == member.email),
+            _                          => panic!("a pending invitation was refused"),
+        }
+
+        let _ = fs::remove_file(&path);
+    }
+}
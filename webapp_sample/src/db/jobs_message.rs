@@ -0,0 +1,337 @@
+use chrono::*;
+
+use actix::prelude::*;
+use actix_web::*;
+
+use diesel;
+use diesel::prelude::*;
+
+use models;
+use schema;
+use db::{DbExecutor, db_error, transaction};
+use jobs::{MAX_ATTEMPTS, Task, backoff};
+
+pub const STATUS_QUEUED: &str = "queued";
+pub const STATUS_RUNNING: &str = "running";
+pub const STATUS_DONE: &str = "done";
+pub const STATUS_DEAD: &str = "dead";
+
+pub const STATUSES: [&str; 4] = [STATUS_QUEUED, STATUS_RUNNING, STATUS_DONE, STATUS_DEAD];
+
+// How many jobs the admin page lists.
+const LISTED_JOBS: i64 = 50;
+
+// Adds a job that becomes due at `due_at`. Handlers call this inside the
+// transaction that makes the job necessary, so the two are stored together.
+pub fn enqueue(conn: &SqliteConnection, task: &Task, due_at: NaiveDateTime) -> QueryResult<models::Job> {
+    use self::schema::jobs::dsl::*;
+
+    let task_payload = serde_json::to_string(task).expect("failed to serialize task");
+    let now = Utc::now().naive_utc();
+
+    diesel::insert_into(jobs)
+        .values(models::NewJob {
+            kind: task.kind(),
+            payload: &task_payload,
+            status: STATUS_QUEUED,
+            max_attempts: MAX_ATTEMPTS,
+            run_at: due_at,
+            created_at: now,
+            updated_at: now,
+        })
+        .execute(conn)?;
+
+    jobs
+        .order(id.desc())
+        .first(conn)
+}
+
+pub struct EnqueueJob {
+    pub request_id: String,
+    pub task: Task,
+    pub run_at: NaiveDateTime,
+}
+
+impl Message for EnqueueJob {
+    type Result = Result<models::Job, Error>;
+}
+
+impl Handler<EnqueueJob> for DbExecutor {
+    type Result = Result<models::Job, Error>;
+
+    fn handle(&mut self, msg: EnqueueJob, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        transaction(conn, || enqueue(conn, &msg.task, msg.run_at))
+        .map_err(|e| db_error(&msg.request_id, e))
+    }
+}
+
+// Marks up to `limit` due jobs as running, oldest first, and counts the attempt.
+pub struct ClaimJobs {
+    pub request_id: String,
+    pub limit: i64,
+    pub now: NaiveDateTime,
+}
+
+impl Message for ClaimJobs {
+    type Result = Result<Vec<models::Job>, Error>;
+}
+
+impl Handler<ClaimJobs> for DbExecutor {
+    type Result = Result<Vec<models::Job>, Error>;
+
+    fn handle(&mut self, msg: ClaimJobs, _: &mut Self::Context) -> Self::Result {
+        use self::schema::jobs::dsl::*;
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        transaction(conn, || {
+            let ids: Vec<i32> = jobs
+                .select(id)
+                .filter(status.eq(STATUS_QUEUED))
+                .filter(run_at.le(msg.now))
+                .order((run_at.asc(), id.asc()))
+                .limit(msg.limit)
+                .load(conn)?;
+
+            diesel::update(jobs
+                .filter(id.eq_any(&ids))
+                .filter(status.eq(STATUS_QUEUED)))
+                .set((
+                    status.eq(STATUS_RUNNING),
+                    attempts.eq(attempts + 1),
+                    locked_at.eq(Some(msg.now)),
+                    updated_at.eq(msg.now),
+                ))
+                .execute(conn)?;
+
+            jobs
+                .filter(id.eq_any(&ids))
+                .order((run_at.asc(), id.asc()))
+                .load::<models::Job>(conn)
+        })
+        .map_err(|e| db_error(&msg.request_id, e))
+    }
+}
+
+// Records the outcome of a run. A failed job is queued again after a backoff,
+// or dead-lettered once it has used up its attempts.
+pub struct FinishJob {
+    pub request_id: String,
+    pub id: i32,
+    pub error: Option<String>,
+    pub now: NaiveDateTime,
+}
+
+impl Message for FinishJob {
+    type Result = Result<models::Job, Error>;
+}
+
+impl Handler<FinishJob> for DbExecutor {
+    type Result = Result<models::Job, Error>;
+
+    fn handle(&mut self, msg: FinishJob, _: &mut Self::Context) -> Self::Result {
+        use self::schema::jobs::dsl::*;
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        transaction(conn, || {
+            let job = jobs
+                .find(msg.id)
+                .first::<models::Job>(conn)?;
+
+            let (next_status, next_run_at) = match msg.error {
+                None                                        => (STATUS_DONE, job.run_at),
+                Some(_) if job.attempts >= job.max_attempts => (STATUS_DEAD, job.run_at),
+                Some(_)                                     => (STATUS_QUEUED, msg.now + backoff(job.attempts)),
+            };
+
+            diesel::update(jobs
+                .find(msg.id))
+                .set((
+                    status.eq(next_status),
+                    run_at.eq(next_run_at),
+                    locked_at.eq(None::<NaiveDateTime>),
+                    last_error.eq(&msg.error),
+                    updated_at.eq(msg.now),
+                ))
+                .execute(conn)?;
+
+            jobs
+                .find(msg.id)
+                .first(conn)
+        })
+        .map_err(|e| db_error(&msg.request_id, e))
+    }
+}
+
+// Queues jobs again that have been running since before `locked_before`; the
+// process that claimed them most likely stopped before finishing them. Newer
+// ones are left alone, since a process being replaced may still be draining
+// them while the next one starts.
+pub fn release(conn: &SqliteConnection, locked_before: NaiveDateTime) -> QueryResult<usize> {
+    use self::schema::jobs::dsl::*;
+
+    diesel::update(jobs
+        .filter(status.eq(STATUS_RUNNING))
+        .filter(locked_at.lt(locked_before).or(locked_at.is_null())))
+        .set((
+            status.eq(STATUS_QUEUED),
+            locked_at.eq(None::<NaiveDateTime>),
+        ))
+        .execute(conn)
+}
+
+pub struct ReleaseJobs {
+    pub request_id: String,
+    pub locked_before: NaiveDateTime,
+}
+
+impl Message for ReleaseJobs {
+    type Result = Result<usize, Error>;
+}
+
+impl Handler<ReleaseJobs> for DbExecutor {
+    type Result = Result<usize, Error>;
+
+    fn handle(&mut self, msg: ReleaseJobs, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        release(conn, msg.locked_before).map_err(|e| db_error(&msg.request_id, e))
+    }
+}
+
+#[derive(Serialize)]
+pub struct JobCount {
+    pub status: &'static str,
+    pub count: i64,
+}
+
+#[derive(Serialize)]
+pub struct JobList {
+    pub counts: Vec<JobCount>,
+    pub jobs: Vec<models::Job>,
+}
+
+// The number of jobs in each status, and the latest jobs, in `status` if given.
+pub struct ReadJobs {
+    pub request_id: String,
+    pub status: Option<String>,
+}
+
+impl Message for ReadJobs {
+    type Result = Result<JobList, Error>;
+}
+
+impl Handler<ReadJobs> for DbExecutor {
+    type Result = Result<JobList, Error>;
+
+    fn handle(&mut self, msg: ReadJobs, _: &mut Self::Context) -> Self::Result {
+        use self::schema::jobs::dsl::*;
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        let counts = STATUSES
+            .iter()
+            .map(|s| {
+                jobs
+                    .filter(status.eq(s))
+                    .count()
+                    .get_result(conn)
+                    .map(|count| JobCount{status: s, count})
+            })
+            .collect::<QueryResult<Vec<_>>>()
+            .map_err(|e| db_error(&msg.request_id, e))?;
+
+        let mut query = jobs
+            .order(id.desc())
+            .limit(LISTED_JOBS)
+            .into_boxed();
+        if let Some(ref s) = msg.status {
+            query = query.filter(status.eq(s));
+        }
+
+        let select_jobs = query
+            .load::<models::Job>(conn)
+            .map_err(|e| db_error(&msg.request_id, e))?;
+
+        Ok(JobList{counts, jobs: select_jobs})
+    }
+}
+
+// Gives a dead job a fresh set of attempts, starting now.
+pub struct RetryJob {
+    pub request_id: String,
+    pub id: i32,
+}
+
+impl Message for RetryJob {
+    type Result = Result<models::Job, Error>;
+}
+
+impl Handler<RetryJob> for DbExecutor {
+    type Result = Result<models::Job, Error>;
+
+    fn handle(&mut self, msg: RetryJob, _: &mut Self::Context) -> Self::Result {
+        use self::schema::jobs::dsl::*;
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+        let now = Utc::now().naive_utc();
+
+        transaction(conn, || {
+            let retried = diesel::update(jobs
+                .find(msg.id)
+                .filter(status.eq(STATUS_DEAD)))
+                .set((
+                    status.eq(STATUS_QUEUED),
+                    attempts.eq(0),
+                    run_at.eq(now),
+                    updated_at.eq(now),
+                ))
+                .execute(conn)?;
+
+            if retried == 0 {
+                return Err(diesel::result::Error::NotFound);
+            }
+
+            jobs
+                .find(msg.id)
+                .first(conn)
+        })
+        .map_err(|e| db_error(&msg.request_id, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use db::tests::{migrated};
+
+    #[test]
+    fn release_leaves_jobs_within_their_lease() {
+        use self::schema::jobs::dsl::{jobs, id, status, locked_at};
+
+        let (path, conn) = migrated(None);
+        let now = Utc::now().naive_utc();
+        let task = Task::DeleteUserFiles{user_uuid: "u-1".to_string()};
+
+        let stale = enqueue(&conn, &task, now).unwrap();
+        let fresh = enqueue(&conn, &task, now).unwrap();
+        for &(job, claimed_at) in &[(stale.id, now - Duration::hours(2)), (fresh.id, now - Duration::minutes(1))] {
+            diesel::update(jobs.find(job))
+                .set((status.eq(STATUS_RUNNING), locked_at.eq(Some(claimed_at))))
+                .execute(&conn)
+                .unwrap();
+        }
+
+        assert_eq!(release(&conn, now - Duration::hours(1)).unwrap(), 1);
+        let statuses: Vec<(i32, String)> = jobs.select((id, status)).order(id).load(&conn).unwrap();
+        assert_eq!(statuses, vec![(stale.id, STATUS_QUEUED.to_string()), (fresh.id, STATUS_RUNNING.to_string())]);
+
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,176 @@
+use chrono::*;
+
+use actix::prelude::*;
+use actix_web::*;
+
+use diesel;
+use diesel::prelude::*;
+
+use models;
+use schema;
+use db::{DbExecutor, db_error, transaction};
+
+pub struct CreateMicropost {
+    pub request_id: String,
+    pub user_id: i32,
+    pub content: String,
+}
+
+impl Message for CreateMicropost {
+    type Result = Result<models::Micropost, Error>;
+}
+
+impl Handler<CreateMicropost> for DbExecutor {
+    type Result = Result<models::Micropost, Error>;
+
+    fn handle(&mut self, msg: CreateMicropost, _: &mut Self::Context) -> Self::Result {
+        use self::schema::microposts::dsl::*;
+
+        let now = Utc::now().naive_utc();
+
+        let new_micropost = models::NewMicropost {
+            user_id: msg.user_id,
+            content: &msg.content,
+            created_at: now,
+            updated_at: now,
+        };
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        let insert_micropost = transaction(conn, || {
+            diesel::insert_into(microposts)
+                .values(&new_micropost)
+                .execute(conn)?;
+
+            microposts
+                .filter(user_id.eq(msg.user_id))
+                .order(id.desc())
+                .first(conn)
+        })
+        .map_err(|e| db_error(&msg.request_id, e))?;
+
+        Ok(insert_micropost)
+    }
+}
+
+pub struct DeleteMicropost {
+    pub request_id: String,
+    pub id: i32,
+    pub user_id: i32,
+}
+
+impl Message for DeleteMicropost {
+    type Result = Result<models::Micropost, Error>;
+}
+
+impl Handler<DeleteMicropost> for DbExecutor {
+    type Result = Result<models::Micropost, Error>;
+
+    fn handle(&mut self, msg: DeleteMicropost, _: &mut Self::Context) -> Self::Result {
+        use self::schema::microposts::dsl::*;
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        // Filtering by owner makes someone else's micropost look like a missing one.
+        let delete_micropost = microposts
+            .filter(id.eq(msg.id))
+            .filter(user_id.eq(msg.user_id))
+            .first(conn)
+            .map_err(|e| db_error(&msg.request_id, e))?;
+
+        diesel::delete(microposts
+            .find(msg.id))
+            .execute(conn)
+            .map_err(|e| db_error(&msg.request_id, e))?;
+
+        Ok(delete_micropost)
+    }
+}
+
+pub struct ReadMicroposts {
+    pub request_id: String,
+    pub user_id: i32,
+    pub page: i64,
+    pub per_page: i64,
+}
+
+impl Message for ReadMicroposts {
+    type Result = Result<(Vec<models::FeedItem>, i64), Error>;
+}
+
+impl Handler<ReadMicroposts> for DbExecutor {
+    type Result = Result<(Vec<models::FeedItem>, i64), Error>;
+
+    fn handle(&mut self, msg: ReadMicroposts, _: &mut Self::Context) -> Self::Result {
+        use self::schema::{microposts, users};
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        let select_microposts = microposts::table
+            .inner_join(users::table)
+            .filter(microposts::user_id.eq(msg.user_id))
+            .order((microposts::created_at.desc(), microposts::id.desc()))
+            .limit(msg.per_page)
+            .offset((msg.page - 1) * msg.per_page)
+            .load::<(models::Micropost, models::User)>(conn)
+            .map_err(|e| db_error(&msg.request_id, e))?;
+
+        let total = microposts::table
+            .filter(microposts::user_id.eq(msg.user_id))
+            .count()
+            .get_result(conn)
+            .map_err(|e| db_error(&msg.request_id, e))?;
+
+        Ok((feed_items(select_microposts), total))
+    }
+}
+
+pub struct ReadFeed {
+    pub request_id: String,
+    pub user_id: i32,
+    pub page: i64,
+    pub per_page: i64,
+}
+
+impl Message for ReadFeed {
+    type Result = Result<(Vec<models::FeedItem>, i64), Error>;
+}
+
+// The home feed: the user's own microposts and those of everyone they follow.
+impl Handler<ReadFeed> for DbExecutor {
+    type Result = Result<(Vec<models::FeedItem>, i64), Error>;
+
+    fn handle(&mut self, msg: ReadFeed, _: &mut Self::Context) -> Self::Result {
+        use self::schema::{microposts, relationships, users};
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        let followed_ids = relationships::table
+            .select(relationships::followed_id)
+            .filter(relationships::follower_id.eq(msg.user_id));
+
+        let select_microposts = microposts::table
+            .inner_join(users::table)
+            .filter(microposts::user_id.eq(msg.user_id).or(microposts::user_id.eq_any(followed_ids)))
+            .order((microposts::created_at.desc(), microposts::id.desc()))
+            .limit(msg.per_page)
+            .offset((msg.page - 1) * msg.per_page)
+            .load::<(models::Micropost, models::User)>(conn)
+            .map_err(|e| db_error(&msg.request_id, e))?;
+
+        let total = microposts::table
+            .filter(microposts::user_id.eq(msg.user_id).or(microposts::user_id.eq_any(followed_ids)))
+            .count()
+            .get_result(conn)
+            .map_err(|e| db_error(&msg.request_id, e))?;
+
+        Ok((feed_items(select_microposts), total))
+    }
+}
+
+fn feed_items(rows: Vec<(models::Micropost, models::User)>) -> Vec<models::FeedItem> {
+    rows
+        .into_iter()
+        .map(|(micropost, user)| models::FeedItem { micropost, user })
+        .collect()
+}
@@ -1,15 +1,256 @@
+pub mod data_exports_message;
+pub mod database_message;
+pub mod invitations_message;
+pub mod jobs_message;
+pub mod microposts_message;
+pub mod relationships_message;
+pub mod sessions_message;
 pub mod users_message;
 
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
 use actix::prelude::*;
+use actix_web::{error, Error};
 
+use diesel;
+use diesel::connection::{SimpleConnection};
 use diesel::prelude::*;
-use r2d2::{Pool};
+use diesel::result::{DatabaseErrorKind};
+use r2d2::{CustomizeConnection, Pool};
 use r2d2_diesel::{ConnectionManager};
 
+use events::{Broadcaster};
+use lifecycle::{Drain};
+use passwords::{PasswordError};
+use sessions::cache::{SessionCache};
+use settings::{SharedSettings, env_or};
+
+// Extra attempts `transaction` makes when SQLite still reports the database
+// locked after the busy timeout.
+const BUSY_RETRIES: u32 = 3;
+
+// The cache is the one `Authenticate` reads; handlers that change a user's
+// session digest forget that user's entries.
 pub struct DbExecutor(
-    pub Pool<ConnectionManager<SqliteConnection>>
+    pub Pool<ConnectionManager<SqliteConnection>>,
+    pub SharedSettings,
+    pub Addr<Broadcaster>,
+    pub Arc<SessionCache>,
 );
 
 impl Actor for DbExecutor {
     type Context = SyncContext<Self>;
 }
+
+impl Handler<Drain> for DbExecutor {
+    type Result = ();
+
+    fn handle(&mut self, msg: Drain, _: &mut Self::Context) -> Self::Result {
+        msg.0.wait();
+    }
+}
+
+// Sets up every pooled connection: WAL lets readers go on while one connection
+// writes, the busy timeout makes writers queue for the lock instead of
+// failing at once with "database is locked", and foreign keys make SQLite
+// enforce the REFERENCES and ON DELETE clauses, which it ignores by default.
+#[derive(Debug)]
+pub struct SqlitePragmas {
+    pub busy_timeout: Duration,
+}
+
+impl SqlitePragmas {
+    // SQLITE_BUSY_TIMEOUT_MS (default 5000).
+    pub fn from_env() -> Self {
+        SqlitePragmas {
+            busy_timeout: Duration::from_millis(env_or("SQLITE_BUSY_TIMEOUT_MS", 5000)),
+        }
+    }
+}
+
+impl CustomizeConnection<SqliteConnection, r2d2_diesel::Error> for SqlitePragmas {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), r2d2_diesel::Error> {
+        let millis = self.busy_timeout.as_secs() * 1000 + u64::from(self.busy_timeout.subsec_millis());
+
+        conn
+            .batch_execute(&format!("PRAGMA busy_timeout = {}; PRAGMA journal_mode = WAL; PRAGMA foreign_keys = ON;", millis))
+            .map_err(r2d2_diesel::Error::QueryError)
+    }
+}
+
+pub fn is_busy(e: &diesel::result::Error) -> bool {
+    match *e {
+        diesel::result::Error::DatabaseError(DatabaseErrorKind::__Unknown, ref info) => info.message().contains("database is locked"),
+        _                                                                           => false,
+    }
+}
+
+// Runs `f` in an immediate transaction, which takes the write lock up front,
+// so nothing can change the rows between what `f` reads and what it writes.
+// When the lock stays taken past the busy timeout, `f` is run again.
+pub fn transaction<T, F>(conn: &SqliteConnection, f: F) -> QueryResult<T>
+    where F: Fn() -> QueryResult<T>
+{
+    let mut retries = 0;
+
+    loop {
+        match conn.immediate_transaction(&f) {
+            Err(ref e) if is_busy(e) && retries < BUSY_RETRIES => {
+                retries += 1;
+                thread::sleep(Duration::from_millis(50 * u64::from(retries)));
+            },
+            result => return result,
+        }
+    }
+}
+
+pub fn db_error(request_id: &str, e: diesel::result::Error) -> Error {
+    match e {
+        diesel::result::Error::NotFound => error::ErrorNotFound("NotFound"),
+        _ => {
+            error!("{}", json!({
+                "event":      "db_error",
+                "request_id": request_id,
+                "error":      e.to_string(),
+            }));
+            error::ErrorInternalServerError("InternalServerError")
+        },
+    }
+}
+
+pub fn password_error(request_id: &str, e: PasswordError) -> Error {
+    error!("{}", json!({
+        "event":      "password_error",
+        "request_id": request_id,
+        "error":      e.to_string(),
+    }));
+    error::ErrorInternalServerError("InternalServerError")
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use uuid::Uuid;
+
+    // A database in the temp directory with every migration older than
+    // `until` applied, or all of them.
+    pub fn migrated(until: Option<&str>) -> (PathBuf, SqliteConnection) {
+        let path = env::temp_dir().join(format!("webapp_sample_{}.db", Uuid::new_v4()));
+        let conn = SqliteConnection::establish(path.to_str().unwrap()).unwrap();
+
+        let mut dirs: Vec<PathBuf> = fs::read_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("migrations"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.is_dir())
+            .collect();
+        dirs.sort();
+        for dir in dirs.iter().filter(|dir| until.map_or(true, |until| dir.file_name().unwrap().to_str().unwrap() < until)) {
+            conn.batch_execute(&fs::read_to_string(dir.join("up.sql")).unwrap()).unwrap();
+        }
+        (path, conn)
+    }
+
+    fn count(conn: &SqliteConnection, table: &str) -> i64 {
+        #[derive(QueryableByName)]
+        struct Count {
+            #[sql_type = "diesel::sql_types::BigInt"]
+            n: i64,
+        }
+        diesel::sql_query(format!("SELECT COUNT(*) AS n FROM {}", table)).get_result::<Count>(conn).unwrap().n
+    }
+
+    #[test]
+    fn transaction_retries_while_another_connection_writes() {
+        let path = env::temp_dir().join(format!("webapp_sample_{}.db", Uuid::new_v4()));
+        let url = path.to_str().unwrap().to_string();
+
+        let writer = SqliteConnection::establish(&url).unwrap();
+        writer.batch_execute("CREATE TABLE t (x INTEGER); BEGIN IMMEDIATE; INSERT INTO t VALUES (1);").unwrap();
+
+        let conn = SqliteConnection::establish(&url).unwrap();
+        let insert = || conn.batch_execute("INSERT INTO t VALUES (2);");
+        assert!(is_busy(&conn.immediate_transaction(insert).unwrap_err()));
+
+        let committer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            writer.batch_execute("COMMIT;").unwrap();
+        });
+        assert!(transaction(&conn, insert).is_ok());
+        committer.join().unwrap();
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn pooled_connections_enforce_foreign_keys() {
+        let (path, mut conn) = migrated(None);
+        SqlitePragmas{busy_timeout: Duration::from_millis(100)}.on_acquire(&mut conn).unwrap();
+
+        conn.batch_execute("
+            INSERT INTO users (id, uuid, name, email, password_digest, created_at, updated_at) VALUES (1, 'u-1', 'alice', 'alice@example.com', 'x', '2026-10-19 00:00:00', '2026-10-19 00:00:00');
+            INSERT INTO microposts (user_id, content, created_at, updated_at) VALUES (1, 'hello', '2026-10-19 00:00:00', '2026-10-19 00:00:00');
+            INSERT INTO sessions (id, user_id, data, created_at, last_seen_at) VALUES ('s-1', 1, '{}', '2026-10-19 00:00:00', '2026-10-19 00:00:00');
+            INSERT INTO invitations (email, role, token_digest, user_id, expires_at, created_at, updated_at) VALUES ('alice@example.com', 'member', 'd-1', 1, '2026-10-20 00:00:00', '2026-10-19 00:00:00', '2026-10-19 00:00:00');
+        ").unwrap();
+
+        assert!(conn.batch_execute("INSERT INTO microposts (user_id, content, created_at, updated_at) VALUES (2, 'orphan', '2026-10-19 00:00:00', '2026-10-19 00:00:00');").is_err());
+
+        conn.batch_execute("DELETE FROM users WHERE id = 1;").unwrap();
+        assert_eq!(count(&conn, "microposts"), 0);
+        assert_eq!(count(&conn, "sessions"), 0);
+        assert_eq!(count(&conn, "invitations WHERE user_id IS NULL"), 1);
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn utc_migration_converts_in_place_and_keeps_rows() {
+        let (path, conn) = migrated(Some("2026-10-19-000010"));
+        let migration = Path::new(env!("CARGO_MANIFEST_DIR")).join("migrations/2026-10-19-000010_store_timestamps_in_utc");
+        let at = "'2026-10-19 18:30:00.250'";
+
+        conn.batch_execute(&format!("
+            PRAGMA foreign_keys = ON;
+            INSERT INTO users (id, uuid, name, email, password_digest, created_at, updated_at) VALUES (1, 'u-1', 'alice', 'alice@example.com', 'x', {at}, {at});
+            INSERT INTO users (id, uuid, name, email, password_digest, created_at, updated_at) VALUES (2, 'u-2', 'bob', 'bob@example.com', 'x', {at}, {at});
+            INSERT INTO microposts (user_id, content, created_at, updated_at) VALUES (1, 'hello', {at}, {at});
+            INSERT INTO relationships (follower_id, followed_id, created_at, updated_at) VALUES (1, 2, {at}, {at});
+            INSERT INTO sessions (id, user_id, data, created_at, last_seen_at) VALUES ('s-1', 1, '{{}}', {at}, {at});
+            INSERT INTO data_exports (user_id, created_at) VALUES (1, {at});
+            INSERT INTO jobs (kind, payload, max_attempts, run_at, created_at, updated_at) VALUES ('k', '{{}}', 1, {at}, {at}, {at});
+        ", at = at)).unwrap();
+
+        let tables = ["users", "microposts", "relationships", "sessions", "data_exports", "jobs"];
+        let before: Vec<i64> = tables.iter().map(|table| count(&conn, table)).collect();
+        assert_eq!(before, vec![2, 1, 1, 1, 1, 1]);
+
+        #[derive(QueryableByName)]
+        struct Times {
+            #[sql_type = "diesel::sql_types::Text"]
+            created_at: String,
+            #[sql_type = "diesel::sql_types::Text"]
+            expected: String,
+        }
+        let times = || diesel::sql_query(format!("SELECT created_at, DATETIME({at}, 'utc') || '.250' AS expected FROM microposts", at = at))
+            .get_result::<Times>(&conn)
+            .unwrap();
+
+        conn.batch_execute(&fs::read_to_string(migration.join("up.sql")).unwrap()).unwrap();
+        let after: Vec<i64> = tables.iter().map(|table| count(&conn, table)).collect();
+        assert_eq!(after, before);
+        let converted = times();
+        assert_eq!(converted.created_at, converted.expected);
+
+        conn.batch_execute(&fs::read_to_string(migration.join("down.sql")).unwrap()).unwrap();
+        let after: Vec<i64> = tables.iter().map(|table| count(&conn, table)).collect();
+        assert_eq!(after, before);
+        assert_eq!(times().created_at, "2026-10-19 18:30:00.250");
+
+        let _ = fs::remove_file(&path);
+    }
+}
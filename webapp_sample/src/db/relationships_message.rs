@@ -0,0 +1,218 @@
+use chrono::*;
+
+use actix::prelude::*;
+use actix_web::*;
+
+use diesel;
+use diesel::prelude::*;
+
+use models;
+use schema;
+use db::{DbExecutor, db_error};
+
+pub struct Follow {
+    pub request_id: String,
+    pub follower_id: i32,
+    pub followed_id: i32,
+}
+
+impl Message for Follow {
+    type Result = Result<(), Error>;
+}
+
+impl Handler<Follow> for DbExecutor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: Follow, _: &mut Self::Context) -> Self::Result {
+        use self::schema::relationships::dsl::*;
+
+        if msg.follower_id == msg.followed_id {
+            return Err(error::ErrorBadRequest("BadRequest"));
+        }
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        schema::users::table
+            .find(msg.followed_id)
+            .select(schema::users::id)
+            .first::<i32>(conn)
+            .map_err(|e| db_error(&msg.request_id, e))?;
+
+        let now = Utc::now().naive_utc();
+
+        let new_relationship = models::NewRelationship {
+            follower_id: msg.follower_id,
+            followed_id: msg.followed_id,
+            created_at: now,
+            updated_at: now,
+        };
+
+        // Following twice is not an error; the unique index keeps a single row.
+        diesel::insert_or_ignore_into(relationships)
+            .values(&new_relationship)
+            .execute(conn)
+            .map_err(|e| db_error(&msg.request_id, e))?;
+
+        Ok(())
+    }
+}
+
+pub struct Unfollow {
+    pub request_id: String,
+    pub follower_id: i32,
+    pub followed_id: i32,
+}
+
+impl Message for Unfollow {
+    type Result = Result<(), Error>;
+}
+
+impl Handler<Unfollow> for DbExecutor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: Unfollow, _: &mut Self::Context) -> Self::Result {
+        use self::schema::relationships::dsl::*;
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        diesel::delete(relationships
+            .filter(follower_id.eq(msg.follower_id))
+            .filter(followed_id.eq(msg.followed_id)))
+            .execute(conn)
+            .map_err(|e| db_error(&msg.request_id, e))?;
+
+        Ok(())
+    }
+}
+
+pub struct ReadFollowing {
+    pub request_id: String,
+    pub user_id: i32,
+    pub page: i64,
+    pub per_page: i64,
+}
+
+impl Message for ReadFollowing {
+    type Result = Result<(Vec<models::User>, i64), Error>;
+}
+
+impl Handler<ReadFollowing> for DbExecutor {
+    type Result = Result<(Vec<models::User>, i64), Error>;
+
+    fn handle(&mut self, msg: ReadFollowing, _: &mut Self::Context) -> Self::Result {
+        use self::schema::{relationships, users};
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        let followed_ids = relationships::table
+            .select(relationships::followed_id)
+            .filter(relationships::follower_id.eq(msg.user_id));
+
+        let select_users = users::table
+            .filter(users::id.eq_any(followed_ids))
+            .order(users::name.asc())
+            .limit(msg.per_page)
+            .offset((msg.page - 1) * msg.per_page)
+            .load::<models::User>(conn)
+            .map_err(|e| db_error(&msg.request_id, e))?;
+
+        let total = relationships::table
+            .filter(relationships::follower_id.eq(msg.user_id))
+            .count()
+            .get_result(conn)
+            .map_err(|e| db_error(&msg.request_id, e))?;
+
+        Ok((select_users, total))
+    }
+}
+
+pub struct ReadFollowers {
+    pub request_id: String,
+    pub user_id: i32,
+    pub page: i64,
+    pub per_page: i64,
+}
+
+impl Message for ReadFollowers {
+    type Result = Result<(Vec<models::User>, i64), Error>;
+}
+
+impl Handler<ReadFollowers> for DbExecutor {
+    type Result = Result<(Vec<models::User>, i64), Error>;
+
+    fn handle(&mut self, msg: ReadFollowers, _: &mut Self::Context) -> Self::Result {
+        use self::schema::{relationships, users};
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        let follower_ids = relationships::table
+            .select(relationships::follower_id)
+            .filter(relationships::followed_id.eq(msg.user_id));
+
+        let select_users = users::table
+            .filter(users::id.eq_any(follower_ids))
+            .order(users::name.asc())
+            .limit(msg.per_page)
+            .offset((msg.page - 1) * msg.per_page)
+            .load::<models::User>(conn)
+            .map_err(|e| db_error(&msg.request_id, e))?;
+
+        let total = relationships::table
+            .filter(relationships::followed_id.eq(msg.user_id))
+            .count()
+            .get_result(conn)
+            .map_err(|e| db_error(&msg.request_id, e))?;
+
+        Ok((select_users, total))
+    }
+}
+
+pub struct ReadRelationshipSummary {
+    pub request_id: String,
+    pub user_id: i32,
+    pub viewer_id: Option<i32>,
+}
+
+impl Message for ReadRelationshipSummary {
+    type Result = Result<models::RelationshipSummary, Error>;
+}
+
+impl Handler<ReadRelationshipSummary> for DbExecutor {
+    type Result = Result<models::RelationshipSummary, Error>;
+
+    fn handle(&mut self, msg: ReadRelationshipSummary, _: &mut Self::Context) -> Self::Result {
+        use self::schema::relationships::dsl::*;
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        let following_count = relationships
+            .filter(follower_id.eq(msg.user_id))
+            .count()
+            .get_result(conn)
+            .map_err(|e| db_error(&msg.request_id, e))?;
+
+        let followers_count = relationships
+            .filter(followed_id.eq(msg.user_id))
+            .count()
+            .get_result(conn)
+            .map_err(|e| db_error(&msg.request_id, e))?;
+
+        let followed_by_viewer = match msg.viewer_id {
+            Some(viewer_id) => {
+                relationships
+                    .filter(follower_id.eq(viewer_id))
+                    .filter(followed_id.eq(msg.user_id))
+                    .count()
+                    .get_result::<i64>(conn)
+                    .map_err(|e| db_error(&msg.request_id, e))? > 0
+            },
+            None => false,
+        };
+
+        Ok(models::RelationshipSummary {
+            following_count,
+            followers_count,
+            followed_by_viewer,
+        })
+    }
+}
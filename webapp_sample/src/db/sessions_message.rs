@@ -0,0 +1,265 @@
+use chrono::*;
+
+use actix::prelude::*;
+use actix_web::*;
+
+use diesel;
+use diesel::prelude::*;
+
+use models;
+use schema;
+use db::{DbExecutor, db_error, transaction};
+use sessions::cache::{ValidSession};
+use sessions::digest;
+use time_zones;
+
+// Sessions idle since before `idle_since`, or created before `created_since`, have expired.
+pub struct ReadSession {
+    pub request_id: String,
+    pub id: String,
+    pub idle_since: NaiveDateTime,
+    pub created_since: NaiveDateTime,
+}
+
+impl Message for ReadSession {
+    type Result = Result<Option<models::Session>, Error>;
+}
+
+impl Handler<ReadSession> for DbExecutor {
+    type Result = Result<Option<models::Session>, Error>;
+
+    fn handle(&mut self, msg: ReadSession, _: &mut Self::Context) -> Self::Result {
+        use self::schema::sessions::dsl::*;
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        let select_session = sessions
+            .select((id, data, last_seen_at))
+            .find(&msg.id)
+            .filter(last_seen_at.gt(msg.idle_since))
+            .filter(created_at.gt(msg.created_since))
+            .first(conn)
+            .optional()
+            .map_err(|e| db_error(&msg.request_id, e))?;
+
+        Ok(select_session)
+    }
+}
+
+// Saves the session under `id`, creating the row when it does not exist yet.
+// `previous_id` is deleted in the same transaction when the id was rotated.
+pub struct WriteSession {
+    pub request_id: String,
+    pub id: String,
+    pub previous_id: Option<String>,
+    pub user_id: Option<i32>,
+    pub data: String,
+}
+
+impl Message for WriteSession {
+    type Result = Result<(), Error>;
+}
+
+impl Handler<WriteSession> for DbExecutor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: WriteSession, _: &mut Self::Context) -> Self::Result {
+        use self::schema::sessions::dsl::*;
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+        let now = Utc::now().naive_utc();
+
+        transaction(conn, || {
+            if let Some(ref previous_id) = msg.previous_id {
+                diesel::delete(sessions
+                    .find(previous_id))
+                    .execute(conn)?;
+            }
+
+            let updated = diesel::update(sessions
+                .find(&msg.id))
+                .set((
+                    user_id.eq(msg.user_id),
+                    data.eq(&msg.data),
+                    last_seen_at.eq(now),
+                ))
+                .execute(conn)?;
+
+            if updated == 0 {
+                let new_session = models::NewSession {
+                    id: &msg.id,
+                    user_id: msg.user_id,
+                    data: &msg.data,
+                    created_at: now,
+                    last_seen_at: now,
+                };
+
+                diesel::insert_into(sessions)
+                    .values(&new_session)
+                    .execute(conn)?;
+            }
+
+            Ok(())
+        })
+        .map_err(|e| db_error(&msg.request_id, e))
+    }
+}
+
+pub struct DeleteSession {
+    pub request_id: String,
+    pub id: String,
+}
+
+impl Message for DeleteSession {
+    type Result = Result<(), Error>;
+}
+
+impl Handler<DeleteSession> for DbExecutor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: DeleteSession, _: &mut Self::Context) -> Self::Result {
+        use self::schema::sessions::dsl::*;
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        diesel::delete(sessions
+            .find(&msg.id))
+            .execute(conn)
+            .map_err(|e| db_error(&msg.request_id, e))?;
+
+        Ok(())
+    }
+}
+
+// Deletes expired sessions and returns how many there were.
+pub struct PurgeSessions {
+    pub request_id: String,
+    pub idle_since: NaiveDateTime,
+    pub created_since: NaiveDateTime,
+}
+
+impl Message for PurgeSessions {
+    type Result = Result<usize, Error>;
+}
+
+impl Handler<PurgeSessions> for DbExecutor {
+    type Result = Result<usize, Error>;
+
+    fn handle(&mut self, msg: PurgeSessions, _: &mut Self::Context) -> Self::Result {
+        use self::schema::sessions::dsl::*;
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        let purged = diesel::delete(sessions
+            .filter(last_seen_at.le(msg.idle_since).or(created_at.le(msg.created_since))))
+            .execute(conn)
+            .map_err(|e| db_error(&msg.request_id, e))?;
+
+        Ok(purged)
+    }
+}
+
+// Signs users out everywhere: deletes their sessions and clears their session
+// digests. All users when `user_id` is None. Returns how many sessions were deleted.
+pub struct RevokeSessions {
+    pub request_id: String,
+    pub user_id: Option<i32>,
+}
+
+impl Message for RevokeSessions {
+    type Result = Result<usize, Error>;
+}
+
+impl Handler<RevokeSessions> for DbExecutor {
+    type Result = Result<usize, Error>;
+
+    fn handle(&mut self, msg: RevokeSessions, _: &mut Self::Context) -> Self::Result {
+        use self::schema::sessions::dsl::*;
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        let revoked = transaction(conn, || {
+            match msg.user_id {
+                Some(revoked_user_id) => {
+                    diesel::update(schema::users::table
+                        .find(revoked_user_id))
+                        .set(schema::users::session_digest.eq(None::<String>))
+                        .execute(conn)?;
+
+                    diesel::delete(sessions
+                        .filter(user_id.eq(revoked_user_id)))
+                        .execute(conn)
+                },
+                None => {
+                    diesel::update(schema::users::table)
+                        .set(schema::users::session_digest.eq(None::<String>))
+                        .execute(conn)?;
+
+                    diesel::delete(sessions)
+                        .execute(conn)
+                },
+            }
+        })
+        .map_err(|e| db_error(&msg.request_id, e))?;
+
+        match msg.user_id {
+            Some(revoked_user_id) => self.3.forget_user(revoked_user_id),
+            None                  => self.3.clear(),
+        }
+
+        Ok(revoked)
+    }
+}
+
+// Checks a signed-in session against the user's session digest, and returns
+// what `Authenticate` needs when it is valid. A legacy bcrypt digest that
+// matches is replaced with a fast one.
+pub struct ValidateUserSession {
+    pub request_id: String,
+    pub user_session: models::UserSession,
+}
+
+impl Message for ValidateUserSession {
+    type Result = Result<Option<ValidSession>, Error>;
+}
+
+impl Handler<ValidateUserSession> for DbExecutor {
+    type Result = Result<Option<ValidSession>, Error>;
+
+    fn handle(&mut self, msg: ValidateUserSession, _: &mut Self::Context) -> Self::Result {
+        use self::schema::users::dsl::*;
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+        let user_session = &msg.user_session;
+
+        let user = users
+            .find(user_session.user_id)
+            .select((session_digest, admin, time_zone))
+            .first::<(Option<String>, bool, String)>(conn)
+            .optional()
+            .map_err(|e| db_error(&msg.request_id, e))?;
+
+        let (digest, is_admin, zone) = match user {
+            Some((Some(digest), is_admin, zone)) => (digest, is_admin, zone),
+            _                                    => return Ok(None),
+        };
+
+        if !digest::verify_session_digest(&user_session.session_id, &digest) {
+            return Ok(None);
+        }
+
+        if digest::is_legacy(&digest) {
+            diesel::update(users
+                .find(user_session.user_id)
+                .filter(session_digest.eq(&digest)))
+                .set(session_digest.eq(digest::session_digest(&user_session.session_id)))
+                .execute(conn)
+                .map_err(|e| db_error(&msg.request_id, e))?;
+        }
+
+        Ok(Some(ValidSession{
+            admin: is_admin,
+            time_zone: time_zones::parse(&zone).unwrap_or(time_zones::DEFAULT),
+        }))
+    }
+}
@@ -1,4 +1,3 @@
-use bcrypt::{hash};
 use chrono::*;
 use uuid::Uuid;
 
@@ -10,9 +9,16 @@ use diesel::prelude::*;
 
 use models;
 use schema;
-use db::{DbExecutor};
-
-pub struct ReadUsers {}
+use db::{DbExecutor, db_error, password_error, transaction};
+use db::jobs_message;
+use events::{Event, Publish};
+use jobs::{Task};
+use passwords::{Verification};
+use sessions::digest::{session_digest};
+
+pub struct ReadUsers {
+    pub request_id: String,
+}
 
 impl Message for ReadUsers {
     type Result = Result<Vec<models::User>, Error>;
@@ -21,25 +27,104 @@ impl Message for ReadUsers {
 impl Handler<ReadUsers> for DbExecutor {
     type Result = Result<Vec<models::User>, Error>;
 
-    fn handle(&mut self, _msg: ReadUsers, _: &mut Self::Context) -> Self::Result {
+    fn handle(&mut self, msg: ReadUsers, _: &mut Self::Context) -> Self::Result {
         use self::schema::users::dsl::*;
 
         let conn: &SqliteConnection = &self.0.get().unwrap();
 
         let select_users = users
             .load::<models::User>(conn)
-            .map_err(|e| {
-                match e {
-                    diesel::result::Error::NotFound => error::ErrorNotFound("NotFound"),
-                    _ => error::ErrorInternalServerError("InternalServerError"),
-                }
-            })?;
+            .map_err(|e| db_error(&msg.request_id, e))?;
 
         Ok(select_users)
     }
 }
 
+// Ranked full-text search over `users_fts`, which migrations keep in sync with `users`.
+// `query` is an FTS5 match expression; build it with `search_helper::fts_query`.
+// Snippets mark matches with `search_helper::HIGHLIGHT_START` and `HIGHLIGHT_END`.
+pub struct SearchUsers {
+    pub request_id: String,
+    pub query: String,
+    pub page: i64,
+    pub per_page: i64,
+}
+
+impl Message for SearchUsers {
+    type Result = Result<(Vec<models::UserSearchResult>, i64), Error>;
+}
+
+#[derive(QueryableByName)]
+struct SearchCount {
+    #[sql_type = "diesel::sql_types::BigInt"]
+    count: i64,
+}
+
+impl Handler<SearchUsers> for DbExecutor {
+    type Result = Result<(Vec<models::UserSearchResult>, i64), Error>;
+
+    fn handle(&mut self, msg: SearchUsers, _: &mut Self::Context) -> Self::Result {
+        use diesel::sql_types::{BigInt, Text};
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        // A read transaction, so the count and the page see the same rows.
+        conn
+            .transaction(|| {
+                // Matches in the name weigh ten times as much as matches in the email.
+                let select_users = diesel::sql_query(
+                    "SELECT users.id, users.name, users.email, users.avatar_key, \
+                            highlight(users_fts, 0, char(2), char(3)) AS name_snippet, \
+                            snippet(users_fts, 1, char(2), char(3), '...', 16) AS email_snippet \
+                     FROM users_fts INNER JOIN users ON users.id = users_fts.rowid \
+                     WHERE users_fts MATCH ? \
+                     ORDER BY bm25(users_fts, 10.0, 1.0), users.id \
+                     LIMIT ? OFFSET ?")
+                    .bind::<Text, _>(&msg.query)
+                    .bind::<BigInt, _>(msg.per_page)
+                    .bind::<BigInt, _>((msg.page - 1) * msg.per_page)
+                    .load::<models::UserSearchResult>(conn)?;
+
+                let total = diesel::sql_query("SELECT COUNT(*) AS count FROM users_fts WHERE users_fts MATCH ?")
+                    .bind::<Text, _>(&msg.query)
+                    .get_result::<SearchCount>(conn)?
+                    .count;
+
+                Ok((select_users, total))
+            })
+            .map_err(|e| db_error(&msg.request_id, e))
+    }
+}
+
+// Adds a user. Handlers call this inside their transaction; `AcceptInvitation`
+// marks the invitation used in the same one.
+pub fn insert_user(conn: &SqliteConnection, name: &str, email: &str, password_digest: &str, admin: bool) -> QueryResult<models::User> {
+    use self::schema::users::dsl::{users, uuid};
+
+    let now = Utc::now().naive_utc();
+    let new_uuid = Uuid::new_v4().to_string();
+
+    diesel::insert_into(users)
+        .values(models::NewUser {
+            uuid: &new_uuid,
+            name,
+            email,
+            password_digest,
+            admin,
+            created_at: now,
+            updated_at: now,
+        })
+        .execute(conn)?;
+
+    users
+        .filter(uuid.eq(&new_uuid))
+        .first(conn)
+}
+
+// Without an invitation, for `webapp_admin user create`; sign-up goes
+// through `AcceptInvitation`.
 pub struct CreateUser {
+    pub request_id: String,
     pub name: String,
     pub email: String,
     pub password: String,
@@ -53,48 +138,25 @@ impl Handler<CreateUser> for DbExecutor {
     type Result = Result<models::User, Error>;
 
     fn handle(&mut self, msg: CreateUser, _: &mut Self::Context) -> Self::Result {
-        use self::schema::users::dsl::*;
+        let digest = self.1
+            .current()
+            .password_policy
+            .hash(&msg.password)
+            .map_err(|e| password_error(&msg.request_id, e))?;
 
-        let digest = hash(&msg.password, 5).unwrap();
-       
-        let now = Local::now().naive_local();
+        let conn: &SqliteConnection = &self.0.get().unwrap();
 
-        let new_user = models::NewUser {
-            uuid: &Uuid::new_v4().to_string(),
-            name: &msg.name,
-            email: &msg.email,
-            password_digest: &digest,
-            created_at: now,
-            updated_at: now,
-        };
+        let created_user = transaction(conn, || insert_user(conn, &msg.name, &msg.email, &digest, false))
+            .map_err(|e| db_error(&msg.request_id, e))?;
 
-        let conn: &SqliteConnection = &self.0.get().unwrap();
+        self.2.do_send(Publish(Event::UserCreated{user: (&created_user).into()}));
 
-        diesel::insert_into(users)
-            .values(new_user)
-            .execute(conn)
-            .map_err(|e| {
-                match e {
-                    diesel::result::Error::NotFound => error::ErrorNotFound("NotFound"),
-                    _ => error::ErrorInternalServerError("InternalServerError"),
-                }
-            })?;
-
-        let insert_user = users
-            .filter(email.eq(&msg.email))
-            .first(conn)
-            .map_err(|e| {
-                match e {
-                    diesel::result::Error::NotFound => error::ErrorNotFound("NotFound"),
-                    _ => error::ErrorInternalServerError("InternalServerError"),
-                }
-            })?;
-
-        Ok(insert_user)
+        Ok(created_user)
     }
 }
 
 pub struct ReadUser {
+    pub request_id: String,
     pub id: i32,
 }
 
@@ -113,69 +175,279 @@ impl Handler<ReadUser> for DbExecutor {
         let select_user = users
             .find(msg.id)
             .first(conn)
-            .map_err(|e| {
-                match e {
-                    diesel::result::Error::NotFound => error::ErrorNotFound("NotFound"),
-                    _ => error::ErrorInternalServerError("InternalServerError"),
-                }
-            })?;
+            .map_err(|e| db_error(&msg.request_id, e))?;
+
+        Ok(select_user)
+    }
+}
+
+pub struct ReadUserByEmail {
+    pub request_id: String,
+    pub email: String,
+}
+
+impl Message for ReadUserByEmail {
+    type Result = Result<models::User, Error>;
+}
+
+impl Handler<ReadUserByEmail> for DbExecutor {
+    type Result = Result<models::User, Error>;
+
+    fn handle(&mut self, msg: ReadUserByEmail, _: &mut Self::Context) -> Self::Result {
+        use self::schema::users::dsl::*;
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        let select_user = users
+            .filter(email.eq(&msg.email))
+            .first(conn)
+            .map_err(|e| db_error(&msg.request_id, e))?;
 
         Ok(select_user)
     }
 }
 
+// PATCH semantics: fields left as None keep their current value.
 pub struct UpdateUser {
+    pub request_id: String,
     pub id: i32,
-    pub name: String,
-    pub email: String,
-    pub password: String,
+    pub lock_version: i32,
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub time_zone: Option<String>,
+}
+
+// Someone else may have saved the user since the form was rendered.
+// `Conflict` carries the row as it is now so the form can be shown again.
+pub enum UserUpdate {
+    Updated(models::User),
+    Conflict(models::User),
 }
 
 impl Message for UpdateUser {
-    type Result = Result<models::User, Error>;
+    type Result = Result<UserUpdate, Error>;
 }
 
 impl Handler<UpdateUser> for DbExecutor {
-    type Result = Result<models::User, Error>;
+    type Result = Result<UserUpdate, Error>;
 
     fn handle(&mut self, msg: UpdateUser, _: &mut Self::Context) -> Self::Result {
         use self::schema::users::dsl::*;
 
         let conn: &SqliteConnection = &self.0.get().unwrap();
 
-        let digest = hash(&msg.password, 5).unwrap();
-        
-        diesel::update(users
-            .find(msg.id))
-            .set((
-                name.eq(&msg.name),
-                email.eq(&msg.email),
-                password_digest.eq(&digest),
-                updated_at.eq(Local::now().naive_local()),
-            ))
-            .execute(conn)
-            .map_err(|e| {
-                match e {
-                    diesel::result::Error::NotFound => error::ErrorNotFound("NotFound"),
-                    _ => error::ErrorInternalServerError("InternalServerError"),
-                }
-            })?;
-
-        let update_user = users
-            .find(msg.id)
-            .first(conn)
-            .map_err(|e| {
-                match e {
-                    diesel::result::Error::NotFound => error::ErrorNotFound("NotFound"),
-                    _ => error::ErrorInternalServerError("InternalServerError"),
-                }
-            })?;
+        let changeset = models::UserChangeset {
+            name: msg.name.as_deref(),
+            email: msg.email.as_deref(),
+            time_zone: msg.time_zone.as_deref(),
+            updated_at: Utc::now().naive_utc(),
+        };
+
+        let update_user = transaction(conn, || {
+            let updated = diesel::update(users
+                .find(msg.id)
+                .filter(lock_version.eq(msg.lock_version)))
+                .set((
+                    &changeset,
+                    lock_version.eq(lock_version + 1),
+                ))
+                .execute(conn)?;
+
+            let current_user = users
+                .find(msg.id)
+                .first(conn)?;
+
+            if updated == 0 {
+                Ok(UserUpdate::Conflict(current_user))
+            } else {
+                Ok(UserUpdate::Updated(current_user))
+            }
+        })
+        .map_err(|e| db_error(&msg.request_id, e))?;
+
+        if let UserUpdate::Updated(ref user) = update_user {
+            // Cached sessions carry the time zone.
+            if msg.time_zone.is_some() {
+                self.3.forget_user(user.id);
+            }
+            self.2.do_send(Publish(Event::UserUpdated{user: user.into()}));
+        }
 
         Ok(update_user)
     }
 }
 
+// Replaces the password after checking the current one. The session digest is
+// replaced as well, so only the session identified by `session_id` stays signed in.
+pub struct ChangeUserPassword {
+    pub request_id: String,
+    pub id: i32,
+    pub current_password: String,
+    pub new_password: String,
+    pub session_id: String,
+}
+
+pub enum PasswordChange {
+    Changed,
+    WrongPassword,
+}
+
+impl Message for ChangeUserPassword {
+    type Result = Result<PasswordChange, Error>;
+}
+
+impl Handler<ChangeUserPassword> for DbExecutor {
+    type Result = Result<PasswordChange, Error>;
+
+    fn handle(&mut self, msg: ChangeUserPassword, _: &mut Self::Context) -> Self::Result {
+        use self::schema::users::dsl::*;
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        let user = users
+            .find(msg.id)
+            .first::<models::User>(conn)
+            .map_err(|e| db_error(&msg.request_id, e))?;
+
+        if let Verification::Invalid = self.1.current().password_policy.verify(&msg.current_password, &user.password_digest) {
+            return Ok(PasswordChange::WrongPassword);
+        }
+
+        let digest = self.1
+            .current()
+            .password_policy
+            .hash(&msg.new_password)
+            .map_err(|e| password_error(&msg.request_id, e))?;
+        let new_session_digest = self::session_digest(&msg.session_id);
+
+        let updated = transaction(conn, || change_password(conn, &user, &digest, &new_session_digest))
+            .map_err(|e| db_error(&msg.request_id, e))?;
+
+        if updated == 0 {
+            return Ok(PasswordChange::WrongPassword);
+        }
+        self.3.forget_user(msg.id);
+
+        Ok(PasswordChange::Changed)
+    }
+}
+
+// Stores the new password and session digests and signs the user out on every
+// other device. The stored rows of the current session go too; it is written
+// again under the id that comes with the new session digest.
+// Hashing happens outside the lock, so nothing changes unless the password
+// verified before is still the current one. Run inside a transaction.
+fn change_password(conn: &SqliteConnection, user: &models::User, new_password_digest: &str, new_session_digest: &str) -> QueryResult<usize> {
+    use self::schema::users::dsl::*;
+
+    let updated = diesel::update(users
+        .find(user.id)
+        .filter(password_digest.eq(&user.password_digest)))
+        .set((
+            password_digest.eq(new_password_digest),
+            session_digest.eq(new_session_digest),
+            updated_at.eq(Utc::now().naive_utc()),
+        ))
+        .execute(conn)?;
+
+    if updated > 0 {
+        diesel::delete(schema::sessions::table
+            .filter(schema::sessions::user_id.eq(user.id)))
+            .execute(conn)?;
+    }
+
+    Ok(updated)
+}
+
+// Grants or revokes access to the admin pages.
+pub struct SetUserAdmin {
+    pub request_id: String,
+    pub id: i32,
+    pub admin: bool,
+}
+
+impl Message for SetUserAdmin {
+    type Result = Result<models::User, Error>;
+}
+
+impl Handler<SetUserAdmin> for DbExecutor {
+    type Result = Result<models::User, Error>;
+
+    fn handle(&mut self, msg: SetUserAdmin, _: &mut Self::Context) -> Self::Result {
+        use self::schema::users::dsl::*;
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        let user = transaction(conn, || {
+            diesel::update(users
+                .find(msg.id))
+                .set(admin.eq(msg.admin))
+                .execute(conn)?;
+
+            users
+                .find(msg.id)
+                .first(conn)
+        })
+        .map_err(|e| db_error(&msg.request_id, e))?;
+        self.3.forget_user(msg.id);
+
+        Ok(user)
+    }
+}
+
+// Replaces the password without knowing the current one, for administrators.
+// Every session of the user is revoked.
+pub struct SetUserPassword {
+    pub request_id: String,
+    pub id: i32,
+    pub password: String,
+}
+
+impl Message for SetUserPassword {
+    type Result = Result<(), Error>;
+}
+
+impl Handler<SetUserPassword> for DbExecutor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: SetUserPassword, _: &mut Self::Context) -> Self::Result {
+        use self::schema::users::dsl::*;
+
+        let digest = self.1
+            .current()
+            .password_policy
+            .hash(&msg.password)
+            .map_err(|e| password_error(&msg.request_id, e))?;
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        transaction(conn, || {
+            let updated = diesel::update(users
+                .find(msg.id))
+                .set((
+                    password_digest.eq(&digest),
+                    session_digest.eq(None::<String>),
+                    updated_at.eq(Utc::now().naive_utc()),
+                ))
+                .execute(conn)?;
+
+            if updated == 0 {
+                return Err(diesel::result::Error::NotFound);
+            }
+
+            diesel::delete(schema::sessions::table
+                .filter(schema::sessions::user_id.eq(msg.id)))
+                .execute(conn)
+        })
+        .map_err(|e| db_error(&msg.request_id, e))?;
+        self.3.forget_user(msg.id);
+
+        Ok(())
+    }
+}
+
 pub struct DeleteUser {
+    pub request_id: String,
     pub id: i32,
 }
 
@@ -191,103 +463,368 @@ impl Handler<DeleteUser> for DbExecutor {
 
         let conn: &SqliteConnection = &self.0.get().unwrap();
 
-        let delete_user = users
+        let delete_user: models::User = transaction(conn, || {
+            let delete_user = users
+                .find(msg.id)
+                .first(conn)?;
+
+            delete_user_rows(conn, &delete_user, Utc::now().naive_utc())?;
+
+            Ok(delete_user)
+        })
+        .map_err(|e| db_error(&msg.request_id, e))?;
+        self.3.forget_user(msg.id);
+
+        self.2.do_send(Publish(Event::UserDeleted{user: (&delete_user).into()}));
+
+        Ok(delete_user)
+    }
+}
+
+// Deletes the user and every row that belongs to them, unlinks the invitations
+// they sent or accepted and queues the removal of their stored files.
+// Run inside a transaction.
+fn delete_user_rows(conn: &SqliteConnection, user: &models::User, now: NaiveDateTime) -> QueryResult<usize> {
+    let user_id = user.id;
+
+    diesel::delete(schema::microposts::table
+        .filter(schema::microposts::user_id.eq(user_id)))
+        .execute(conn)?;
+
+    diesel::delete(schema::sessions::table
+        .filter(schema::sessions::user_id.eq(user_id)))
+        .execute(conn)?;
+
+    diesel::delete(schema::relationships::table
+        .filter(schema::relationships::follower_id.eq(user_id)
+            .or(schema::relationships::followed_id.eq(user_id))))
+        .execute(conn)?;
+
+    diesel::delete(schema::data_exports::table
+        .filter(schema::data_exports::user_id.eq(user_id)))
+        .execute(conn)?;
+
+    diesel::update(schema::invitations::table
+        .filter(schema::invitations::user_id.eq(user_id)))
+        .set(schema::invitations::user_id.eq(None::<i32>))
+        .execute(conn)?;
+
+    diesel::update(schema::invitations::table
+        .filter(schema::invitations::invited_by.eq(user_id)))
+        .set(schema::invitations::invited_by.eq(None::<i32>))
+        .execute(conn)?;
+
+    jobs_message::enqueue(conn, &Task::DeleteUserFiles{user_uuid: user.uuid.clone()}, now)?;
+
+    diesel::delete(schema::users::table
+        .find(user_id))
+        .execute(conn)
+}
+
+// Self-service deletion: after the password is confirmed the account is
+// signed out everywhere and purged by `PurgeDeletedUsers` once `purge_at` has passed.
+// Until then the owner can sign in and cancel with `CancelUserDeletion`.
+pub struct ScheduleUserDeletion {
+    pub request_id: String,
+    pub id: i32,
+    pub password: String,
+    pub purge_at: NaiveDateTime,
+}
+
+pub enum DeletionRequest {
+    Scheduled,
+    WrongPassword,
+}
+
+impl Message for ScheduleUserDeletion {
+    type Result = Result<DeletionRequest, Error>;
+}
+
+impl Handler<ScheduleUserDeletion> for DbExecutor {
+    type Result = Result<DeletionRequest, Error>;
+
+    fn handle(&mut self, msg: ScheduleUserDeletion, _: &mut Self::Context) -> Self::Result {
+        use self::schema::users::dsl::*;
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        let user = users
             .find(msg.id)
-            .first(conn)
-            .map_err(|e| {
-                match e {
-                    diesel::result::Error::NotFound => error::ErrorNotFound("NotFound"),
-                    _ => error::ErrorInternalServerError("InternalServerError"),
-                }
-            })?;
-
-        diesel::delete(users
+            .first::<models::User>(conn)
+            .map_err(|e| db_error(&msg.request_id, e))?;
+
+        if let Verification::Invalid = self.1.current().password_policy.verify(&msg.password, &user.password_digest) {
+            return Ok(DeletionRequest::WrongPassword);
+        }
+
+        transaction(conn, || {
+            diesel::update(users
+                .find(msg.id))
+                .set((
+                    deletion_scheduled_at.eq(Some(msg.purge_at)),
+                    session_digest.eq(None::<String>),
+                ))
+                .execute(conn)?;
+
+            diesel::delete(schema::sessions::table
+                .filter(schema::sessions::user_id.eq(msg.id)))
+                .execute(conn)
+        })
+        .map_err(|e| db_error(&msg.request_id, e))?;
+        self.3.forget_user(msg.id);
+
+        Ok(DeletionRequest::Scheduled)
+    }
+}
+
+pub struct CancelUserDeletion {
+    pub request_id: String,
+    pub id: i32,
+}
+
+impl Message for CancelUserDeletion {
+    type Result = Result<(), Error>;
+}
+
+impl Handler<CancelUserDeletion> for DbExecutor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: CancelUserDeletion, _: &mut Self::Context) -> Self::Result {
+        use self::schema::users::dsl::*;
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        diesel::update(users
             .find(msg.id))
+            .set(deletion_scheduled_at.eq(None::<NaiveDateTime>))
             .execute(conn)
-            .map_err(|e| {
-                match e {
-                    diesel::result::Error::NotFound => error::ErrorNotFound("NotFound"),
-                    _ => error::ErrorInternalServerError("InternalServerError"),
-                }
-            })?;
+            .map_err(|e| db_error(&msg.request_id, e))?;
 
-        Ok(delete_user)
+        Ok(())
     }
 }
 
-pub struct ReadUserByEmail {
+// Deletes accounts whose grace period ended at or before `now` and returns them,
+// so their stored files can be removed as well.
+pub struct PurgeDeletedUsers {
+    pub request_id: String,
+    pub now: NaiveDateTime,
+}
+
+impl Message for PurgeDeletedUsers {
+    type Result = Result<Vec<models::User>, Error>;
+}
+
+impl Handler<PurgeDeletedUsers> for DbExecutor {
+    type Result = Result<Vec<models::User>, Error>;
+
+    fn handle(&mut self, msg: PurgeDeletedUsers, _: &mut Self::Context) -> Self::Result {
+        use self::schema::users::dsl::*;
+
+        let conn: &SqliteConnection = &self.0.get().unwrap();
+
+        transaction(conn, || {
+            let purged = users
+                .filter(deletion_scheduled_at.le(msg.now))
+                .load::<models::User>(conn)?;
+
+            for user in &purged {
+                delete_user_rows(conn, user, msg.now)?;
+            }
+
+            Ok(purged)
+        })
+        .map_err(|e| db_error(&msg.request_id, e))
+    }
+}
+
+// Checks the credentials and, when they match, stores the digest of `session_id`
+// as the user's only valid session. A password hashed below the current policy
+// is rehashed while the plain text is at hand.
+// Returns None for an unknown email or a wrong password.
+pub struct SignInUser {
+    pub request_id: String,
     pub email: String,
+    pub password: String,
+    pub session_id: String,
 }
 
-impl Message for ReadUserByEmail {
-    type Result = Result<models::User, Error>;
+impl Message for SignInUser {
+    type Result = Result<Option<models::User>, Error>;
 }
 
-impl Handler<ReadUserByEmail> for DbExecutor {
-    type Result = Result<models::User, Error>;
+impl Handler<SignInUser> for DbExecutor {
+    type Result = Result<Option<models::User>, Error>;
 
-    fn handle(&mut self, msg: ReadUserByEmail, _: &mut Self::Context) -> Self::Result {
+    fn handle(&mut self, msg: SignInUser, _: &mut Self::Context) -> Self::Result {
         use self::schema::users::dsl::*;
 
         let conn: &SqliteConnection = &self.0.get().unwrap();
 
-        let select_user = users
+        let user = users
             .filter(email.eq(&msg.email))
-            .first(conn)
-            .map_err(|e| {
-                match e {
-                    diesel::result::Error::NotFound => error::ErrorNotFound("NotFound"),
-                    _ => error::ErrorInternalServerError("InternalServerError"),
-                }
-            })?;
+            .first::<models::User>(conn)
+            .optional()
+            .map_err(|e| db_error(&msg.request_id, e))?;
 
-        Ok(select_user)
+        let user = match user {
+            Some(user) => user,
+            None       => return Ok(None),
+        };
+
+        let new_password_digest = match self.1.current().password_policy.verify(&msg.password, &user.password_digest) {
+            Verification::Invalid          => return Ok(None),
+            Verification::Valid            => user.password_digest.clone(),
+            Verification::ValidNeedsRehash => {
+                info!("{}", json!({
+                    "event":      "password_rehash",
+                    "request_id": msg.request_id,
+                    "user_id":    user.id,
+                }));
+                self.1
+                    .current()
+                    .password_policy
+                    .hash(&msg.password)
+                    .map_err(|e| password_error(&msg.request_id, e))?
+            },
+        };
+
+        let digest = self::session_digest(&msg.session_id);
+
+        // As in `ChangeUserPassword`, a password changed since it was verified wins.
+        let update_user = transaction(conn, || {
+            let updated = diesel::update(users
+                .find(user.id)
+                .filter(password_digest.eq(&user.password_digest)))
+                .set((
+                    password_digest.eq(&new_password_digest),
+                    session_digest.eq(&digest),
+                    updated_at.eq(Utc::now().naive_utc()),
+                ))
+                .execute(conn)?;
+
+            if updated == 0 {
+                return Ok(None);
+            }
+
+            users
+                .find(user.id)
+                .first(conn)
+                .map(Some)
+        })
+        .map_err(|e| db_error(&msg.request_id, e))?;
+
+        if update_user.is_some() {
+            self.3.forget_user(user.id);
+        }
+
+        Ok(update_user)
     }
 }
 
-pub struct UpdateUserSession {
+pub struct UpdateUserAvatar {
+    pub request_id: String,
     pub id: i32,
-    pub session_id: String,
+    pub avatar_key: Option<String>,
 }
 
-impl Message for UpdateUserSession {
+impl Message for UpdateUserAvatar {
     type Result = Result<models::User, Error>;
 }
 
-impl Handler<UpdateUserSession> for DbExecutor {
+impl Handler<UpdateUserAvatar> for DbExecutor {
     type Result = Result<models::User, Error>;
 
-    fn handle(&mut self, msg: UpdateUserSession, _: &mut Self::Context) -> Self::Result {
+    fn handle(&mut self, msg: UpdateUserAvatar, _: &mut Self::Context) -> Self::Result {
         use self::schema::users::dsl::*;
 
         let conn: &SqliteConnection = &self.0.get().unwrap();
 
-        let digest = hash(&msg.session_id, 5).unwrap();
-        
-        diesel::update(users
-            .find(msg.id))
-            .set((
-                session_digest.eq(&digest),
-                updated_at.eq(Local::now().naive_local()),
-            ))
-            .execute(conn)
-            .map_err(|e| {
-                match e {
-                    diesel::result::Error::NotFound => error::ErrorNotFound("NotFound"),
-                    _ => error::ErrorInternalServerError("InternalServerError"),
-                }
-            })?;
-
-        let update_user = users
-            .find(msg.id)
-            .first(conn)
-            .map_err(|e| {
-                match e {
-                    diesel::result::Error::NotFound => error::ErrorNotFound("NotFound"),
-                    _ => error::ErrorInternalServerError("InternalServerError"),
-                }
-            })?;
+        transaction(conn, || {
+            diesel::update(users
+                .find(msg.id))
+                .set((
+                    avatar_key.eq(&msg.avatar_key),
+                    updated_at.eq(Utc::now().naive_utc()),
+                ))
+                .execute(conn)?;
+
+            users
+                .find(msg.id)
+                .first(conn)
+        })
+        .map_err(|e| db_error(&msg.request_id, e))
+    }
+}
 
-        Ok(update_user)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use diesel::connection::{SimpleConnection};
+    use serde_json;
+
+    use db::tests::{migrated};
+
+    #[test]
+    fn changing_the_password_signs_out_every_session() {
+        let (path, conn) = migrated(None);
+        conn.batch_execute("
+            INSERT INTO users (id, uuid, name, email, password_digest, created_at, updated_at) VALUES (1, 'u-1', 'alice', 'alice@example.com', 'old', '2026-10-19 00:00:00', '2026-10-19 00:00:00');
+            INSERT INTO users (id, uuid, name, email, password_digest, created_at, updated_at) VALUES (2, 'u-2', 'bob', 'bob@example.com', 'x', '2026-10-19 00:00:00', '2026-10-19 00:00:00');
+            INSERT INTO sessions (id, user_id, data, created_at, last_seen_at) VALUES ('phone', 1, '{}', '2026-10-19 00:00:00', '2026-10-19 00:00:00');
+            INSERT INTO sessions (id, user_id, data, created_at, last_seen_at) VALUES ('laptop', 1, '{}', '2026-10-19 00:00:00', '2026-10-19 00:00:00');
+            INSERT INTO sessions (id, user_id, data, created_at, last_seen_at) VALUES ('bob', 2, '{}', '2026-10-19 00:00:00', '2026-10-19 00:00:00');
+        ").unwrap();
+        let alice: models::User = schema::users::table.find(1).first(&conn).unwrap();
+        let mut stale: models::User = schema::users::table.find(1).first(&conn).unwrap();
+        stale.password_digest = "older".to_string();
+        let session_ids = || schema::sessions::table.select(schema::sessions::id).load::<String>(&conn).unwrap();
+
+        assert_eq!(transaction(&conn, || change_password(&conn, &stale, "new", "s")).unwrap(), 0);
+        assert_eq!(session_ids().len(), 3);
+
+        assert_eq!(transaction(&conn, || change_password(&conn, &alice, "new", "s")).unwrap(), 1);
+        assert_eq!(session_ids(), vec!["bob".to_string()]);
+
+        let changed: models::User = schema::users::table.find(1).first(&conn).unwrap();
+        assert_eq!(changed.password_digest, "new");
+        assert_eq!(changed.session_digest, Some("s".to_string()));
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn deleting_a_user_queues_their_files_and_unlinks_invitations() {
+        let (path, conn) = migrated(None);
+        conn.batch_execute("
+            INSERT INTO users (id, uuid, name, email, password_digest, created_at, updated_at) VALUES (1, 'u-1', 'alice', 'alice@example.com', 'x', '2026-10-19 00:00:00', '2026-10-19 00:00:00');
+            INSERT INTO users (id, uuid, name, email, password_digest, created_at, updated_at) VALUES (2, 'u-2', 'bob', 'bob@example.com', 'x', '2026-10-19 00:00:00', '2026-10-19 00:00:00');
+            INSERT INTO invitations (email, role, token_digest, invited_by, user_id, expires_at, created_at, updated_at) VALUES ('bob@example.com', 'member', 'd-1', 1, 2, '2026-10-20 00:00:00', '2026-10-19 00:00:00', '2026-10-19 00:00:00');
+            INSERT INTO invitations (email, role, token_digest, invited_by, user_id, expires_at, created_at, updated_at) VALUES ('alice@example.com', 'member', 'd-2', 2, 1, '2026-10-20 00:00:00', '2026-10-19 00:00:00', '2026-10-19 00:00:00');
+        ").unwrap();
+
+        let alice: models::User = schema::users::table.find(1).first(&conn).unwrap();
+        let now = Utc::now().naive_utc();
+        transaction(&conn, || delete_user_rows(&conn, &alice, now)).unwrap();
+
+        let links: Vec<(Option<i32>, Option<i32>)> = schema::invitations::table
+            .select((schema::invitations::invited_by, schema::invitations::user_id))
+            .order(schema::invitations::id)
+            .load(&conn)
+            .unwrap();
+        assert_eq!(links, vec![(None, Some(2)), (Some(2), None)]);
+
+        let queued: Vec<models::Job> = schema::jobs::table.load(&conn).unwrap();
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].kind, "delete_user_files");
+        assert_eq!(serde_json::from_str::<Task>(&queued[0].payload).unwrap(), Task::DeleteUserFiles{user_uuid: "u-1".to_string()});
+        assert_eq!(queued[0].run_at, now);
+
+        assert_eq!(schema::users::table.count().get_result::<i64>(&conn).unwrap(), 1);
+
+        let _ = fs::remove_file(&path);
     }
 }
@@ -0,0 +1,112 @@
+pub mod socket;
+
+use std::collections::HashMap;
+
+use actix::prelude::*;
+
+use models;
+
+// The public part of a user, as shown on the users list. Events go to every
+// signed-in browser, so email addresses and digests stay out.
+#[derive(Clone, Debug, Serialize)]
+pub struct EventUser {
+    pub id: i32,
+    pub name: String,
+    pub avatar_key: Option<String>,
+}
+
+impl<'a> From<&'a models::User> for EventUser {
+    fn from(user: &'a models::User) -> Self {
+        EventUser {
+            id: user.id,
+            name: user.name.clone(),
+            avatar_key: user.avatar_key.clone(),
+        }
+    }
+}
+
+// Domain events, sent to clients as `{"type": "user_created", "user": {...}}`.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    UserCreated { user: EventUser },
+    UserUpdated { user: EventUser },
+    UserDeleted { user: EventUser },
+}
+
+impl Message for Event {
+    type Result = ();
+}
+
+// Fans published events out to every subscribed socket.
+#[derive(Default)]
+pub struct Broadcaster {
+    subscribers: HashMap<usize, Recipient<Event>>,
+    next_id: usize,
+}
+
+impl Actor for Broadcaster {
+    type Context = Context<Self>;
+}
+
+pub struct Subscribe(pub Recipient<Event>);
+
+impl Message for Subscribe {
+    type Result = usize;
+}
+
+impl Handler<Subscribe> for Broadcaster {
+    type Result = usize;
+
+    fn handle(&mut self, msg: Subscribe, _: &mut Self::Context) -> Self::Result {
+        self.next_id += 1;
+        self.subscribers.insert(self.next_id, msg.0);
+        self.next_id
+    }
+}
+
+pub struct Unsubscribe(pub usize);
+
+impl Message for Unsubscribe {
+    type Result = ();
+}
+
+impl Handler<Unsubscribe> for Broadcaster {
+    type Result = ();
+
+    fn handle(&mut self, msg: Unsubscribe, _: &mut Self::Context) -> Self::Result {
+        self.subscribers.remove(&msg.0);
+    }
+}
+
+pub struct Publish(pub Event);
+
+impl Message for Publish {
+    type Result = ();
+}
+
+impl Handler<Publish> for Broadcaster {
+    type Result = ();
+
+    fn handle(&mut self, msg: Publish, _: &mut Self::Context) -> Self::Result {
+        // Sockets that went away without unsubscribing are dropped here.
+        self.subscribers.retain(|_, subscriber| subscriber.do_send(msg.0.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_is_tagged_and_leaves_out_the_email() {
+        let event = Event::UserCreated {
+            user: EventUser{id: 1, name: "alice".to_string(), avatar_key: None},
+        };
+
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            json!({"type": "user_created", "user": {"id": 1, "name": "alice", "avatar_key": null}}),
+        );
+    }
+}
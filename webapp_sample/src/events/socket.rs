@@ -0,0 +1,82 @@
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use actix_web::ws;
+
+use context;
+use events::{Broadcaster, Event, Subscribe, Unsubscribe};
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(60);
+
+// One browser connected to `/events`. Forwards every published event as a
+// JSON text frame, and closes connections that stop answering pings.
+pub struct EventSocket {
+    events: Addr<Broadcaster>,
+    subscription: Option<usize>,
+    last_seen: Instant,
+}
+
+impl EventSocket {
+    pub fn new(events: Addr<Broadcaster>) -> Self {
+        EventSocket {
+            events,
+            subscription: None,
+            last_seen: Instant::now(),
+        }
+    }
+}
+
+impl Actor for EventSocket {
+    type Context = ws::WebsocketContext<Self, context::Context>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
+            if act.last_seen.elapsed() > CLIENT_TIMEOUT {
+                ctx.stop();
+            } else {
+                ctx.ping("");
+            }
+        });
+
+        self.events
+            .send(Subscribe(ctx.address().recipient()))
+            .into_actor(self)
+            .then(|res, act, ctx| {
+                match res {
+                    Ok(id) => act.subscription = Some(id),
+                    Err(_) => ctx.stop(),
+                }
+                actix::fut::ok(())
+            })
+            .wait(ctx);
+    }
+
+    fn stopped(&mut self, _: &mut Self::Context) {
+        if let Some(id) = self.subscription.take() {
+            self.events.do_send(Unsubscribe(id));
+        }
+    }
+}
+
+impl Handler<Event> for EventSocket {
+    type Result = ();
+
+    fn handle(&mut self, msg: Event, ctx: &mut Self::Context) -> Self::Result {
+        if let Ok(text) = serde_json::to_string(&msg) {
+            ctx.text(text);
+        }
+    }
+}
+
+impl StreamHandler<ws::Message, ws::ProtocolError> for EventSocket {
+    fn handle(&mut self, msg: ws::Message, ctx: &mut Self::Context) {
+        self.last_seen = Instant::now();
+
+        match msg {
+            ws::Message::Ping(msg) => ctx.pong(&msg),
+            ws::Message::Close(_)  => ctx.stop(),
+            _                      => (),
+        }
+    }
+}
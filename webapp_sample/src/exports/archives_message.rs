@@ -0,0 +1,118 @@
+use futures::Future;
+
+use actix::prelude::*;
+use actix_web::{error, Error};
+
+use db::data_exports_message::{FinishDataExport, ReadPersonalData};
+use exports::{self, ExportExecutor};
+use images::{AVATAR_SIZES};
+use images::avatars_message::{avatar_path};
+
+fn storage_error(request_id: &str, e: &dyn ToString) -> Error {
+    error!("{}", json!({
+        "event":      "storage_error",
+        "request_id": request_id,
+        "error":      e.to_string(),
+    }));
+    error::ErrorInternalServerError("InternalServerError")
+}
+
+// Builds the archive for a `data_exports` row and marks the row ready.
+// Run by a `build_data_export` job, which retries on error.
+pub struct BuildArchive {
+    pub request_id: String,
+    pub export_id: i32,
+    pub user_id: i32,
+}
+
+impl Message for BuildArchive {
+    type Result = Result<(), Error>;
+}
+
+impl Handler<BuildArchive> for ExportExecutor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: BuildArchive, _: &mut Self::Context) -> Self::Result {
+        let stored = self.db
+            .send(ReadPersonalData{request_id: msg.request_id.clone(), user_id: msg.user_id})
+            .wait()
+            .map_err(Error::from)
+            .and_then(|res| res)
+            .and_then(|data| {
+                let avatar = match data.user.avatar_key {
+                    Some(ref key) => self.storage.get(&avatar_path(key, AVATAR_SIZES[AVATAR_SIZES.len() - 1])).ok(),
+                    None          => None,
+                };
+                let key = exports::archive_key(&data.user.uuid, msg.export_id);
+
+                exports::archive(&data, avatar.as_deref())
+                    .and_then(|bytes| self.storage.put(&key, &bytes))
+                    .map(|_| key)
+                    .map_err(|e| storage_error(&msg.request_id, &e))
+            });
+
+        info!("{}", json!({
+            "event":      "data_export_finished",
+            "request_id": msg.request_id,
+            "export_id":  msg.export_id,
+            "ok":         stored.is_ok(),
+        }));
+
+        let key = stored?;
+
+        self.db
+            .send(FinishDataExport{
+                request_id: msg.request_id,
+                id: msg.export_id,
+                storage_key: Some(key),
+            })
+            .wait()
+            .map_err(Error::from)
+            .and_then(|res| res)
+    }
+}
+
+pub struct ReadArchive {
+    pub request_id: String,
+    pub key: String,
+}
+
+impl Message for ReadArchive {
+    type Result = Result<Vec<u8>, Error>;
+}
+
+impl Handler<ReadArchive> for ExportExecutor {
+    type Result = Result<Vec<u8>, Error>;
+
+    fn handle(&mut self, msg: ReadArchive, _: &mut Self::Context) -> Self::Result {
+        match self.storage.get(&msg.key) {
+            Ok(data) => Ok(data),
+            Err(ref e) if e.kind() == ::std::io::ErrorKind::NotFound => Err(error::ErrorNotFound("NotFound")),
+            Err(e) => Err(storage_error(&msg.request_id, &e)),
+        }
+    }
+}
+
+// Removes every avatar and archive stored for a deleted user.
+pub struct DeleteUserFiles {
+    pub request_id: String,
+    pub user_uuid: String,
+}
+
+impl Message for DeleteUserFiles {
+    type Result = Result<(), Error>;
+}
+
+impl Handler<DeleteUserFiles> for ExportExecutor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: DeleteUserFiles, _: &mut Self::Context) -> Self::Result {
+        for prefix in &[format!("avatars/{}", msg.user_uuid), format!("exports/{}", msg.user_uuid)] {
+            self.storage
+                .delete_prefix(prefix)
+                .map_err(|e| storage_error(&msg.request_id, &e))?;
+        }
+
+        Ok(())
+    }
+}
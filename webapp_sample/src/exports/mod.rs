@@ -0,0 +1,154 @@
+pub mod archives_message;
+
+use std::io::{self, Cursor, Write};
+use std::sync::Arc;
+
+use zip::{CompressionMethod, ZipWriter};
+use zip::result::{ZipError};
+use zip::write::{FileOptions};
+
+use actix::prelude::*;
+
+use db::{DbExecutor};
+use db::data_exports_message::{PersonalData};
+use lifecycle::{Drain};
+use storage::{Storage};
+
+// Builds personal data archives and serves them off the HTTP worker threads.
+pub struct ExportExecutor {
+    pub db: Addr<DbExecutor>,
+    pub storage: Arc<dyn Storage>,
+}
+
+impl Actor for ExportExecutor {
+    type Context = SyncContext<Self>;
+}
+
+impl Handler<Drain> for ExportExecutor {
+    type Result = ();
+
+    fn handle(&mut self, msg: Drain, _: &mut Self::Context) -> Self::Result {
+        msg.0.wait();
+    }
+}
+
+pub fn archive_key(user_uuid: &str, export_id: i32) -> String {
+    format!("exports/{}/{}.zip", user_uuid, export_id)
+}
+
+// A ZIP with one JSON file per kind of record, plus the avatar when there is one.
+// Password and session digests are left out; they are secrets, not personal data.
+pub fn archive(data: &PersonalData, avatar: Option<&[u8]>) -> io::Result<Vec<u8>> {
+    let user = &data.user;
+    let related = |users: &Vec<::models::User>| -> Vec<_> {
+        users.iter().map(|user| json!({"id": user.id, "name": user.name})).collect()
+    };
+    let sessions: Vec<_> = data.sessions
+        .iter()
+        .map(|(created_at, last_seen_at)| json!({"created_at": created_at, "last_seen_at": last_seen_at}))
+        .collect();
+
+    let files = vec![
+        ("profile.json", json!({
+            "id":                    user.id,
+            "uuid":                  user.uuid,
+            "name":                  user.name,
+            "email":                 user.email,
+            "created_at":            user.created_at,
+            "updated_at":            user.updated_at,
+            "avatar_key":            user.avatar_key,
+            "deletion_scheduled_at": user.deletion_scheduled_at,
+            "time_zone":             user.time_zone,
+        })),
+        ("microposts.json", json!(data.microposts)),
+        ("following.json", json!(related(&data.following))),
+        ("followers.json", json!(related(&data.followers))),
+        ("sessions.json", json!(sessions)),
+        ("data_exports.json", json!(data.data_exports)),
+    ];
+
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+
+    for (name, value) in files {
+        zip.start_file(name, options).map_err(zip_error)?;
+        zip.write_all(serde_json::to_string_pretty(&value)?.as_bytes())?;
+    }
+
+    if let Some(avatar) = avatar {
+        zip.start_file("avatar.png", options).map_err(zip_error)?;
+        zip.write_all(avatar)?;
+    }
+
+    Ok(zip.finish().map_err(zip_error)?.into_inner())
+}
+
+fn zip_error(e: ZipError) -> io::Error {
+    match e {
+        ZipError::Io(e) => e,
+        other           => io::Error::other(other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Read;
+
+    use chrono::NaiveDateTime;
+    use zip::ZipArchive;
+
+    use models::{User};
+
+    fn user(id: i32, name: &str) -> User {
+        let now = NaiveDateTime::from_timestamp(1_000_000, 0);
+        User {
+            id,
+            uuid: format!("uuid-{}", id),
+            name: name.to_string(),
+            email: format!("{}@example.com", name),
+            password_digest: "$argon2id$secret".to_string(),
+            session_digest: Some("$2b$05$secret".to_string()),
+            created_at: now,
+            updated_at: now,
+            avatar_key: None,
+            lock_version: 0,
+            deletion_scheduled_at: None,
+            admin: false,
+            time_zone: "Asia/Tokyo".to_string(),
+        }
+    }
+
+    #[test]
+    fn archive_holds_json_files_without_digests() {
+        let data = PersonalData {
+            user: user(1, "alice"),
+            microposts: Vec::new(),
+            following: vec![user(2, "bob")],
+            followers: Vec::new(),
+            sessions: Vec::new(),
+            data_exports: Vec::new(),
+        };
+
+        let bytes = archive(&data, Some(b"png")).unwrap();
+        let mut zip = ZipArchive::new(Cursor::new(bytes)).unwrap();
+
+        let mut names: Vec<String> = (0..zip.len()).map(|i| zip.by_index(i).unwrap().name().to_string()).collect();
+        names.sort();
+        assert_eq!(names, vec![
+            "avatar.png", "data_exports.json", "followers.json", "following.json",
+            "microposts.json", "profile.json", "sessions.json",
+        ]);
+
+        let mut profile = String::new();
+        zip.by_name("profile.json").unwrap().read_to_string(&mut profile).unwrap();
+        assert!(profile.contains("alice@example.com"));
+        assert!(!profile.contains("secret"));
+
+        let mut following = String::new();
+        zip.by_name("following.json").unwrap().read_to_string(&mut following).unwrap();
+        assert!(following.contains("bob"));
+        assert!(!following.contains("bob@example.com"));
+    }
+}
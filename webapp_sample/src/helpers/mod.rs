@@ -1 +1,4 @@
+pub mod pagination_helper;
+pub mod search_helper;
 pub mod sessions_helper;
+pub mod uploads_helper;
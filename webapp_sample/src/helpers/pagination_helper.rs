@@ -0,0 +1,59 @@
+pub const PER_PAGE: i64 = 20;
+// Keeps `(page - 1) * per_page` far from overflowing; later pages are empty anyway.
+pub const MAX_PAGE: i64 = 100_000;
+
+#[derive(Deserialize)]
+pub struct PageParam {
+    page: Option<i64>,
+}
+
+impl PageParam {
+    pub fn page(&self) -> i64 {
+        match self.page {
+            Some(page) if page > MAX_PAGE => MAX_PAGE,
+            Some(page) if page > 0        => page,
+            _                             => 1,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Pagination {
+    pub page: i64,
+    pub total_pages: i64,
+    pub prev_page: Option<i64>,
+    pub next_page: Option<i64>,
+}
+
+impl Pagination {
+    pub fn new(page: i64, per_page: i64, total: i64) -> Self {
+        let total_pages = if total == 0 { 1 } else { (total + per_page - 1) / per_page };
+
+        Pagination {
+            page,
+            total_pages,
+            prev_page: if page > 1 { Some(page - 1) } else { None },
+            next_page: if page < total_pages { Some(page + 1) } else { None },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pagination_links() {
+        assert_eq!(Pagination::new(1, 20, 0), Pagination{page: 1, total_pages: 1, prev_page: None, next_page: None});
+        assert_eq!(Pagination::new(1, 20, 21), Pagination{page: 1, total_pages: 2, prev_page: None, next_page: Some(2)});
+        assert_eq!(Pagination::new(2, 20, 40), Pagination{page: 2, total_pages: 2, prev_page: Some(1), next_page: None});
+    }
+
+    #[test]
+    fn page_is_clamped() {
+        assert_eq!(PageParam{page: None}.page(), 1);
+        assert_eq!(PageParam{page: Some(-3)}.page(), 1);
+        assert_eq!(PageParam{page: Some(7)}.page(), 7);
+        assert_eq!(PageParam{page: Some(i64::max_value())}.page(), MAX_PAGE);
+    }
+}
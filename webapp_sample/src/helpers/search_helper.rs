@@ -0,0 +1,85 @@
+use url::form_urlencoded;
+
+// The trigram tokenizer cannot match anything shorter than this.
+pub const MIN_TERM_CHARS: usize = 3;
+
+pub const HIGHLIGHT_START: char = '\u{2}';
+pub const HIGHLIGHT_END: char = '\u{3}';
+
+#[derive(Deserialize)]
+pub struct SearchParam {
+    q: Option<String>,
+}
+
+impl SearchParam {
+    pub fn q(&self) -> String {
+        match self.q {
+            Some(ref q) => q.trim().to_string(),
+            None        => String::new(),
+        }
+    }
+}
+
+// Turns free text into an FTS5 match expression. Every whitespace separated
+// term becomes a quoted phrase, so operators and syntax in the input are
+// matched literally, and all terms have to match.
+// Returns None when no term is long enough to search for.
+pub fn fts_query(input: &str) -> Option<String> {
+    let terms: Vec<String> = input
+        .split_whitespace()
+        .filter(|term| term.chars().count() >= MIN_TERM_CHARS)
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect();
+
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+// Escapes a snippet for HTML and turns the highlight markers into <mark> tags.
+pub fn highlight_html(snippet: &str) -> String {
+    let mut html = String::with_capacity(snippet.len());
+
+    for c in snippet.chars() {
+        match c {
+            HIGHLIGHT_START => html.push_str("<mark>"),
+            HIGHLIGHT_END   => html.push_str("</mark>"),
+            '&'             => html.push_str("&amp;"),
+            '<'             => html.push_str("&lt;"),
+            '>'             => html.push_str("&gt;"),
+            '"'             => html.push_str("&quot;"),
+            '\''            => html.push_str("&#x27;"),
+            _               => html.push(c),
+        }
+    }
+
+    html
+}
+
+// `q=...` for links that have to keep the current search, e.g. pagination.
+pub fn query_string(q: &str) -> String {
+    form_urlencoded::Serializer::new(String::new())
+        .append_pair("q", q)
+        .finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fts_query_quotes_terms() {
+        assert_eq!(fts_query("  alice  example "), Some("\"alice\" \"example\"".to_string()));
+        assert_eq!(fts_query("a\"b OR c*"), Some("\"a\"\"b\"".to_string()));
+        assert_eq!(fts_query("ab 山田"), None);
+        assert_eq!(fts_query("山田花"), Some("\"山田花\"".to_string()));
+    }
+
+    #[test]
+    fn highlight_html_escapes_snippet() {
+        let snippet = format!("{}<b>{}&x", HIGHLIGHT_START, HIGHLIGHT_END);
+        assert_eq!(highlight_html(&snippet), "<mark>&lt;b&gt;</mark>&amp;x");
+    }
+}
@@ -1,16 +1,15 @@
-use bcrypt::{verify};
 use rand::prelude::*;
 use rand::distributions::{Alphanumeric};
 
 use actix_web::*;
 use actix_web::middleware::session::{Session};
 
-use models::{User, UserSession};
+use models::{UserSession};
 
-const USER_SESSION_KEY: &str  = "USER_SESSION";
-const FLASH_MESSAGE_KEY: &str = "FLASH_MESSAGE";
+pub const USER_SESSION_KEY: &str = "USER_SESSION";
+const FLASH_MESSAGE_KEY: &str    = "FLASH_MESSAGE";
 
-#[derive(Serialize, Deserialize)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct FlashMessage {
     pub error_messages: Vec<String>,
 }
@@ -47,31 +46,19 @@ pub fn user_session(session: &Session) -> Result<Option<UserSession>, Error> {
     }
 }
 
-pub fn valid_session_id(user_session: &UserSession, digest: &str) -> Result<bool, Error> {
-    match verify(&user_session.session_id, digest){
-        Ok(true) => Ok(true),
-        _        => Err(error::ErrorForbidden("Forbidden")),
-    } 
+// A session with a fresh id. Persisting its digest invalidates every other
+// session of that user; store it with `set_user_session` once that succeeded.
+pub fn new_user_session(user_id: i32) -> UserSession {
+    UserSession {
+        user_id,
+        session_id: create_session_id(),
+    }
 }
 
-pub fn signin(user: &User, password: &str, session: &Session) -> Result<UserSession, Error> {
-    match verify(password, &user.password_digest.clone()) {
-        Ok(true) => {
-            let user_session = UserSession {
-                user_id: user.id,
-                session_id: create_session_id(),
-            };
-
-            session
-                .set(USER_SESSION_KEY, user_session.clone())
-                .expect("error set session id");
-
-            Ok(user_session)
-        },
-        _ => {
-            Err(error::ErrorForbidden("Forbidden"))
-        }
-    }
+pub fn set_user_session(session: &Session, user_session: UserSession) {
+    session
+        .set(USER_SESSION_KEY, user_session)
+        .expect("error set session id");
 }
 
 pub fn signout(session: &Session) {
@@ -89,6 +76,6 @@ fn random_string(n: usize) -> String {
         .collect()
 }
 
-fn create_session_id() -> String {
+pub fn create_session_id() -> String {
     random_string(30)
 }
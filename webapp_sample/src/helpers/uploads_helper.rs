@@ -0,0 +1,74 @@
+use futures::{future, Future, Stream};
+
+use actix_web::{HttpRequest, HttpMessage};
+use actix_web::multipart::{MultipartItem};
+
+#[derive(Debug, PartialEq)]
+pub enum UploadError {
+    Missing,
+    TooLarge,
+    UnsupportedFormat,
+    Invalid,
+    Internal,
+}
+
+impl UploadError {
+    pub fn message(&self) -> &'static str {
+        match *self {
+            UploadError::Missing           => "ファイルを選択してください",
+            UploadError::TooLarge          => "ファイルサイズが大きすぎます",
+            UploadError::UnsupportedFormat => "画像はPNG、JPEG、GIFのいずれかを選択してください",
+            UploadError::Invalid           => "画像を読み込めませんでした",
+            UploadError::Internal          => "アップロードに失敗しました",
+        }
+    }
+}
+
+// Reads the first multipart field called `name` into memory, draining the others.
+// Fails as soon as that field grows beyond `limit` bytes.
+pub fn read_file_field<S: 'static>(req: &HttpRequest<S>, name: &'static str, limit: usize) -> Box<dyn Future<Item = Vec<u8>, Error = UploadError>> {
+    Box::new(
+        req
+            .multipart()
+            .map_err(|_| UploadError::Invalid)
+            .fold(None, move |found: Option<Vec<u8>>, item| -> Box<dyn Future<Item = Option<Vec<u8>>, Error = UploadError>> {
+                match item {
+                    MultipartItem::Field(field) => {
+                        let matches = found.is_none() && field
+                            .content_disposition()
+                            .map(|disposition| disposition.get_name() == Some(name))
+                            .unwrap_or(false);
+
+                        if matches {
+                            Box::new(
+                                field
+                                    .map_err(|_| UploadError::Invalid)
+                                    .fold(Vec::new(), move |mut data, chunk| {
+                                        if data.len() + chunk.len() > limit {
+                                            Err(UploadError::TooLarge)
+                                        } else {
+                                            data.extend_from_slice(&chunk);
+                                            Ok(data)
+                                        }
+                                    })
+                                    .map(Some)
+                            )
+                        } else {
+                            Box::new(
+                                field
+                                    .map_err(|_| UploadError::Invalid)
+                                    .for_each(|_| Ok(()))
+                                    .map(move |_| found)
+                            )
+                        }
+                    },
+                    MultipartItem::Nested(_) => Box::new(future::err(UploadError::Invalid)),
+                }
+            })
+            .and_then(|found| match found {
+                Some(ref data) if data.is_empty() => Err(UploadError::Missing),
+                Some(data)                        => Ok(data),
+                None                              => Err(UploadError::Missing),
+            })
+    )
+}
@@ -0,0 +1,156 @@
+use uuid::Uuid;
+
+use actix::prelude::*;
+
+use helpers::uploads_helper::{UploadError};
+use images::{self, ImageExecutor, AVATAR_SIZES};
+
+pub fn avatar_path(key: &str, size: u32) -> String {
+    format!("{}/{}.png", key, size)
+}
+
+pub struct StoreAvatar {
+    pub request_id: String,
+    pub user_uuid: String,
+    pub data: Vec<u8>,
+}
+
+impl Message for StoreAvatar {
+    type Result = Result<String, UploadError>;
+}
+
+impl Handler<StoreAvatar> for ImageExecutor {
+    type Result = Result<String, UploadError>;
+
+    fn handle(&mut self, msg: StoreAvatar, _: &mut Self::Context) -> Self::Result {
+        let thumbnails = images::thumbnails(&msg.data, &AVATAR_SIZES)
+            .map_err(|e| {
+                info!("{}", json!({
+                    "event":      "avatar_rejected",
+                    "request_id": msg.request_id,
+                    "error":      e.to_string(),
+                }));
+                UploadError::Invalid
+            })?;
+
+        let key = format!("avatars/{}/{}", msg.user_uuid, Uuid::new_v4().to_simple());
+
+        for (size, png) in thumbnails {
+            self.0
+                .put(&avatar_path(&key, size), &png)
+                .map_err(|e| {
+                    error!("{}", json!({
+                        "event":      "storage_error",
+                        "request_id": msg.request_id,
+                        "error":      e.to_string(),
+                    }));
+                    UploadError::Internal
+                })?;
+        }
+
+        Ok(key)
+    }
+}
+
+pub struct ReadAvatar {
+    pub request_id: String,
+    pub key: String,
+    pub size: u32,
+}
+
+impl Message for ReadAvatar {
+    type Result = Result<Option<Vec<u8>>, UploadError>;
+}
+
+impl Handler<ReadAvatar> for ImageExecutor {
+    type Result = Result<Option<Vec<u8>>, UploadError>;
+
+    fn handle(&mut self, msg: ReadAvatar, _: &mut Self::Context) -> Self::Result {
+        if !AVATAR_SIZES.contains(&msg.size) {
+            return Ok(None);
+        }
+
+        match self.0.get(&avatar_path(&msg.key, msg.size)) {
+            Ok(data) => Ok(Some(data)),
+            Err(ref e) if e.kind() == ::std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => {
+                error!("{}", json!({
+                    "event":      "storage_error",
+                    "request_id": msg.request_id,
+                    "error":      e.to_string(),
+                }));
+                Err(UploadError::Internal)
+            },
+        }
+    }
+}
+
+pub struct DeleteAvatar {
+    pub request_id: String,
+    pub key: String,
+}
+
+impl Message for DeleteAvatar {
+    type Result = Result<(), UploadError>;
+}
+
+impl Handler<DeleteAvatar> for ImageExecutor {
+    type Result = Result<(), UploadError>;
+
+    fn handle(&mut self, msg: DeleteAvatar, _: &mut Self::Context) -> Self::Result {
+        self.0
+            .delete_prefix(&msg.key)
+            .map_err(|e| {
+                error!("{}", json!({
+                    "event":      "storage_error",
+                    "request_id": msg.request_id,
+                    "error":      e.to_string(),
+                }));
+                UploadError::Internal
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use image::{DynamicImage, ImageOutputFormat, RgbImage};
+
+    use storage::memory::{MemoryStorage};
+
+    #[test]
+    fn store_read_and_delete_avatar() {
+        let storage = Arc::new(MemoryStorage::default());
+        let mut data = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::new(64, 64))
+            .write_to(&mut data, ImageOutputFormat::Png)
+            .unwrap();
+
+        let mut system = System::new("test");
+        let executor = {
+            let storage = storage.clone();
+            SyncArbiter::start(1, move || ImageExecutor(storage.clone()))
+        };
+
+        let key = system
+            .block_on(executor.send(StoreAvatar { request_id: "test".to_string(), user_uuid: "uuid".to_string(), data }))
+            .unwrap()
+            .unwrap();
+        assert_eq!(storage.blobs.lock().unwrap().len(), AVATAR_SIZES.len());
+
+        let thumb = system
+            .block_on(executor.send(ReadAvatar { request_id: "test".to_string(), key: key.clone(), size: 40 }))
+            .unwrap()
+            .unwrap();
+        assert!(thumb.is_some());
+
+        system
+            .block_on(executor.send(DeleteAvatar { request_id: "test".to_string(), key }))
+            .unwrap()
+            .unwrap();
+        assert!(storage.blobs.lock().unwrap().is_empty());
+    }
+}
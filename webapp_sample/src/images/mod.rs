@@ -0,0 +1,107 @@
+pub mod avatars_message;
+
+use std::io::{Cursor};
+use std::sync::Arc;
+
+use image::{ImageError, ImageFormat, ImageOutputFormat};
+use image::error::{LimitError, LimitErrorKind};
+use image::imageops::{FilterType};
+use image::io::{Reader};
+
+use actix::prelude::*;
+
+use lifecycle::{Drain};
+use storage::{Storage};
+
+pub const MAX_UPLOAD_BYTES: usize = 2 * 1024 * 1024;
+pub const MAX_DIMENSION: u32 = 4096;
+pub const AVATAR_SIZES: [u32; 3] = [40, 128, 256];
+
+// Resizes and stores images off the HTTP worker threads.
+pub struct ImageExecutor(
+    pub Arc<dyn Storage>
+);
+
+impl Actor for ImageExecutor {
+    type Context = SyncContext<Self>;
+}
+
+impl Handler<Drain> for ImageExecutor {
+    type Result = ();
+
+    fn handle(&mut self, msg: Drain, _: &mut Self::Context) -> Self::Result {
+        msg.0.wait();
+    }
+}
+
+// Detects the format from the file contents; the client supplied content type is ignored.
+pub fn sniff_format(data: &[u8]) -> Option<ImageFormat> {
+    match image::guess_format(data) {
+        Ok(format @ ImageFormat::Png)  => Some(format),
+        Ok(format @ ImageFormat::Jpeg) => Some(format),
+        Ok(format @ ImageFormat::Gif)  => Some(format),
+        _                              => None,
+    }
+}
+
+// Crops the image to a square and encodes one PNG per entry of `sizes`.
+pub fn thumbnails(data: &[u8], sizes: &[u32]) -> Result<Vec<(u32, Vec<u8>)>, ImageError> {
+    let (width, height) = Reader::new(Cursor::new(data))
+        .with_guessed_format()?
+        .into_dimensions()?;
+
+    if width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return Err(ImageError::Limits(LimitError::from_kind(LimitErrorKind::DimensionError)));
+    }
+
+    let original = image::load_from_memory(data)?;
+
+    sizes
+        .iter()
+        .map(|&size| {
+            let mut png = Vec::new();
+            original
+                .resize_to_fill(size, size, FilterType::Lanczos3)
+                .write_to(&mut png, ImageOutputFormat::Png)?;
+            Ok((size, png))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use image::{DynamicImage, GenericImageView, RgbImage};
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::new(width, height))
+            .write_to(&mut data, ImageOutputFormat::Png)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn sniff_format_ignores_non_images() {
+        assert_eq!(sniff_format(&png(1, 1)), Some(ImageFormat::Png));
+        assert_eq!(sniff_format(b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>"), None);
+        assert_eq!(sniff_format(b""), None);
+    }
+
+    #[test]
+    fn thumbnails_are_square() {
+        let thumbs = thumbnails(&png(300, 200), &AVATAR_SIZES).unwrap();
+
+        assert_eq!(thumbs.len(), AVATAR_SIZES.len());
+        for (size, data) in thumbs {
+            let thumb = image::load_from_memory(&data).unwrap();
+            assert_eq!((thumb.width(), thumb.height()), (size, size));
+        }
+    }
+
+    #[test]
+    fn thumbnails_reject_huge_images() {
+        assert!(thumbnails(&png(MAX_DIMENSION + 1, 1), &AVATAR_SIZES).is_err());
+    }
+}
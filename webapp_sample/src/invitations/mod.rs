@@ -0,0 +1,115 @@
+use std::iter;
+
+use blake2::{Blake2b, Digest};
+use blake2::digest::consts::{U32};
+use chrono::{NaiveDateTime};
+use rand::prelude::*;
+use rand::distributions::{Alphanumeric};
+
+use models::{Invitation};
+
+type Blake2b256 = Blake2b<U32>;
+
+pub const ROLE_MEMBER: &str = "member";
+pub const ROLE_ADMIN: &str = "admin";
+
+pub const ROLES: [&str; 2] = [ROLE_MEMBER, ROLE_ADMIN];
+
+const TOKEN_LEN: usize = 40;
+
+// The secret in an invitation link. Only its digest is stored, so a link
+// can be shown once and is lost afterwards; resending makes a new one.
+pub fn create_token() -> String {
+    let mut rng = thread_rng();
+    iter::repeat(())
+        .map(|()| rng.sample(Alphanumeric))
+        .take(TOKEN_LEN)
+        .collect()
+}
+
+pub fn valid_token(token: &str) -> bool {
+    token.len() == TOKEN_LEN && token.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+// Tokens are random like session ids, so a fast hash is enough, and the
+// digest can be looked up directly.
+pub fn token_digest(token: &str) -> String {
+    Blake2b256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// Where the invitee signs up.
+pub fn url(scheme: &str, host: &str, token: &str) -> String {
+    format!("{}://{}/users/new?invitation={}", scheme, host, token)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Pending,
+    Used,
+    Revoked,
+    Expired,
+}
+
+impl Status {
+    pub fn of(invitation: &Invitation, now: NaiveDateTime) -> Self {
+        if invitation.accepted_at.is_some() {
+            Status::Used
+        } else if invitation.revoked_at.is_some() {
+            Status::Revoked
+        } else if invitation.expires_at <= now {
+            Status::Expired
+        } else {
+            Status::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{Duration};
+
+    #[test]
+    fn tokens_are_random_and_stored_as_digests() {
+        let token = create_token();
+        assert!(valid_token(&token));
+        assert_ne!(token, create_token());
+        assert!(!valid_token(&token[1..]));
+        assert!(!valid_token(&format!("{}/", &token[1..])));
+
+        assert_eq!(token_digest(&token).len(), 64);
+        assert_eq!(token_digest(&token), token_digest(&token));
+        assert_ne!(token_digest(&token), token_digest(&create_token()));
+    }
+
+    #[test]
+    fn status_prefers_used_then_revoked_then_expired() {
+        let now = NaiveDateTime::from_timestamp(1_000_000, 0);
+        let mut invitation = Invitation {
+            id: 1,
+            email: "alice@example.com".to_string(),
+            role: ROLE_MEMBER.to_string(),
+            token_digest: token_digest("token"),
+            invited_by: Some(1),
+            user_id: None,
+            expires_at: now + Duration::days(1),
+            accepted_at: None,
+            revoked_at: None,
+            created_at: now,
+            updated_at: now,
+        };
+        assert_eq!(Status::of(&invitation, now), Status::Pending);
+        assert_eq!(Status::of(&invitation, now + Duration::days(1)), Status::Expired);
+
+        invitation.revoked_at = Some(now);
+        assert_eq!(Status::of(&invitation, now + Duration::days(1)), Status::Revoked);
+
+        invitation.accepted_at = Some(now);
+        assert_eq!(Status::of(&invitation, now), Status::Used);
+    }
+}
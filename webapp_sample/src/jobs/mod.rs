@@ -0,0 +1,281 @@
+use std::cmp;
+use std::time::Duration;
+
+use chrono::{self, Utc};
+use futures::Future;
+use uuid::Uuid;
+
+use actix::fut;
+use actix::prelude::*;
+
+use db::{DbExecutor};
+use db::data_exports_message::{FinishDataExport};
+use db::jobs_message::{self, ClaimJobs, FinishJob, ReleaseJobs};
+use exports::{ExportExecutor};
+use exports::archives_message::{BuildArchive, DeleteUserFiles};
+use lifecycle::{Drain};
+use models;
+
+pub const MAX_ATTEMPTS: i32 = 5;
+
+const BACKOFF_BASE_SECS: i64 = 30;
+const BACKOFF_MAX_SECS: i64 = 60 * 60;
+
+// Work done outside request handling. Stored as JSON in `jobs.payload`,
+// e.g. `{"kind": "delete_user_files", "user_uuid": "..."}`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Task {
+    BuildDataExport { export_id: i32, user_id: i32 },
+    DeleteUserFiles { user_uuid: String },
+}
+
+impl Task {
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            Task::BuildDataExport{..} => "build_data_export",
+            Task::DeleteUserFiles{..} => "delete_user_files",
+        }
+    }
+}
+
+// The wait before the next run after `attempts` failed ones:
+// 30 seconds, doubling each time, at most an hour.
+pub fn backoff(attempts: i32) -> chrono::Duration {
+    let exponent = (attempts - 1).clamp(0, 16) as u32;
+    chrono::Duration::seconds(cmp::min(BACKOFF_BASE_SECS * 2i64.pow(exponent), BACKOFF_MAX_SECS))
+}
+
+// Runs one claimed job at a time; start as many as jobs may run concurrently.
+pub struct JobWorker {
+    pub db: Addr<DbExecutor>,
+    pub exports: Addr<ExportExecutor>,
+}
+
+impl Actor for JobWorker {
+    type Context = SyncContext<Self>;
+}
+
+impl Handler<Drain> for JobWorker {
+    type Result = ();
+
+    fn handle(&mut self, msg: Drain, _: &mut Self::Context) -> Self::Result {
+        msg.0.wait();
+    }
+}
+
+pub struct RunJob(pub models::Job);
+
+impl Message for RunJob {
+    type Result = ();
+}
+
+impl Handler<RunJob> for JobWorker {
+    type Result = ();
+
+    fn handle(&mut self, msg: RunJob, _: &mut Self::Context) -> Self::Result {
+        let RunJob(job) = msg;
+        let request_id = format!("job-{}-{}", job.id, Uuid::new_v4());
+
+        let task = serde_json::from_str::<Task>(&job.payload);
+        let outcome = match task {
+            Ok(ref task) => self.perform(&request_id, task),
+            Err(ref e)   => Err(format!("unreadable payload: {}", e)),
+        };
+
+        let finished = self.db
+            .send(FinishJob{
+                request_id: request_id.clone(),
+                id: job.id,
+                error: outcome.err(),
+                now: Utc::now().naive_utc(),
+            })
+            .wait();
+
+        let finished = match finished {
+            Ok(Ok(finished)) => finished,
+            _                => return,
+        };
+
+        info!("{}", json!({
+            "event":      "job_finished",
+            "request_id": request_id,
+            "job_id":     finished.id,
+            "kind":       finished.kind,
+            "attempt":    finished.attempts,
+            "status":     finished.status,
+            "error":      finished.last_error,
+        }));
+
+        if finished.status == jobs_message::STATUS_DEAD {
+            if let Ok(ref task) = task {
+                self.dead_lettered(&request_id, task);
+            }
+        }
+    }
+}
+
+impl JobWorker {
+    fn perform(&self, request_id: &str, task: &Task) -> Result<(), String> {
+        let result = match *task {
+            Task::BuildDataExport{export_id, user_id} => self.exports
+                .send(BuildArchive{request_id: request_id.to_string(), export_id, user_id})
+                .wait(),
+            Task::DeleteUserFiles{ref user_uuid}      => self.exports
+                .send(DeleteUserFiles{request_id: request_id.to_string(), user_uuid: user_uuid.clone()})
+                .wait(),
+        };
+
+        match result {
+            Ok(Ok(()))  => Ok(()),
+            Ok(Err(e))  => Err(e.to_string()),
+            Err(e)      => Err(e.to_string()),
+        }
+    }
+
+    // Tells whoever waits on a job that it will not happen.
+    fn dead_lettered(&self, request_id: &str, task: &Task) {
+        if let Task::BuildDataExport{export_id, ..} = *task {
+            let _ = self.db
+                .send(FinishDataExport{request_id: request_id.to_string(), id: export_id, storage_key: None})
+                .wait();
+        }
+    }
+}
+
+// Every `interval`, claims as many due jobs as there are idle workers and
+// hands them out. Jobs that have been running for longer than `lease` are
+// taken to be left by a process that stopped, and queued again, at start and
+// then every `lease`.
+pub struct JobQueue {
+    db: Addr<DbExecutor>,
+    workers: Addr<JobWorker>,
+    concurrency: usize,
+    interval: Duration,
+    lease: Duration,
+    running: usize,
+    claiming: bool,
+    paused: bool,
+}
+
+impl JobQueue {
+    pub fn new(db: Addr<DbExecutor>, workers: Addr<JobWorker>, concurrency: usize, interval: Duration, lease: Duration) -> Self {
+        JobQueue {
+            db,
+            workers,
+            concurrency,
+            interval,
+            lease,
+            running: 0,
+            claiming: false,
+            paused: false,
+        }
+    }
+}
+
+impl Actor for JobQueue {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.release().wait(ctx);
+
+        ctx.run_interval(self.lease, |act, ctx| act.release().spawn(ctx));
+        ctx.run_interval(self.interval, |act, ctx| act.claim(ctx));
+    }
+}
+
+impl JobQueue {
+    fn release(&self) -> impl ActorFuture<Item = (), Error = (), Actor = Self> {
+        let request_id = Uuid::new_v4().to_string();
+        let lease = chrono::Duration::from_std(self.lease).unwrap_or_else(|_| chrono::Duration::max_value());
+
+        self.db
+            .send(ReleaseJobs{
+                request_id: request_id.clone(),
+                locked_before: Utc::now().naive_utc() - lease,
+            })
+            .into_actor(self)
+            .then(move |res, _, _| {
+                if let Ok(Ok(released)) = res {
+                    if released > 0 {
+                        info!("{}", json!({
+                            "event":      "jobs_released",
+                            "request_id": request_id,
+                            "count":      released,
+                        }));
+                    }
+                }
+                fut::ok(())
+            })
+    }
+
+    fn claim(&mut self, ctx: &mut Context<Self>) {
+        if self.paused || self.claiming || self.running >= self.concurrency {
+            return;
+        }
+        self.claiming = true;
+
+        self.db
+            .send(ClaimJobs{
+                request_id: Uuid::new_v4().to_string(),
+                limit: (self.concurrency - self.running) as i64,
+                now: Utc::now().naive_utc(),
+            })
+            .into_actor(self)
+            .then(|res, act, ctx| {
+                act.claiming = false;
+
+                for job in res.ok().and_then(Result::ok).unwrap_or_default() {
+                    act.running += 1;
+                    act.workers
+                        .send(RunJob(job))
+                        .into_actor(act)
+                        .then(|_, act, _| {
+                            act.running -= 1;
+                            fut::ok(())
+                        })
+                        .spawn(ctx);
+                }
+                fut::ok(())
+            })
+            .spawn(ctx);
+    }
+}
+
+// Stops claiming jobs, so the workers can be drained at shutdown.
+pub struct Pause;
+
+impl Message for Pause {
+    type Result = ();
+}
+
+impl Handler<Pause> for JobQueue {
+    type Result = ();
+
+    fn handle(&mut self, _: Pause, _: &mut Self::Context) -> Self::Result {
+        self.paused = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_an_hour() {
+        assert_eq!(backoff(1), chrono::Duration::seconds(30));
+        assert_eq!(backoff(2), chrono::Duration::seconds(60));
+        assert_eq!(backoff(4), chrono::Duration::seconds(240));
+        assert_eq!(backoff(8), chrono::Duration::hours(1));
+        assert_eq!(backoff(100), chrono::Duration::hours(1));
+    }
+
+    #[test]
+    fn task_payload_is_tagged_with_its_kind() {
+        let task = Task::DeleteUserFiles{user_uuid: "u-1".to_string()};
+        let payload = serde_json::to_string(&task).unwrap();
+
+        assert_eq!(payload, r#"{"kind":"delete_user_files","user_uuid":"u-1"}"#);
+        assert_eq!(serde_json::from_str::<Task>(&payload).unwrap(), task);
+    }
+}
@@ -0,0 +1,61 @@
+extern crate argon2;
+extern crate bcrypt;
+extern crate blake2;
+extern crate brotli2;
+extern crate chrono;
+extern crate chrono_tz;
+extern crate env_logger;
+extern crate failure;
+extern crate flate2;
+extern crate handlebars;
+extern crate image;
+#[macro_use]
+extern crate log;
+extern crate rand;
+extern crate regex;
+extern crate rustls;
+extern crate url;
+extern crate uuid;
+extern crate webpki;
+extern crate zip;
+
+extern crate futures;
+#[macro_use]
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate serde_json;
+
+extern crate actix;
+extern crate actix_web;
+
+#[macro_use]
+extern crate diesel;
+extern crate r2d2;
+extern crate r2d2_diesel;
+
+pub mod db;
+pub mod models;
+pub mod schema;
+pub mod accounts;
+pub mod assets;
+pub mod context;
+pub mod controllers;
+pub mod events;
+pub mod exports;
+pub mod helpers;
+pub mod images;
+pub mod invitations;
+pub mod jobs;
+pub mod lifecycle;
+pub mod logging;
+pub mod middleware;
+pub mod openapi;
+pub mod passwords;
+pub mod sessions;
+pub mod settings;
+pub mod storage;
+pub mod time_zones;
+pub mod tls;
+
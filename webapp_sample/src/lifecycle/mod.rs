@@ -0,0 +1,153 @@
+use std::sync::{Arc, Barrier};
+
+use futures::future::{join_all, Future};
+
+use actix::fut;
+use actix::prelude::*;
+use actix::actors::signal::{ProcessSignals, Signal, SignalType, Subscribe};
+use actix_web::server::{Server, StopServer};
+
+use db::{DbExecutor};
+use exports::{ExportExecutor};
+use images::{ImageExecutor};
+use jobs::{JobQueue, JobWorker, Pause};
+use settings::{EnvVars, Settings, SharedSettings, StartEnv};
+use tls::{Certificates};
+
+// Sent once to every thread of a SyncArbiter. Each handler waits until all of
+// them have arrived, so when every reply is in, all work queued before the
+// drain has finished and the threads are idle.
+pub struct Drain(pub Arc<Barrier>);
+
+impl Message for Drain {
+    type Result = ();
+}
+
+fn drain<A>(addr: Addr<A>, threads: usize) -> impl Future<Item = (), Error = MailboxError>
+where
+    A: Actor<Context = SyncContext<A>> + Handler<Drain>,
+{
+    let barrier = Arc::new(Barrier::new(threads));
+
+    let replies: Vec<_> = (0..threads)
+        .map(|_| addr.send(Drain(barrier.clone())))
+        .collect();
+
+    join_all(replies).map(|_| ())
+}
+
+// Handles process signals in place of actix-web's defaults.
+// SIGTERM and SIGINT stop accepting connections, let in-flight requests finish
+// within the server's shutdown timeout, drain the sync actors and stop the system.
+// The job queue stops handing out jobs, then job workers are drained first and
+// exports next, since both still need the database.
+// SIGQUIT stops immediately. SIGHUP reloads settings from `.env` and the environment,
+// and the TLS certificate from its files.
+pub struct Lifecycle {
+    pub server: Addr<Server>,
+    pub db: Addr<DbExecutor>,
+    pub db_threads: usize,
+    pub images: Addr<ImageExecutor>,
+    pub image_threads: usize,
+    pub exports: Addr<ExportExecutor>,
+    pub export_threads: usize,
+    pub job_queue: Addr<JobQueue>,
+    pub job_workers: Addr<JobWorker>,
+    pub job_worker_threads: usize,
+    pub settings: SharedSettings,
+    pub start_env: StartEnv,
+    // The plain HTTP listener that redirects to HTTPS, when TLS is on.
+    pub redirect_server: Option<Addr<Server>>,
+    pub certificates: Option<Arc<Certificates>>,
+    pub stopping: bool,
+}
+
+impl Actor for Lifecycle {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ProcessSignals::from_registry().do_send(Subscribe(ctx.address().recipient()));
+    }
+}
+
+impl Lifecycle {
+    fn shutdown(&mut self, ctx: &mut Context<Self>) {
+        if self.stopping {
+            return;
+        }
+        self.stopping = true;
+
+        info!("{}", json!({"event": "shutdown_started"}));
+
+        let db = self.db.clone();
+        let db_threads = self.db_threads;
+        let images = self.images.clone();
+        let image_threads = self.image_threads;
+        let exports = self.exports.clone();
+        let export_threads = self.export_threads;
+        let job_queue = self.job_queue.clone();
+        let job_workers = self.job_workers.clone();
+        let job_worker_threads = self.job_worker_threads;
+
+        if let Some(ref redirect_server) = self.redirect_server {
+            redirect_server.do_send(StopServer{graceful: false});
+        }
+
+        self.server
+            .send(StopServer{graceful: true})
+            .then(move |_| {
+                info!("{}", json!({"event": "server_stopped"}));
+                job_queue.send(Pause)
+            })
+            .and_then(move |_| drain(job_workers, job_worker_threads))
+            .and_then(move |_| drain(exports, export_threads))
+            .and_then(move |_| drain(db, db_threads).join(drain(images, image_threads)))
+            .into_actor(self)
+            .then(|res, _, _| {
+                info!("{}", json!({
+                    "event":   "shutdown_finished",
+                    "drained": res.is_ok(),
+                }));
+                System::current().stop();
+                fut::ok(())
+            })
+            .spawn(ctx);
+    }
+
+    fn reload(&self) {
+        let vars = EnvVars::with_file(".env", &self.start_env).unwrap_or_else(|e| {
+            warn!("{}", json!({
+                "event": "reload_env_file_failed",
+                "error": e.to_string(),
+            }));
+            EnvVars::process()
+        });
+
+        self.settings.replace(Settings::from_vars(&vars));
+
+        info!("{}", json!({"event": "settings_reloaded"}));
+
+        if let Some(ref certificates) = self.certificates {
+            match certificates.reload() {
+                Ok(())  => info!("{}", json!({"event": "certificate_reloaded"})),
+                Err(e)  => warn!("{}", json!({
+                    "event": "certificate_reload_failed",
+                    "error": e.to_string(),
+                })),
+            }
+        }
+    }
+}
+
+impl Handler<Signal> for Lifecycle {
+    type Result = ();
+
+    fn handle(&mut self, msg: Signal, ctx: &mut Self::Context) {
+        match msg.0 {
+            SignalType::Term | SignalType::Int => self.shutdown(ctx),
+            SignalType::Quit                   => System::current().stop(),
+            SignalType::Hup                    => self.reload(),
+            _                                  => (),
+        }
+    }
+}
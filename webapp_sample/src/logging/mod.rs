@@ -0,0 +1,41 @@
+use std::env;
+use std::io::Write;
+
+use chrono::Local;
+use env_logger::{Builder};
+use log::{LevelFilter};
+use serde_json::value::{Map, Value};
+
+// Writes every record as a single JSON line.
+// Records whose message is a JSON object (e.g. `info!("{}", json!({...}))`)
+// have their fields merged into the line instead of being quoted as a string.
+pub fn init() {
+    let mut builder = Builder::new();
+
+    builder
+        .format(|buf, record| {
+            let message = record.args().to_string();
+
+            let mut line = match serde_json::from_str::<Value>(&message) {
+                Ok(Value::Object(fields)) => fields,
+                _ => {
+                    let mut fields = Map::new();
+                    fields.insert("message".to_string(), Value::String(message));
+                    fields
+                },
+            };
+
+            line.insert("timestamp".to_string(), Value::String(Local::now().to_rfc3339()));
+            line.insert("level".to_string(), Value::String(record.level().to_string()));
+            line.insert("target".to_string(), Value::String(record.target().to_string()));
+
+            writeln!(buf, "{}", Value::Object(line))
+        })
+        .filter(None, LevelFilter::Info);
+
+    if let Ok(filters) = env::var("RUST_LOG") {
+        builder.parse(&filters);
+    }
+
+    builder.init();
+}
@@ -1,200 +1,375 @@
-extern crate bcrypt;
-extern crate chrono;
 extern crate dotenv;
-extern crate env_logger;
-extern crate failure;
-extern crate handlebars;
+extern crate listenfd;
+#[macro_use]
 extern crate log;
-extern crate rand;
-extern crate regex;
-extern crate uuid;
-
-extern crate futures;
 #[macro_use]
-extern crate serde_derive;
 extern crate serde_json;
 
 extern crate actix;
 extern crate actix_web;
 
-#[macro_use]
 extern crate diesel;
 extern crate r2d2;
 extern crate r2d2_diesel;
 
-mod db;
-mod models;
-mod schema;
-mod context;
-mod controllers;
-mod helpers;
-mod middleware;
+extern crate webapp_sample;
 
 use std::env;
-use std::io::Write;
+use std::net::TcpListener;
+use std::process;
+use std::sync::Arc;
+use std::time::Duration;
 
-use chrono::Local;
 use dotenv::dotenv;
-use log::LevelFilter;
+use listenfd::ListenFd;
 
 use actix::prelude::*;
-use actix_web::{server, fs, App};
+use actix_web::{server, App};
 use actix_web::http::{Method};
-use actix_web::middleware::{Logger};
-use actix_web::middleware::session::{SessionStorage, CookieSessionBackend};
+use actix_web::middleware::session::{SessionStorage};
 
 use diesel::prelude::*;
 use r2d2_diesel::ConnectionManager;
 
-use db::{DbExecutor};
-use context::{Context};
-use middleware::{Authenticate};
+use webapp_sample::{controllers, logging, settings};
+use webapp_sample::accounts::{AccountPurger};
+use webapp_sample::assets::{AssetFiles, Assets};
+use webapp_sample::db::{DbExecutor, SqlitePragmas};
+use webapp_sample::events::{Broadcaster};
+use webapp_sample::exports::{ExportExecutor};
+use webapp_sample::images::{ImageExecutor};
+use webapp_sample::jobs::{JobQueue, JobWorker};
+use webapp_sample::lifecycle::{Lifecycle};
+use webapp_sample::sessions::{DbSessionBackend};
+use webapp_sample::sessions::cache::{SessionCache};
+use webapp_sample::sessions::purger::{SessionPurger};
+use webapp_sample::settings::{Settings, SharedSettings, StartEnv};
+use webapp_sample::storage::{LocalStorage};
+use webapp_sample::tls::{self, Certificates, TlsConfig};
+use webapp_sample::context::{Context};
+use webapp_sample::middleware::{Authenticate};
+use webapp_sample::middleware::access;
+use webapp_sample::middleware::access_log::{AccessLog};
+use webapp_sample::middleware::format::{self, JsonErrors};
+use webapp_sample::middleware::rate_limit::{RateLimit, Quota};
+use webapp_sample::middleware::request_id::{AssignRequestId};
+use webapp_sample::middleware::security_headers::{SecurityHeaders};
+
+// Every page is also served as JSON under its path with a `.json` suffix,
+// registered first so that `/users/1.json` is not taken for `/users/{id}`.
+macro_rules! route {
+    ($app:expr, $path:expr, $method:expr, $handler:expr,) => {
+        $app
+            .route(&json_path($path), $method, $handler)
+            .route($path, $method, $handler)
+    };
+}
+
+// The route table: `page` routes get a JSON twin through `route!`, `raw`
+// ones are registered as they are. Also lists the routes for the test that
+// checks them against the OpenAPI document.
+macro_rules! routes {
+    ($($kind:ident $method:ident $path:expr => $handler:path;)*) => {
+        fn register_routes(mut app: App<Context>) -> App<Context> {
+            $(app = routes!(@$kind app, $path, Method::$method, $handler);)*
+            app
+        }
+
+        #[cfg(test)]
+        fn route_table() -> Vec<(Method, &'static str)> {
+            vec![$((Method::$method, $path)),*]
+        }
+    };
+    (@page $app:expr, $path:expr, $method:expr, $handler:path) => {
+        route!($app, $path, $method, $handler,)
+    };
+    (@raw $app:expr, $path:expr, $method:expr, $handler:path) => {
+        $app.route($path, $method, $handler)
+    };
+}
+
+fn json_path(path: &str) -> String {
+    match path {
+        "/"  => format!("/index{}", format::JSON_SUFFIX),
+        path => format!("{}{}", path, format::JSON_SUFFIX),
+    }
+}
+
+// Cookies get `Secure` when the server speaks HTTPS.
+fn app(context: Context, secure: bool) -> App<Context> {
+    let rate_limit_store = context.rate_limit_store.clone();
+    let settings = context.settings.clone();
+    let assets = context.assets.clone();
 
-fn app(context: Context) -> App<Context> {
     let mut app = App::with_state(context);
    
     app = app.middleware(
-        Logger::default()
-    );
-    
-    app = app.middleware(
-        SessionStorage::new(
-            CookieSessionBackend::signed(&[0; 32])
-                .secure(false)
-        )
+        AssignRequestId
     );
 
-    let exclude_path = vec!["/signin".to_string()];
     app = app.middleware(
-        Authenticate::new(exclude_path)
+        SecurityHeaders
     );
 
-    app = app.handler(
-        "/public/css",
-        fs::StaticFiles::new("./src/public/css")
-        .unwrap()
-        .show_files_listing()
+    app = app.middleware(
+        SessionStorage::new(
+            DbSessionBackend::new(settings)
+                .secure(secure)
+        )
     );
 
-    app = app.handler(
-        "/public/js",
-        fs::StaticFiles::new("./src/public/js")
-        .unwrap()
-        .show_files_listing()
+    app = app.middleware(
+        AccessLog
     );
 
-    app = app.route(
-        "/",
-        Method::GET,
-        controllers::root_controller::handle_index,
-    );
-    
-    app = app.route(
-        "/users",
-        Method::GET,
-        controllers::users_controller::handle_index,
+    app = app.middleware(
+        JsonErrors
     );
 
-    app = app.route(
-        "/users/new",
-        Method::GET,
-        controllers::users_controller::handle_new,
+    app = app.middleware(
+        RateLimit::new(rate_limit_store)
+            .route(Some(Method::POST), "/signin", Quota::per_minute(5))
+            .route(Some(Method::POST), "/users", Quota::per_minute(10))
+            .route(None, "/users", Quota::per_second(10))
     );
 
-    app = app.route(
-        "/users",
-        Method::POST,
-        controllers::users_controller::handle_create,
+    app = app.middleware(
+        Authenticate::new(access::access_rules())
     );
 
-    app = app.route(
-        "/users/{id}",
-        Method::GET,
-        controllers::users_controller::handle_show,
+    app = app.handler(
+        "/public",
+        AssetFiles(assets)
     );
 
-    app = app.route(
-        "/users/{id}/edit",
-        Method::GET,
-        controllers::users_controller::handle_edit,
-    );
+    register_routes(app)
+}
 
-    app = app.route(
-        "/users/{id}",
-        Method::POST,
-        controllers::users_controller::handle_post,
-    );
+routes! {
+    page GET    "/"                            => controllers::root_controller::handle_index;
+    raw  GET    "/openapi.json"                => controllers::docs_controller::handle_openapi;
+    raw  GET    "/api-docs"                    => controllers::docs_controller::handle_api_docs;
 
-    app = app.route(
-        "/users/{id}",
-        Method::PATCH,
-        controllers::users_controller::handle_update,
-    );
+    page GET    "/users"                       => controllers::users_controller::handle_index;
+    page GET    "/users/new"                   => controllers::users_controller::handle_new;
+    page GET    "/users/search"                => controllers::users_controller::handle_search;
+    page POST   "/users"                       => controllers::users_controller::handle_create;
+    page GET    "/users/{id}"                  => controllers::users_controller::handle_show;
+    page GET    "/users/{id}/edit"             => controllers::users_controller::handle_edit;
+    page POST   "/users/{id}"                  => controllers::users_controller::handle_post;
+    page PATCH  "/users/{id}"                  => controllers::users_controller::handle_update;
+    page DELETE "/users/{id}"                  => controllers::users_controller::handle_destroy;
+    page GET    "/users/{id}/password"         => controllers::users_controller::handle_password_edit;
+    page POST   "/users/{id}/password"         => controllers::users_controller::handle_password_update;
+    page POST   "/users/{id}/avatar"           => controllers::users_controller::handle_avatar_update;
+    raw  GET    "/users/{id}/avatar/{size}"    => controllers::users_controller::handle_avatar_show;
+    page GET    "/users/{id}/following"        => controllers::relationships_controller::handle_following;
+    page GET    "/users/{id}/followers"        => controllers::relationships_controller::handle_followers;
 
-    app = app.route(
-        "/users/{id}",
-        Method::DELETE,
-        controllers::users_controller::handle_destroy,
-    );
+    page POST   "/microposts"                  => controllers::microposts_controller::handle_create;
+    page POST   "/microposts/{id}"             => controllers::microposts_controller::handle_post;
+    page DELETE "/microposts/{id}"             => controllers::microposts_controller::handle_destroy;
 
-    app = app.route(
-        "/signin",
-        Method::GET,
-        controllers::sessions_controller::handle_new,
-    );
+    page POST   "/relationships"               => controllers::relationships_controller::handle_create;
+    page POST   "/relationships/{followed_id}" => controllers::relationships_controller::handle_post;
+    page DELETE "/relationships/{followed_id}" => controllers::relationships_controller::handle_destroy;
 
-    app = app.route(
-        "/signin",
-        Method::POST,
-        controllers::sessions_controller::handle_create,
-    );
+    page GET    "/account"                     => controllers::account_controller::handle_show;
+    page POST   "/account/exports"             => controllers::account_controller::handle_export_create;
+    raw  GET    "/account/exports/{id}"        => controllers::account_controller::handle_export_show;
+    page POST   "/account/deletion"            => controllers::account_controller::handle_deletion_post;
+    page DELETE "/account/deletion"            => controllers::account_controller::handle_deletion_destroy;
 
-    app = app.route(
-        "/signout",
-        Method::POST,
-        controllers::sessions_controller::handle_post,
-    );
+    page GET    "/admin/jobs"                  => controllers::jobs_controller::handle_index;
+    page POST   "/admin/jobs/{id}/retry"       => controllers::jobs_controller::handle_retry;
+    page GET    "/admin/invitations"           => controllers::invitations_controller::handle_index;
+    page POST   "/admin/invitations"           => controllers::invitations_controller::handle_create;
+    page POST   "/admin/invitations/{id}/revoke" => controllers::invitations_controller::handle_revoke;
+    page POST   "/admin/invitations/{id}/resend" => controllers::invitations_controller::handle_resend;
 
-    app = app.route(
-        "/signout",
-        Method::DELETE,
-        controllers::sessions_controller::handle_destroy,
-    );
+    raw  GET    "/events"                      => controllers::events_controller::handle_socket;
 
-    app
+    page GET    "/signin"                      => controllers::sessions_controller::handle_new;
+    page POST   "/signin"                      => controllers::sessions_controller::handle_create;
+    page POST   "/signout"                     => controllers::sessions_controller::handle_post;
+    page DELETE "/signout"                     => controllers::sessions_controller::handle_destroy;
 }
 
+const DB_THREADS: usize = 3;
+const IMAGE_THREADS: usize = 2;
+const EXPORT_THREADS: usize = 2;
+
 fn main() {
     let sys = actix::System::new("webapp_sample");
 
+    let start_env = StartEnv::capture();
     dotenv().ok();
     let database_url = env::var("DATABASE_URL")
         .expect("DATABASE_URL must be set");
     let manager = ConnectionManager::<SqliteConnection>::new(database_url);
     let pool = r2d2::Pool::builder()
+        .connection_customizer(Box::new(SqlitePragmas::from_env()))
         .build(manager)
         .expect("Failed to create pool.");
-    let addr = SyncArbiter::start(3, move || DbExecutor(pool.clone()));
-
-    env_logger::Builder::new()
-        .format(|buf, record| {
-            writeln!(buf,
-                "{} [{}] - {}",
-                Local::now().format("%Y-%m-%d %H:%M:%S %z"),
-                record.level(),
-                record.args()
-            )
-        })
-        .filter(None, LevelFilter::Info)
-        .init();
-
-    let context = Context::new(addr);
+    let settings = SharedSettings::new(Settings::from_env());
+    let db_settings = settings.clone();
+    let events = Broadcaster::default().start();
+    let db_events = events.clone();
+    let session_cache = Arc::new(SessionCache::from_env());
+    let db_session_cache = session_cache.clone();
+    let addr = SyncArbiter::start(DB_THREADS, move || DbExecutor(pool.clone(), db_settings.clone(), db_events.clone(), db_session_cache.clone()));
+
+    let data_dir = env::var("DATA_DIR")
+        .unwrap_or_else(|_| "./data".to_string());
+    let storage = Arc::new(LocalStorage::new(data_dir));
+    let images = {
+        let storage = storage.clone();
+        SyncArbiter::start(IMAGE_THREADS, move || ImageExecutor(storage.clone()))
+    };
+    let exports = {
+        let db = addr.clone();
+        SyncArbiter::start(EXPORT_THREADS, move || ExportExecutor{db: db.clone(), storage: storage.clone()})
+    };
+
+    logging::init();
+
+    SessionPurger{
+        db: addr.clone(),
+        settings: settings.clone(),
+        interval: Duration::from_secs(10 * 60),
+    }.start();
+
+    AccountPurger{
+        db: addr.clone(),
+        interval: Duration::from_secs(60 * 60),
+    }.start();
+
+    let job_worker_threads = settings::env_or("JOB_WORKERS", 2);
+    let job_workers = {
+        let db = addr.clone();
+        let exports = exports.clone();
+        SyncArbiter::start(job_worker_threads, move || JobWorker{db: db.clone(), exports: exports.clone()})
+    };
+    let job_queue = JobQueue::new(
+        addr.clone(),
+        job_workers.clone(),
+        job_worker_threads,
+        Duration::from_secs(settings::env_or("JOB_POLL_SECS", 2)),
+        Duration::from_secs(settings::env_or("JOB_LEASE_SECS", 3600)),
+    ).start();
+
+    let assets = match Assets::from_env() {
+        Ok(assets) => Arc::new(assets),
+        Err(e) => {
+            error!("{}", json!({
+                "event": "assets_unreadable",
+                "error": e.to_string(),
+                "hint":  "set ASSETS_DIR, or build with --features embed-assets",
+            }));
+            process::exit(1);
+        },
+    };
+    let context = Context::new(addr.clone(), images.clone(), exports.clone(), events, session_cache, assets, settings.clone());
  
-    server::new(move || app(context.clone()))
-        .bind("127.0.0.1:8088")
-        .unwrap()
-        .start();
+    let certificates = TlsConfig::from_env().map(|config| {
+        Arc::new(Certificates::load(config).expect("failed to load TLS certificate"))
+    });
+    let secure = certificates.is_some();
+
+    let mut server = server::new(move || app(context.clone(), secure))
+        .shutdown_timeout(settings::env_or("SHUTDOWN_TIMEOUT_SECS", 30))
+        .disable_signals();
+
+    // Prefer sockets handed over by systemd or systemfd, so the ports stay
+    // open across restarts; otherwise bind BIND_ADDR, and with TLS also
+    // REDIRECT_BIND_ADDR for plain HTTP.
+    let mut listenfd = ListenFd::from_env();
+    let listener = listenfd.take_tcp_listener(0).expect("failed to take inherited listener");
+    let listener = listener.unwrap_or_else(|| bind_listener("BIND_ADDR", "127.0.0.1:8088"));
+    let https_port = listener.local_addr().map(|addr| addr.port()).unwrap_or(443);
 
-    println!("Started http server: 127.0.0.1:8080");
+    server = match certificates {
+        Some(ref certificates) => server.listen_rustls(listener, tls::server_config(certificates.clone())),
+        None                   => server.listen(listener),
+    };
+
+    let redirect_server = if secure {
+        let listener = listenfd.take_tcp_listener(1).expect("failed to take inherited listener");
+        let listener = listener.unwrap_or_else(|| bind_listener("REDIRECT_BIND_ADDR", "127.0.0.1:8080"));
+
+        let redirect_server = server::new(move || {
+                App::new().default_resource(move |r| r.f(move |req| tls::redirect_to_https(req, https_port)))
+            })
+            .workers(1)
+            .disable_signals()
+            .listen(listener);
+
+        info!("{}", json!({
+            "event": "redirect_started",
+            "addrs": redirect_server.addrs().iter().map(|addr| addr.to_string()).collect::<Vec<_>>(),
+        }));
+        Some(redirect_server.start())
+    } else {
+        None
+    };
+
+    let addrs: Vec<String> = server.addrs().iter().map(|addr| addr.to_string()).collect();
+    let server = server.start();
+
+    Lifecycle{
+        server,
+        db: addr,
+        db_threads: DB_THREADS,
+        images,
+        image_threads: IMAGE_THREADS,
+        exports,
+        export_threads: EXPORT_THREADS,
+        job_queue,
+        job_workers,
+        job_worker_threads,
+        settings,
+        start_env,
+        redirect_server,
+        certificates,
+        stopping: false,
+    }.start();
+
+    info!("{}", json!({
+        "event": "server_started",
+        "addrs": addrs,
+        "tls":   secure,
+    }));
     let _ = sys.run();
 }
+
+fn bind_listener(key: &str, default: &str) -> TcpListener {
+    let addr = env::var(key)
+        .unwrap_or_else(|_| default.to_string());
+    TcpListener::bind(&addr).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use webapp_sample::openapi;
+
+    #[test]
+    fn every_route_is_in_the_openapi_document() {
+        let document = openapi::document();
+        let routes = route_table();
+
+        for &(ref method, path) in &routes {
+            let operation = &document["paths"][path][method.as_str().to_lowercase()];
+            assert!(operation.is_object(), "{} {} is missing from the OpenAPI document", method, path);
+        }
+
+        let operations: usize = document["paths"]
+            .as_object()
+            .unwrap()
+            .values()
+            .map(|item| item.as_object().unwrap().len())
+            .sum();
+        assert_eq!(operations, routes.len(), "the OpenAPI document describes routes that are not registered");
+    }
+}
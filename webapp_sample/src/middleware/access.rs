@@ -0,0 +1,140 @@
+use actix_web::http::{Method};
+
+use middleware::format::{JSON_SUFFIX};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    // Anyone; the session is not checked at all.
+    Public,
+    // Visitors who are not signed in, e.g. sign-up.
+    AnonymousOnly,
+    Authenticated,
+    Admin,
+}
+
+struct Rule {
+    method: Option<Method>,
+    pattern: Vec<String>,
+    access: Access,
+}
+
+// Which visitors may reach which routes, checked by `Authenticate`.
+// Patterns match path segments: `*` matches one segment and a trailing `**`
+// any number of them, so `/users/*/edit` and `/admin/**` work. Rules are
+// matched in registration order; the first match wins and paths no rule
+// matches get `fallback`. `.json` paths are matched like their HTML ones.
+pub struct AccessRules {
+    rules: Vec<Rule>,
+    fallback: Access,
+}
+
+impl AccessRules {
+    pub fn new(fallback: Access) -> Self {
+        Self {
+            rules: Vec::new(),
+            fallback,
+        }
+    }
+
+    pub fn route(mut self, method: Option<Method>, pattern: &str, access: Access) -> Self {
+        self.rules.push(Rule {
+            method,
+            pattern: segments(pattern).map(str::to_string).collect(),
+            access,
+        });
+        self
+    }
+
+    pub fn access(&self, method: &Method, path: &str) -> Access {
+        let path = match path.trim_end_matches(JSON_SUFFIX) {
+            "/index" => "/",
+            path     => path,
+        };
+        let path: Vec<&str> = segments(path).collect();
+
+        self.rules
+            .iter()
+            .find(|rule| rule.method.as_ref().is_none_or(|m| m == method) && matches(&rule.pattern, &path))
+            .map_or(self.fallback, |rule| rule.access)
+    }
+}
+
+// The rules `app()` installs.
+pub fn access_rules() -> AccessRules {
+    AccessRules::new(Access::Authenticated)
+        .route(None, "/public/**", Access::Public)
+        .route(None, "/signin", Access::Public)
+        // Matched without its `.json`, like every path.
+        .route(Some(Method::GET), "/openapi", Access::Public)
+        .route(Some(Method::GET), "/api-docs", Access::Public)
+        .route(Some(Method::GET), "/users/new", Access::AnonymousOnly)
+        .route(Some(Method::POST), "/users", Access::AnonymousOnly)
+        .route(None, "/admin/**", Access::Admin)
+}
+
+fn segments(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|segment| !segment.is_empty())
+}
+
+fn matches(pattern: &[String], path: &[&str]) -> bool {
+    match (pattern.split_first(), path.split_first()) {
+        (None, None)                                 => true,
+        (Some((first, rest)), _) if first == "**"    => rest.is_empty(),
+        (Some((first, rest)), Some((segment, tail))) => (first == "*" || first == segment) && matches(rest, tail),
+        _                                            => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Exercises the matching; the app's own table is `access_rules()`.
+    fn example_rules() -> AccessRules {
+        AccessRules::new(Access::Authenticated)
+            .route(None, "/public/**", Access::Public)
+            .route(None, "/signin", Access::Public)
+            .route(Some(Method::GET), "/users/new", Access::AnonymousOnly)
+            .route(Some(Method::POST), "/users", Access::AnonymousOnly)
+            .route(None, "/admin/**", Access::Admin)
+            .route(Some(Method::GET), "/users/*/avatar/*", Access::Public)
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let rules = example_rules();
+
+        assert_eq!(rules.access(&Method::GET, "/public/css/layout.css"), Access::Public);
+        assert_eq!(rules.access(&Method::GET, "/public"), Access::Public);
+        assert_eq!(rules.access(&Method::POST, "/signin"), Access::Public);
+        assert_eq!(rules.access(&Method::GET, "/signin.json"), Access::Public);
+        assert_eq!(rules.access(&Method::GET, "/users/new"), Access::AnonymousOnly);
+        assert_eq!(rules.access(&Method::POST, "/users.json"), Access::AnonymousOnly);
+        assert_eq!(rules.access(&Method::GET, "/users"), Access::Authenticated);
+        assert_eq!(rules.access(&Method::POST, "/admin/jobs/3/retry"), Access::Admin);
+        assert_eq!(rules.access(&Method::GET, "/users/3/avatar/small"), Access::Public);
+        assert_eq!(rules.access(&Method::GET, "/users/3/avatar"), Access::Authenticated);
+        assert_eq!(rules.access(&Method::GET, "/index.json"), Access::Authenticated);
+        assert_eq!(rules.access(&Method::GET, "/publicity"), Access::Authenticated);
+    }
+
+    #[test]
+    fn app_rules_guard_each_kind_of_route() {
+        let rules = access_rules();
+
+        assert_eq!(rules.access(&Method::GET, "/openapi.json"), Access::Public);
+        assert_eq!(rules.access(&Method::GET, "/api-docs"), Access::Public);
+        assert_eq!(rules.access(&Method::POST, "/signin"), Access::Public);
+        assert_eq!(rules.access(&Method::GET, "/public/css/layout.css"), Access::Public);
+        assert_eq!(rules.access(&Method::GET, "/users/new"), Access::AnonymousOnly);
+        assert_eq!(rules.access(&Method::POST, "/users"), Access::AnonymousOnly);
+        assert_eq!(rules.access(&Method::POST, "/users.json"), Access::AnonymousOnly);
+        assert_eq!(rules.access(&Method::GET, "/users"), Access::Authenticated);
+        assert_eq!(rules.access(&Method::POST, "/users/3"), Access::Authenticated);
+        assert_eq!(rules.access(&Method::GET, "/users/3/avatar/small"), Access::Authenticated);
+        assert_eq!(rules.access(&Method::POST, "/signout"), Access::Authenticated);
+        assert_eq!(rules.access(&Method::GET, "/admin/invitations"), Access::Admin);
+        assert_eq!(rules.access(&Method::POST, "/admin/invitations/3/resend.json"), Access::Admin);
+        assert_eq!(rules.access(&Method::POST, "/admin/jobs/3/retry"), Access::Admin);
+    }
+}
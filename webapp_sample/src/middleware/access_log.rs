@@ -0,0 +1,53 @@
+use std::time::{Instant};
+
+use actix_web::middleware::{Finished, Middleware, Started};
+use actix_web::middleware::session::{RequestSession};
+use actix_web::{HttpRequest, HttpResponse, Result};
+
+use helpers::{sessions_helper};
+use middleware::request_id::{RequestId};
+
+struct StartTime(Instant);
+
+// Writes one structured access log record per request once the response
+// has been sent. Must be registered after `AssignRequestId` and `SessionStorage`.
+pub struct AccessLog;
+
+impl<S> Middleware<S> for AccessLog {
+    fn start(&self, req: &HttpRequest<S>) -> Result<Started> {
+        req.extensions_mut().insert(StartTime(Instant::now()));
+
+        Ok(Started::Done)
+    }
+
+    fn finish(&self, req: &HttpRequest<S>, resp: &HttpResponse) -> Finished {
+        let latency_ms = req
+            .extensions()
+            .get::<StartTime>()
+            .map(|start| {
+                let elapsed = start.0.elapsed();
+                elapsed.as_secs() as f64 * 1000.0 + f64::from(elapsed.subsec_nanos()) / 1_000_000.0
+            });
+
+        let user_id = match sessions_helper::user_session(&req.session()) {
+            Ok(Some(user_session)) => Some(user_session.user_id),
+            _                      => None,
+        };
+
+        let RequestId(request_id) = RequestId::get(req);
+
+        info!("{}", json!({
+            "event":      "access",
+            "request_id": request_id,
+            "user_id":    user_id,
+            "remote_ip":  req.connection_info().remote(),
+            "method":     req.method().as_str(),
+            "path":       req.path(),
+            "status":     resp.status().as_u16(),
+            "latency_ms": latency_ms,
+            "user_agent": req.headers().get("User-Agent").and_then(|value| value.to_str().ok()),
+        }));
+
+        Finished::Done
+    }
+}
@@ -0,0 +1,128 @@
+use actix_web::middleware::{Middleware, Response};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::{Body, FromRequest, HttpRequest, HttpResponse, Result};
+
+use middleware::{STATIC_PREFIX};
+
+pub const JSON_SUFFIX: &str = ".json";
+
+// The representation a client asked for: JSON when the path ends in `.json`
+// or `Accept` lists `application/json`, HTML otherwise.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Html,
+    Json,
+}
+
+impl Format {
+    pub fn of<S>(req: &HttpRequest<S>) -> Self {
+        let accept = req
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("");
+
+        negotiate(req.path(), accept)
+    }
+
+    pub fn is_json(self) -> bool {
+        self == Format::Json
+    }
+}
+
+impl<S> FromRequest<S> for Format {
+    type Config = ();
+    type Result = Self;
+
+    fn from_request(req: &HttpRequest<S>, _: &Self::Config) -> Self::Result {
+        Format::of(req)
+    }
+}
+
+fn negotiate(path: &str, accept: &str) -> Format {
+    let accepts_json = accept
+        .split(',')
+        .any(|range| {
+            let mut params = range.split(';').map(str::trim);
+            let media_type = params.next().unwrap_or("");
+            let quality = params
+                .filter_map(|param| param.strip_prefix("q="))
+                .filter_map(|q| q.parse::<f32>().ok())
+                .next()
+                .unwrap_or(1.0);
+
+            media_type.eq_ignore_ascii_case("application/json") && quality > 0.0
+        });
+
+    if path.ends_with(JSON_SUFFIX) || accepts_json {
+        Format::Json
+    } else {
+        Format::Html
+    }
+}
+
+// Replaces the body of error responses that are not already JSON for JSON clients,
+// so failures from extractors, the database layer or other middleware
+// arrive as `{"error": ...}` like the errors the controllers report.
+// Responses whose format `Accept` decides carry `Vary: Accept`, so shared
+// caches keep the HTML and the JSON apart.
+pub struct JsonErrors;
+
+impl<S> Middleware<S> for JsonErrors {
+    fn response(&self, req: &HttpRequest<S>, mut resp: HttpResponse) -> Result<Response> {
+        if !req.path().starts_with(STATIC_PREFIX) && !req.path().ends_with(JSON_SUFFIX) {
+            resp.headers_mut().append(header::VARY, HeaderValue::from_static("Accept"));
+        }
+
+        let status = resp.status();
+        let has_json = resp
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .is_some_and(|value| value.as_bytes().starts_with(b"application/json"));
+
+        if Format::of(req).is_json() && (status.is_client_error() || status.is_server_error()) && !has_json {
+            let body = json!({
+                "error": status.canonical_reason().unwrap_or("Error"),
+            });
+
+            resp.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+            resp.set_body(Body::from(body.to_string()));
+        }
+
+        Ok(Response::Done(resp))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use actix_web::test::{TestRequest};
+
+    #[test]
+    fn negotiate_prefers_suffix_then_accept() {
+        assert_eq!(negotiate("/users.json", ""), Format::Json);
+        assert_eq!(negotiate("/users/1.json", "text/html"), Format::Json);
+        assert_eq!(negotiate("/users", "application/json"), Format::Json);
+        assert_eq!(negotiate("/users", "text/html, application/json;q=0.9"), Format::Json);
+        assert_eq!(negotiate("/users", "application/json;q=0"), Format::Html);
+        assert_eq!(negotiate("/users", "text/html,application/xhtml+xml,*/*;q=0.8"), Format::Html);
+        assert_eq!(negotiate("/users", ""), Format::Html);
+    }
+
+    #[test]
+    fn negotiated_responses_vary_on_accept() {
+        let vary = |path: &str| {
+            let req = TestRequest::with_header(header::ACCEPT, "application/json").uri(path).finish();
+            let resp = HttpResponse::NotFound().header(header::VARY, "Accept-Encoding").finish();
+            match JsonErrors.response(&req, resp).unwrap() {
+                Response::Done(resp) => resp.headers().get_all(header::VARY).iter().map(|value| value.to_str().unwrap().to_string()).collect::<Vec<_>>(),
+                _                    => panic!("expected a finished response"),
+            }
+        };
+
+        assert_eq!(vary("/users"), vec!["Accept-Encoding", "Accept"]);
+        assert_eq!(vary("/users.json"), vec!["Accept-Encoding"]);
+        assert_eq!(vary("/public/css/layout.css"), vec!["Accept-Encoding"]);
+    }
+}
@@ -1,62 +1,130 @@
+pub mod access;
+pub mod access_log;
+pub mod format;
+pub mod rate_limit;
+pub mod request_id;
+pub mod security_headers;
+
 use actix_web::middleware::{Finished, Middleware, Response, Started};
 use actix_web::middleware::session::{RequestSession};
 use actix_web::{HttpRequest, HttpResponse, Result};
-use futures::Future;
+use futures::{future, Future};
+use futures::future::{Either};
 
-use db::{users_message};
+use db::{sessions_message};
 use context::{Context};
 use helpers::{sessions_helper};
 use controllers;
+use sessions::cache::{ValidSession};
+
+use self::access::{Access, AccessRules};
+use self::format::{Format};
+use self::request_id::{RequestId};
+
+// Paths served from `./src/public`. Sessions are not read for them.
+pub const STATIC_PREFIX: &str = "/public/";
 
+// Applies `AccessRules`. A signed-in session counts only while it is still
+// the user's current one, which is answered from the session cache when possible.
+// The `ValidSession` is left in the request extensions for the handlers.
 pub struct Authenticate {
-    exclude_path: Vec<String>
+    rules: AccessRules,
 }
 
 impl Authenticate {
-    pub fn new(exclude_path: Vec<String>) -> Self {
+    pub fn new(rules: AccessRules) -> Self {
         Self {
-            exclude_path: exclude_path, 
+            rules,
         }
     }
 }
 
+// The response that turns the request away, if any.
+fn deny(access: Access, session: Option<ValidSession>, format: Format) -> Option<HttpResponse> {
+    match (access, session) {
+        (Access::Public, _) | (Access::AnonymousOnly, None) => None,
+        (Access::AnonymousOnly, Some(_))                    => Some(match format {
+            Format::Json => controllers::http_status(403),
+            Format::Html => controllers::http_redirect("/", 303),
+        }),
+        (Access::Admin, Some(session)) if !session.admin    => Some(controllers::http_status(403)),
+        (_, Some(_))                                        => None,
+        (_, None)                                           => Some(controllers::signin_required(format)),
+    }
+}
+
 impl Middleware<Context> for Authenticate {
     fn start(&self, req: &HttpRequest<Context>) -> Result<Started> {
-        let session = req.session();
-
-        match sessions_helper::user_session(&session) {
-            Ok(Some(user_session)) => {
-                Ok(Started::Future(Box::new(
-                    req
-                        .state()
-                        .db
-                        .send(users_message::ReadUser{id: user_session.user_id})
-                        .from_err()
-                        .and_then(move |res| {
-                            res.and_then(move |user| {
-                                sessions_helper::valid_session_id(
-                                    &user_session,
-                                    &user.session_digest.clone().unwrap(),
-                                ).and_then(|_| {
-                                    println!("signin");
-                                    Ok(None)
-                                })
-                            })
-                        })
-                        .or_else(|_| {
-                            Ok(Some(controllers::http_status(403)))
-                        })
-                )))
-            },
-            _ => {
-                println!("not signin");
-                if self.exclude_path.contains(&req.path().to_string()) {
-                    Ok(Started::Done)
-                } else {
-                    Ok(Started::Response(controllers::http_redirect("/signin", 303)))
-                }
-            },
+        let access = self.rules.access(req.method(), req.path());
+        if access == Access::Public {
+            return Ok(Started::Done);
         }
+
+        let format = Format::of(req);
+        let RequestId(request_id) = RequestId::get(req);
+
+        let user_session = match sessions_helper::user_session(&req.session()) {
+            Ok(Some(user_session)) => user_session,
+            _                      => {
+                debug!("{}", json!({
+                    "event":         "authenticate",
+                    "request_id":    request_id,
+                    "user_id":       null,
+                    "authenticated": false,
+                }));
+
+                return Ok(match deny(access, None, format) {
+                    Some(resp) => Started::Response(resp),
+                    None       => Started::Done,
+                });
+            },
+        };
+
+        let req = req.clone();
+        let session_cache = req.state().session_cache.clone();
+        let cached = session_cache.get(&user_session);
+
+        let validated = match cached {
+            Some(session) => Either::A(future::ok(Some(session))),
+            None          => Either::B(
+                req
+                    .state()
+                    .db
+                    .send(sessions_message::ValidateUserSession{
+                        request_id: request_id.clone(),
+                        user_session: user_session.clone(),
+                    })
+                    .then(|res| match res {
+                        Ok(Ok(session)) => Ok(session),
+                        _               => Ok(None),
+                    })
+            ),
+        };
+
+        Ok(Started::Future(Box::new(
+            validated
+                .map(move |session| {
+                    debug!("{}", json!({
+                        "event":         "authenticate",
+                        "request_id":    request_id,
+                        "user_id":       user_session.user_id,
+                        "authenticated": session.is_some(),
+                        "cached":        cached.is_some(),
+                    }));
+
+                    match session {
+                        Some(session) => {
+                            if cached.is_none() {
+                                session_cache.insert(&user_session, session);
+                            }
+                            req.extensions_mut().insert(session);
+                            deny(access, Some(session), format)
+                        },
+                        // A session that is no longer current counts as signed out.
+                        None => deny(access, None, format),
+                    }
+                })
+        )))
     }
 
     fn response(&self, _req: &HttpRequest<Context>, resp: HttpResponse) -> Result<Response> {
@@ -67,3 +135,32 @@ impl Middleware<Context> for Authenticate {
         Finished::Done
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use actix_web::http::{header, StatusCode};
+    use chrono_tz::{Tz};
+
+    fn status(resp: Option<HttpResponse>) -> Option<(StatusCode, Option<String>)> {
+        resp.map(|resp| (
+            resp.status(),
+            resp.headers().get(header::LOCATION).map(|location| location.to_str().unwrap().to_string()),
+        ))
+    }
+
+    #[test]
+    fn signed_out_visitors_are_sent_to_sign_in_and_members_kept_out_of_admin() {
+        let member = ValidSession{admin: false, time_zone: Tz::UTC};
+        let signin = Some((StatusCode::SEE_OTHER, Some("/signin".to_string())));
+
+        assert_eq!(status(deny(Access::Authenticated, None, Format::Html)), signin);
+        assert_eq!(status(deny(Access::Authenticated, None, Format::Json)), Some((StatusCode::UNAUTHORIZED, None)));
+        assert_eq!(status(deny(Access::Admin, None, Format::Html)), signin);
+        assert_eq!(status(deny(Access::Admin, Some(member), Format::Html)), Some((StatusCode::FORBIDDEN, None)));
+        assert_eq!(status(deny(Access::AnonymousOnly, Some(member), Format::Json)), Some((StatusCode::FORBIDDEN, None)));
+        assert_eq!(status(deny(Access::AnonymousOnly, None, Format::Html)), None);
+        assert_eq!(status(deny(Access::Authenticated, Some(member), Format::Html)), None);
+    }
+}
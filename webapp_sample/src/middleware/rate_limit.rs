@@ -0,0 +1,342 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix_web::middleware::{Middleware, Started};
+use actix_web::middleware::session::{RequestSession};
+use actix_web::http::{Method, StatusCode};
+use actix_web::{HttpRequest, HttpResponse, Result};
+
+use context::{Context};
+use helpers::{sessions_helper};
+use middleware::format::{JSON_SUFFIX};
+use middleware::request_id::{RequestId};
+
+// MemoryStore drops idle buckets once it holds more keys than this, at most
+// once per interval, so a flood of new keys cannot make every request scan
+// the map. A bucket idle for an hour has refilled for every quota shorter
+// than that.
+const MEMORY_STORE_PRUNE_THRESHOLD: usize = 10_000;
+const MEMORY_STORE_PRUNE_INTERVAL_SECS: f64 = 60.0;
+const MEMORY_STORE_IDLE_SECS: f64 = 3600.0;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Quota {
+    pub capacity: f64,
+    pub refill_per_sec: f64,
+}
+
+impl Quota {
+    // Panics on a zero capacity or period, which would never let a request through.
+    pub fn new(capacity: u32, per: Duration) -> Self {
+        assert!(capacity > 0, "rate limit quota needs a capacity");
+        assert!(per > Duration::from_secs(0), "rate limit quota needs a period");
+
+        let secs = per.as_secs() as f64 + f64::from(per.subsec_nanos()) / 1_000_000_000.0;
+
+        Quota {
+            capacity: f64::from(capacity),
+            refill_per_sec: f64::from(capacity) / secs,
+        }
+    }
+
+    pub fn per_second(capacity: u32) -> Self {
+        Quota::new(capacity, Duration::from_secs(1))
+    }
+
+    pub fn per_minute(capacity: u32) -> Self {
+        Quota::new(capacity, Duration::from_secs(60))
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Bucket {
+    pub tokens: f64,
+    pub updated_at: f64,
+}
+
+impl Bucket {
+    pub fn full(quota: &Quota, now: f64) -> Self {
+        Bucket {
+            tokens: quota.capacity,
+            updated_at: now,
+        }
+    }
+
+    // Refills the bucket for the time elapsed since the last call and takes one token.
+    // Returns how long the caller has to wait when the bucket is empty.
+    pub fn take(&mut self, quota: &Quota, now: f64) -> ::std::result::Result<(), Duration> {
+        let elapsed = (now - self.updated_at).max(0.0);
+        self.tokens = (self.tokens + elapsed * quota.refill_per_sec).min(quota.capacity);
+        self.updated_at = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            let wait = (1.0 - self.tokens) / quota.refill_per_sec;
+            Err(Duration::from_millis((wait * 1000.0).ceil() as u64))
+        }
+    }
+}
+
+// Keeps the buckets. Implementations must make `take` atomic per key, so a store
+// shared between processes (e.g. a SQLite table) has to do it inside a transaction.
+pub trait RateLimitStore: Send + Sync {
+    fn take(&self, key: &str, quota: &Quota, now: f64) -> ::std::result::Result<(), Duration>;
+}
+
+#[derive(Default)]
+pub struct MemoryStore {
+    buckets: Mutex<Buckets>,
+}
+
+#[derive(Default)]
+struct Buckets {
+    by_key: HashMap<String, Bucket>,
+    pruned_at: f64,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+}
+
+impl RateLimitStore for MemoryStore {
+    fn take(&self, key: &str, quota: &Quota, now: f64) -> ::std::result::Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.by_key.len() > MEMORY_STORE_PRUNE_THRESHOLD && now - buckets.pruned_at >= MEMORY_STORE_PRUNE_INTERVAL_SECS {
+            buckets.by_key.retain(|_, bucket| now - bucket.updated_at < MEMORY_STORE_IDLE_SECS);
+            buckets.pruned_at = now;
+        }
+
+        buckets
+            .by_key
+            .entry(key.to_string())
+            .or_insert_with(|| Bucket::full(quota, now))
+            .take(quota, now)
+    }
+}
+
+struct Rule {
+    method: Option<Method>,
+    path: String,
+    quota: Quota,
+}
+
+// Token bucket rate limiter.
+// Signed in users are limited per user id, anonymous visitors per client IP.
+// Rules are matched by path prefix, whole segments only, in registration
+// order; the first match wins. A `.json` route shares the rules of its HTML
+// twin.
+pub struct RateLimit {
+    store: Arc<dyn RateLimitStore>,
+    rules: Vec<Rule>,
+}
+
+impl RateLimit {
+    pub fn new(store: Arc<dyn RateLimitStore>) -> Self {
+        Self {
+            store,
+            rules: Vec::new(),
+        }
+    }
+
+    pub fn route(mut self, method: Option<Method>, path: &str, quota: Quota) -> Self {
+        self.rules.push(Rule {
+            method,
+            path: path.to_string(),
+            quota,
+        });
+        self
+    }
+
+    fn matching_rule(&self, method: &Method, path: &str) -> Option<(usize, Quota)> {
+        let path = match path.trim_end_matches(JSON_SUFFIX) {
+            "/index" => "/",
+            path     => path,
+        };
+
+        self.rules
+            .iter()
+            .enumerate()
+            .find(|(_, rule)| {
+                let method_matches = match rule.method {
+                    Some(ref m) => m == method,
+                    None        => true,
+                };
+                method_matches && within(path, &rule.path)
+            })
+            .map(|(index, rule)| (index, rule.quota))
+    }
+}
+
+// `/users` covers `/users` and `/users/1`, but not `/users_export`.
+fn within(path: &str, prefix: &str) -> bool {
+    path.starts_with(prefix) && (
+        path.len() == prefix.len() ||
+        prefix.ends_with('/') ||
+        path[prefix.len()..].starts_with('/')
+    )
+}
+
+fn now() -> f64 {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_else(|_| Duration::from_secs(0));
+
+    since_epoch.as_secs() as f64 + f64::from(since_epoch.subsec_nanos()) / 1_000_000_000.0
+}
+
+impl Middleware<Context> for RateLimit {
+    fn start(&self, req: &HttpRequest<Context>) -> Result<Started> {
+        let (index, quota) = match self.matching_rule(req.method(), req.path()) {
+            Some(rule) => rule,
+            None       => return Ok(Started::Done),
+        };
+
+        let client = match sessions_helper::user_session(&req.session()) {
+            Ok(Some(user_session)) => format!("user:{}", user_session.user_id),
+            _ => match req.peer_addr() {
+                Some(addr) => format!("ip:{}", addr.ip()),
+                None       => "ip:unknown".to_string(),
+            },
+        };
+
+        let key = format!("rule{}:{}", index, client);
+
+        match self.store.take(&key, &quota, now()) {
+            Ok(()) => Ok(Started::Done),
+            Err(wait) => {
+                let retry_after = wait.as_secs() + if wait.subsec_nanos() > 0 { 1 } else { 0 };
+                let RequestId(request_id) = RequestId::get(req);
+
+                info!("{}", json!({
+                    "event":       "rate_limited",
+                    "request_id":  request_id,
+                    "key":         key,
+                    "retry_after": retry_after,
+                }));
+
+                Ok(Started::Response(
+                    HttpResponse::build(StatusCode::TOO_MANY_REQUESTS)
+                        .header("Retry-After", retry_after.to_string())
+                        .finish()
+                ))
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_allows_burst_then_limits() {
+        let quota = Quota::per_minute(3);
+        let mut bucket = Bucket::full(&quota, 0.0);
+
+        assert!(bucket.take(&quota, 0.0).is_ok());
+        assert!(bucket.take(&quota, 0.0).is_ok());
+        assert!(bucket.take(&quota, 0.0).is_ok());
+
+        let wait = bucket.take(&quota, 0.0).unwrap_err();
+        assert_eq!(wait, Duration::from_secs(20));
+    }
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let quota = Quota::per_second(2);
+        let mut bucket = Bucket::full(&quota, 0.0);
+
+        assert!(bucket.take(&quota, 0.0).is_ok());
+        assert!(bucket.take(&quota, 0.0).is_ok());
+        assert!(bucket.take(&quota, 0.1).is_err());
+        assert!(bucket.take(&quota, 0.6).is_ok());
+        assert!(bucket.take(&quota, 10.0).is_ok());
+        assert!(bucket.take(&quota, 10.0).is_ok());
+        assert!(bucket.take(&quota, 10.0).is_err());
+    }
+
+    #[test]
+    fn memory_store_keeps_keys_apart() {
+        let store = MemoryStore::new();
+        let quota = Quota::per_minute(1);
+
+        assert!(store.take("ip:127.0.0.1", &quota, 0.0).is_ok());
+        assert!(store.take("ip:127.0.0.1", &quota, 0.0).is_err());
+        assert!(store.take("user:1", &quota, 0.0).is_ok());
+    }
+
+    #[test]
+    fn memory_store_prunes_idle_buckets_at_most_once_per_interval() {
+        let store = MemoryStore::new();
+        let quota = Quota::per_minute(1);
+
+        for i in 0..=MEMORY_STORE_PRUNE_THRESHOLD {
+            assert!(store.take(&format!("ip:{}", i), &quota, 0.0).is_ok());
+        }
+        store.buckets.lock().unwrap().pruned_at = MEMORY_STORE_IDLE_SECS;
+
+        assert!(store.take("user:1", &quota, MEMORY_STORE_IDLE_SECS + 1.0).is_ok());
+        assert_eq!(store.buckets.lock().unwrap().by_key.len(), MEMORY_STORE_PRUNE_THRESHOLD + 2);
+
+        assert!(store.take("user:2", &quota, MEMORY_STORE_IDLE_SECS + MEMORY_STORE_PRUNE_INTERVAL_SECS).is_ok());
+        assert_eq!(store.buckets.lock().unwrap().by_key.len(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "capacity")]
+    fn quota_rejects_zero_capacity() {
+        Quota::per_minute(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "period")]
+    fn quota_rejects_zero_period() {
+        Quota::new(1, Duration::from_secs(0));
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let rate_limit = RateLimit::new(Arc::new(MemoryStore::new()))
+            .route(Some(Method::POST), "/signin", Quota::per_minute(5))
+            .route(None, "/users", Quota::per_second(10));
+
+        assert_eq!(rate_limit.matching_rule(&Method::POST, "/signin").map(|r| r.0), Some(0));
+        assert_eq!(rate_limit.matching_rule(&Method::GET, "/signin").map(|r| r.0), None);
+        assert_eq!(rate_limit.matching_rule(&Method::GET, "/users/1").map(|r| r.0), Some(1));
+        assert_eq!(rate_limit.matching_rule(&Method::POST, "/signinfoo").map(|r| r.0), None);
+        assert_eq!(rate_limit.matching_rule(&Method::GET, "/users_export").map(|r| r.0), None);
+    }
+
+    #[test]
+    fn json_routes_share_the_html_rules() {
+        let rate_limit = RateLimit::new(Arc::new(MemoryStore::new()))
+            .route(Some(Method::POST), "/signin", Quota::per_minute(5))
+            .route(None, "/users", Quota::per_second(10));
+
+        assert_eq!(rate_limit.matching_rule(&Method::POST, "/signin.json").map(|r| r.0), Some(0));
+        assert_eq!(rate_limit.matching_rule(&Method::GET, "/users.json").map(|r| r.0), Some(1));
+        assert_eq!(rate_limit.matching_rule(&Method::GET, "/users/1.json").map(|r| r.0), Some(1));
+    }
+
+    #[test]
+    fn signin_json_counts_against_the_signin_quota() {
+        let rate_limit = RateLimit::new(Arc::new(MemoryStore::new()))
+            .route(Some(Method::POST), "/signin", Quota::per_minute(5));
+
+        let mut attempts = ["/signin", "/signin.json"].iter().cycle().map(|path| {
+            let (index, quota) = rate_limit.matching_rule(&Method::POST, path).unwrap();
+            rate_limit.store.take(&format!("rule{}:ip:127.0.0.1", index), &quota, 0.0)
+        });
+
+        for _ in 0..5 {
+            assert!(attempts.next().unwrap().is_ok());
+        }
+        assert!(attempts.next().unwrap().is_err());
+    }
+}
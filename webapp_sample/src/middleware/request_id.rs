@@ -0,0 +1,119 @@
+use uuid::Uuid;
+
+use actix_web::middleware::{Middleware, Response, Started};
+use actix_web::http::header::{HeaderValue};
+use actix_web::{FromRequest, HttpRequest, HttpResponse, Result};
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+#[derive(Clone, Debug)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    pub fn get<S>(req: &HttpRequest<S>) -> Self {
+        match req.extensions().get::<RequestId>() {
+            Some(request_id) => request_id.clone(),
+            None             => RequestId(String::new()),
+        }
+    }
+}
+
+impl<S> FromRequest<S> for RequestId {
+    type Config = ();
+    type Result = Self;
+
+    fn from_request(req: &HttpRequest<S>, _: &Self::Config) -> Self::Result {
+        RequestId::get(req)
+    }
+}
+
+pub struct AssignRequestId;
+
+// Accepts a client supplied id only if it is short and made of safe characters,
+// since it is echoed back in the response and written to the logs.
+fn valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+impl<S> Middleware<S> for AssignRequestId {
+    fn start(&self, req: &HttpRequest<S>) -> Result<Started> {
+        let given = req
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| valid_request_id(id))
+            .map(|id| id.to_string());
+
+        let id = match given {
+            Some(id) => id,
+            None     => Uuid::new_v4().to_string(),
+        };
+
+        req.extensions_mut().insert(RequestId(id));
+
+        Ok(Started::Done)
+    }
+
+    fn response(&self, req: &HttpRequest<S>, mut resp: HttpResponse) -> Result<Response> {
+        let RequestId(id) = RequestId::get(req);
+
+        if let Ok(value) = HeaderValue::from_str(&id) {
+            resp.headers_mut().insert(REQUEST_ID_HEADER, value);
+        }
+
+        Ok(Response::Done(resp))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use actix_web::test::{TestRequest};
+
+    fn assigned(req: &HttpRequest) -> String {
+        match AssignRequestId.start(req) {
+            Ok(Started::Done) => RequestId::get(req).0,
+            _                 => panic!("AssignRequestId answered the request"),
+        }
+    }
+
+    #[test]
+    fn valid_request_id_checks_length_and_characters() {
+        assert!(valid_request_id("3f2a-req_1.retry"));
+        assert!(valid_request_id(&"a".repeat(MAX_REQUEST_ID_LEN)));
+        assert!(!valid_request_id(&"a".repeat(MAX_REQUEST_ID_LEN + 1)));
+        assert!(!valid_request_id(""));
+        assert!(!valid_request_id("a b"));
+        assert!(!valid_request_id("a\"}, {\"event\": \"forged"));
+        assert!(!valid_request_id("日本"));
+    }
+
+    #[test]
+    fn inbound_id_is_reused_when_valid() {
+        let req = TestRequest::with_header(REQUEST_ID_HEADER, "upstream-42").finish();
+        assert_eq!(assigned(&req), "upstream-42");
+
+        let resp = AssignRequestId.response(&req, HttpResponse::Ok().finish()).unwrap();
+        match resp {
+            Response::Done(resp) => assert_eq!(resp.headers()[REQUEST_ID_HEADER], "upstream-42"),
+            _                    => panic!("expected a finished response"),
+        }
+    }
+
+    #[test]
+    fn new_id_is_generated_otherwise() {
+        let invalid = TestRequest::with_header(REQUEST_ID_HEADER, "not valid").finish();
+        let generated = assigned(&invalid);
+        assert!(Uuid::parse_str(&generated).is_ok());
+
+        let missing = TestRequest::default().finish();
+        let other = assigned(&missing);
+        assert!(Uuid::parse_str(&other).is_ok());
+        assert_ne!(generated, other);
+    }
+}
@@ -0,0 +1,93 @@
+use std::iter;
+
+use rand::prelude::*;
+use rand::distributions::{Alphanumeric};
+
+use actix_web::middleware::{Middleware, Response, Started};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::{FromRequest, HttpRequest, HttpResponse, Result};
+
+const NONCE_LEN: usize = 22;
+const HSTS_MAX_AGE: u64 = 31_536_000;
+
+// Per request nonce allowed by the Content-Security-Policy `script-src`.
+// Handlers that render an inline script take this extractor and pass it to
+// the template, e.g. `<script nonce="{{csp_nonce}}">`.
+#[derive(Clone, Debug)]
+pub struct CspNonce(pub String);
+
+impl CspNonce {
+    pub fn get<S>(req: &HttpRequest<S>) -> Self {
+        match req.extensions().get::<CspNonce>() {
+            Some(nonce) => nonce.clone(),
+            None        => CspNonce(String::new()),
+        }
+    }
+}
+
+impl<S> FromRequest<S> for CspNonce {
+    type Config = ();
+    type Result = Self;
+
+    fn from_request(req: &HttpRequest<S>, _: &Self::Config) -> Self::Result {
+        CspNonce::get(req)
+    }
+}
+
+fn create_nonce() -> String {
+    let mut rng = thread_rng();
+    iter::repeat(())
+        .map(|()| rng.sample(Alphanumeric))
+        .take(NONCE_LEN)
+        .collect()
+}
+
+fn content_security_policy(nonce: &str) -> String {
+    [
+        "default-src 'self'".to_string(),
+        format!("script-src 'self' 'nonce-{}'", nonce),
+        "style-src 'self'".to_string(),
+        "img-src 'self' data:".to_string(),
+        "connect-src 'self'".to_string(),
+        "object-src 'none'".to_string(),
+        "base-uri 'self'".to_string(),
+        "form-action 'self'".to_string(),
+        "frame-ancestors 'none'".to_string(),
+    ].join("; ")
+}
+
+// Adds CSP, framing, sniffing and referrer headers to every response,
+// and HSTS when the request came in over TLS.
+pub struct SecurityHeaders;
+
+impl<S> Middleware<S> for SecurityHeaders {
+    fn start(&self, req: &HttpRequest<S>) -> Result<Started> {
+        req.extensions_mut().insert(CspNonce(create_nonce()));
+
+        Ok(Started::Done)
+    }
+
+    fn response(&self, req: &HttpRequest<S>, mut resp: HttpResponse) -> Result<Response> {
+        let CspNonce(nonce) = CspNonce::get(req);
+        let https = req.connection_info().scheme() == "https";
+
+        {
+            let headers = resp.headers_mut();
+
+            if let Ok(value) = HeaderValue::from_str(&content_security_policy(&nonce)) {
+                headers.insert(header::CONTENT_SECURITY_POLICY, value);
+            }
+            headers.insert(header::X_FRAME_OPTIONS, HeaderValue::from_static("DENY"));
+            headers.insert(header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+            headers.insert(header::REFERRER_POLICY, HeaderValue::from_static("same-origin"));
+
+            if https {
+                if let Ok(value) = HeaderValue::from_str(&format!("max-age={}; includeSubDomains", HSTS_MAX_AGE)) {
+                    headers.insert(header::STRICT_TRANSPORT_SECURITY, value);
+                }
+            }
+        }
+
+        Ok(Response::Done(resp))
+    }
+}
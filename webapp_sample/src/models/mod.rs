@@ -1,5 +1,6 @@
-use super::schema::users;
+use super::schema::{data_exports, invitations, jobs, microposts, relationships, sessions, users};
 use chrono::{NaiveDateTime};
+use diesel::sql_types::{Integer, Nullable, Text};
 
 #[derive(Serialize, Queryable)]
 pub struct User {
@@ -7,10 +8,35 @@ pub struct User {
     pub uuid: String,
     pub name: String,
     pub email: String,
+    // Never rendered, and so never sent to JSON clients.
+    #[serde(skip_serializing)]
     pub password_digest: String,
+    #[serde(skip_serializing)]
     pub session_digest: Option<String>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub avatar_key: Option<String>,
+    pub lock_version: i32,
+    pub deletion_scheduled_at: Option<NaiveDateTime>,
+    pub admin: bool,
+    // An IANA name such as `Asia/Tokyo`; times are stored in UTC and shown in it.
+    pub time_zone: String,
+}
+
+#[derive(Serialize, QueryableByName)]
+pub struct UserSearchResult {
+    #[sql_type = "Integer"]
+    pub id: i32,
+    #[sql_type = "Text"]
+    pub name: String,
+    #[sql_type = "Text"]
+    pub email: String,
+    #[sql_type = "Nullable<Text>"]
+    pub avatar_key: Option<String>,
+    #[sql_type = "Text"]
+    pub name_snippet: String,
+    #[sql_type = "Text"]
+    pub email_snippet: String,
 }
 
 #[derive(Insertable)]
@@ -20,12 +46,155 @@ pub struct NewUser<'a> {
     pub name: &'a str,
     pub email: &'a str,
     pub password_digest: &'a str,
+    pub admin: bool,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
 
+// Only the fields that are Some are written.
+#[derive(AsChangeset)]
+#[table_name = "users"]
+pub struct UserChangeset<'a> {
+    pub name: Option<&'a str>,
+    pub email: Option<&'a str>,
+    pub time_zone: Option<&'a str>,
+    pub updated_at: NaiveDateTime,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct UserSession {
     pub user_id: i32,
     pub session_id: String,
 }
+
+#[derive(Serialize, Queryable)]
+pub struct Micropost {
+    pub id: i32,
+    pub user_id: i32,
+    pub content: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "microposts"]
+pub struct NewMicropost<'a> {
+    pub user_id: i32,
+    pub content: &'a str,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Serialize)]
+pub struct FeedItem {
+    pub micropost: Micropost,
+    pub user: User,
+}
+
+#[derive(Insertable)]
+#[table_name = "relationships"]
+pub struct NewRelationship {
+    pub follower_id: i32,
+    pub followed_id: i32,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Serialize)]
+pub struct RelationshipSummary {
+    pub following_count: i64,
+    pub followers_count: i64,
+    pub followed_by_viewer: bool,
+}
+
+// What the session backend needs of a `sessions` row.
+#[derive(Queryable)]
+pub struct Session {
+    pub id: String,
+    pub data: String,
+    pub last_seen_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "sessions"]
+pub struct NewSession<'a> {
+    pub id: &'a str,
+    pub user_id: Option<i32>,
+    pub data: &'a str,
+    pub created_at: NaiveDateTime,
+    pub last_seen_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Queryable)]
+pub struct DataExport {
+    pub id: i32,
+    pub user_id: i32,
+    pub status: String,
+    pub storage_key: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub completed_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[table_name = "data_exports"]
+pub struct NewDataExport<'a> {
+    pub user_id: i32,
+    pub status: &'a str,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Queryable)]
+pub struct Job {
+    pub id: i32,
+    pub kind: String,
+    pub payload: String,
+    pub status: String,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub run_at: NaiveDateTime,
+    pub locked_at: Option<NaiveDateTime>,
+    pub last_error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "jobs"]
+pub struct NewJob<'a> {
+    pub kind: &'a str,
+    pub payload: &'a str,
+    pub status: &'a str,
+    pub max_attempts: i32,
+    pub run_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Queryable)]
+pub struct Invitation {
+    pub id: i32,
+    pub email: String,
+    pub role: String,
+    // Only the digest of the token is kept; the link is shown once.
+    #[serde(skip_serializing)]
+    pub token_digest: String,
+    pub invited_by: Option<i32>,
+    pub user_id: Option<i32>,
+    pub expires_at: NaiveDateTime,
+    pub accepted_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "invitations"]
+pub struct NewInvitation<'a> {
+    pub email: &'a str,
+    pub role: &'a str,
+    pub token_digest: &'a str,
+    pub invited_by: Option<i32>,
+    pub expires_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
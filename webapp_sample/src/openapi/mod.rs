@@ -0,0 +1,389 @@
+pub mod schema;
+
+use serde::de::{DeserializeOwned};
+use serde_json::value::{Map, Value};
+
+use actix_web::http::{Method};
+
+use controllers::account_controller::{AccountDeletionParam, AccountExportPath};
+use controllers::invitations_controller::{InvitationsCreateParam, InvitationsReadPath};
+use controllers::jobs_controller::{JobsIndexParam, JobsReadPath};
+use controllers::microposts_controller::{MicropostsCreateParam, MicropostsPostParam, MicropostsReadPath};
+use controllers::relationships_controller::{RelationshipsCreateParam, RelationshipsPostParam, RelationshipsReadPath, RelationshipsUsersPath};
+use controllers::sessions_controller::{SessionsCreateParam, SessionsDeleteParam};
+use controllers::users_controller::{UsersAvatarPath, UsersCreateParam, UsersNewParam, UsersPasswordParam, UsersPostParam, UsersReadPath};
+use helpers::pagination_helper::{PageParam};
+use helpers::search_helper::{SearchParam};
+use images;
+
+use self::schema::{ObjectSchema};
+
+const FORM: &str = "application/x-www-form-urlencoded";
+
+// One operation of the document. Parameters and request bodies are described
+// from the extractor types the handler takes, so they follow the code.
+pub struct Operation {
+    tag: &'static str,
+    summary: &'static str,
+    public: bool,
+    parameters: Vec<Value>,
+    request_body: Option<Value>,
+    responses: Map<String, Value>,
+}
+
+impl Operation {
+    pub fn new(tag: &'static str, summary: &'static str) -> Self {
+        Operation {
+            tag,
+            summary,
+            public: false,
+            parameters: Vec::new(),
+            request_body: None,
+            responses: Map::new(),
+        }
+    }
+
+    // Reachable without a session.
+    pub fn public(mut self) -> Self {
+        self.public = true;
+        self
+    }
+
+    // The fields of a `Path<T>` extractor.
+    pub fn path<T: DeserializeOwned>(self) -> Self {
+        self.parameters::<T>("path")
+    }
+
+    // The fields of a `Query<T>` extractor.
+    pub fn query<T: DeserializeOwned>(self) -> Self {
+        self.parameters::<T>("query")
+    }
+
+    // A `Form<T>` extractor, sent as an urlencoded body.
+    pub fn form<T: DeserializeOwned>(mut self) -> Self {
+        let schema = ObjectSchema::of::<T>();
+        self.request_body = Some(json!({
+            "required": !schema.required.is_empty(),
+            "content":  {FORM: {"schema": schema.to_json()}},
+        }));
+        self
+    }
+
+    // A multipart body carrying one file in `field`.
+    pub fn multipart(mut self, field: &str, max_bytes: usize) -> Self {
+        let mut properties = Map::new();
+        properties.insert(field.to_string(), json!({"type": "string", "format": "binary", "maxLength": max_bytes}));
+
+        self.request_body = Some(json!({
+            "required": true,
+            "content":  {"multipart/form-data": {"schema": {
+                "type":       "object",
+                "properties": properties,
+                "required":   [field],
+            }}},
+        }));
+        self
+    }
+
+    // Error statuses carry the `{"error": ...}` body `JsonErrors` fills in.
+    pub fn response(self, status: u16, description: &str) -> Self {
+        let body = match status {
+            200 | 201 | 409    => Some(json!({"type": "object"})),
+            _ if status >= 400 => Some(json!({"$ref": "#/components/schemas/Error"})),
+            _                  => None,
+        };
+        self.with_response(status, description, body)
+    }
+
+    // Errors the user can fix, reported by `controllers::failed`.
+    pub fn errors(self, status: u16, description: &str) -> Self {
+        self.with_response(status, description, Some(json!({"$ref": "#/components/schemas/Errors"})))
+    }
+
+    fn with_response(mut self, status: u16, description: &str, body: Option<Value>) -> Self {
+        let mut response = json!({"description": description});
+        if let Some(body) = body {
+            response["content"] = json!({"application/json": {"schema": body}});
+        }
+
+        self.responses.insert(status.to_string(), response);
+        self
+    }
+
+    fn parameters<T: DeserializeOwned>(mut self, location: &str) -> Self {
+        let schema = ObjectSchema::of::<T>();
+
+        for (name, field) in schema.properties {
+            self.parameters.push(json!({
+                "name":     name,
+                "in":       location,
+                "required": location == "path" || schema.required.contains(&name),
+                "schema":   field,
+            }));
+        }
+        self
+    }
+
+    fn to_json(&self) -> Value {
+        let mut responses = self.responses.clone();
+        if !self.public {
+            responses.insert("401".to_string(), json!({
+                "description": "Not signed in. HTML clients are redirected to /signin.",
+            }));
+        }
+
+        let mut operation = json!({
+            "tags":       [self.tag],
+            "summary":    self.summary,
+            "parameters": self.parameters,
+            "responses":  responses,
+        });
+        if let Some(ref body) = self.request_body {
+            operation["requestBody"] = body.clone();
+        }
+        if self.public {
+            operation["security"] = json!([]);
+        }
+        operation
+    }
+}
+
+#[derive(Default)]
+pub struct Document {
+    paths: Map<String, Value>,
+}
+
+impl Document {
+    pub fn operation(mut self, method: Method, path: &str, operation: Operation) -> Self {
+        let item = self.paths
+            .entry(path.to_string())
+            .or_insert_with(|| json!({}));
+        item[method.as_str().to_lowercase()] = operation.to_json();
+        self
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "openapi": "3.0.3",
+            "info": {
+                "title":       "webapp_sample",
+                "version":     env!("CARGO_PKG_VERSION"),
+                "description": "Every page also answers with its data as JSON when its path ends in `.json` \
+                                (`/index.json` for `/`) or `Accept` lists `application/json`. \
+                                HTML clients are redirected with 303 where JSON clients get the statuses below.",
+            },
+            "paths": self.paths,
+            "components": {
+                "securitySchemes": {
+                    "session": {"type": "apiKey", "in": "cookie", "name": "session_id"},
+                },
+                "schemas": {
+                    "Error":  {
+                        "type": "object",
+                        "properties": {"error": {"type": "string"}},
+                        "required": ["error"],
+                    },
+                    "Errors": {
+                        "type": "object",
+                        "properties": {"errors": {"type": "array", "items": {"type": "string"}}},
+                        "required": ["errors"],
+                    },
+                },
+            },
+            "security": [{"session": []}],
+        })
+    }
+}
+
+// Describes every route `app()` registers; a test in main.rs checks that
+// none is left out.
+pub fn document() -> Value {
+    Document::default()
+        .operation(Method::GET, "/", Operation::new("microposts", "Home feed")
+            .query::<PageParam>()
+            .response(200, "Feed"))
+        .operation(Method::GET, "/openapi.json", Operation::new("docs", "This document")
+            .public()
+            .response(200, "OpenAPI document"))
+        .operation(Method::GET, "/api-docs", Operation::new("docs", "Swagger UI for this document")
+            .public()
+            .response(200, "HTML page"))
+
+        .operation(Method::GET, "/users", Operation::new("users", "List users")
+            .response(200, "Users"))
+        .operation(Method::GET, "/users/new", Operation::new("users", "Sign-up form for an invitation link")
+            .public()
+            .query::<UsersNewParam>()
+            .response(200, "Form data with the invitation")
+            .response(403, "Already signed in, or no usable invitation"))
+        .operation(Method::GET, "/users/search", Operation::new("users", "Search users")
+            .query::<SearchParam>()
+            .query::<PageParam>()
+            .response(200, "Matching users"))
+        .operation(Method::POST, "/users", Operation::new("users", "Sign up with an invitation; the email is the invited one")
+            .public()
+            .form::<UsersCreateParam>()
+            .response(201, "Created user")
+            .response(403, "Already signed in")
+            .errors(422, "Invalid fields, no usable invitation, or the email is taken")
+            .response(429, "Too many sign-ups"))
+        .operation(Method::GET, "/users/{id}", Operation::new("users", "Show a user and their microposts")
+            .path::<UsersReadPath>()
+            .query::<PageParam>()
+            .response(200, "User")
+            .response(404, "No such user"))
+        .operation(Method::GET, "/users/{id}/edit", Operation::new("users", "Profile form")
+            .path::<UsersReadPath>()
+            .response(200, "Form data"))
+        .operation(Method::POST, "/users/{id}", Operation::new("users", "Update or delete a user; `method` overrides POST")
+            .path::<UsersReadPath>()
+            .form::<UsersPostParam>()
+            .response(200, "Updated user")
+            .response(204, "Deleted")
+            .response(400, "No lock version")
+            .response(409, "Edited concurrently; the current user as on the edit page"))
+        .operation(Method::PATCH, "/users/{id}", Operation::new("users", "Update a user")
+            .path::<UsersReadPath>()
+            .form::<UsersPostParam>()
+            .response(200, "Updated user")
+            .response(400, "No lock version")
+            .response(409, "Edited concurrently; the current user as on the edit page"))
+        .operation(Method::DELETE, "/users/{id}", Operation::new("users", "Delete a user")
+            .path::<UsersReadPath>()
+            .response(204, "Deleted"))
+        .operation(Method::GET, "/users/{id}/password", Operation::new("users", "Password form")
+            .path::<UsersReadPath>()
+            .response(200, "Form data")
+            .response(403, "Not this user"))
+        .operation(Method::POST, "/users/{id}/password", Operation::new("users", "Change the password")
+            .path::<UsersReadPath>()
+            .form::<UsersPasswordParam>()
+            .response(200, "Changed")
+            .response(403, "Not this user")
+            .errors(422, "Wrong current password or invalid new one"))
+        .operation(Method::POST, "/users/{id}/avatar", Operation::new("users", "Upload an avatar")
+            .path::<UsersReadPath>()
+            .multipart("avatar", images::MAX_UPLOAD_BYTES)
+            .response(200, "Stored")
+            .errors(422, "Missing, too large or not a PNG, JPEG or GIF"))
+        .operation(Method::GET, "/users/{id}/avatar/{size}", Operation::new("users", "Avatar image")
+            .path::<UsersAvatarPath>()
+            .response(200, "PNG image")
+            .response(404, "No avatar"))
+        .operation(Method::GET, "/users/{id}/following", Operation::new("relationships", "Users a user follows")
+            .path::<RelationshipsUsersPath>()
+            .query::<PageParam>()
+            .response(200, "Users"))
+        .operation(Method::GET, "/users/{id}/followers", Operation::new("relationships", "Users following a user")
+            .path::<RelationshipsUsersPath>()
+            .query::<PageParam>()
+            .response(200, "Users"))
+
+        .operation(Method::POST, "/microposts", Operation::new("microposts", "Post a micropost")
+            .form::<MicropostsCreateParam>()
+            .response(201, "Created micropost")
+            .errors(422, "Invalid content"))
+        .operation(Method::POST, "/microposts/{id}", Operation::new("microposts", "Delete a micropost; `method` overrides POST")
+            .path::<MicropostsReadPath>()
+            .form::<MicropostsPostParam>()
+            .response(204, "Deleted"))
+        .operation(Method::DELETE, "/microposts/{id}", Operation::new("microposts", "Delete a micropost")
+            .path::<MicropostsReadPath>()
+            .response(204, "Deleted"))
+
+        .operation(Method::POST, "/relationships", Operation::new("relationships", "Follow a user")
+            .form::<RelationshipsCreateParam>()
+            .response(201, "Followed"))
+        .operation(Method::POST, "/relationships/{followed_id}", Operation::new("relationships", "Unfollow a user; `method` overrides POST")
+            .path::<RelationshipsReadPath>()
+            .form::<RelationshipsPostParam>()
+            .response(204, "Unfollowed"))
+        .operation(Method::DELETE, "/relationships/{followed_id}", Operation::new("relationships", "Unfollow a user")
+            .path::<RelationshipsReadPath>()
+            .response(204, "Unfollowed"))
+
+        .operation(Method::GET, "/account", Operation::new("account", "Account page with exports and deletion state")
+            .response(200, "Account"))
+        .operation(Method::POST, "/account/exports", Operation::new("account", "Request a data export")
+            .response(201, "Queued export"))
+        .operation(Method::GET, "/account/exports/{id}", Operation::new("account", "Download a data export")
+            .path::<AccountExportPath>()
+            .response(200, "ZIP archive")
+            .response(404, "Not ready or not yours"))
+        .operation(Method::POST, "/account/deletion", Operation::new("account", "Schedule deletion; `method` = DELETE cancels it")
+            .form::<AccountDeletionParam>()
+            .response(201, "Scheduled")
+            .response(204, "Cancelled")
+            .errors(422, "Wrong password"))
+        .operation(Method::DELETE, "/account/deletion", Operation::new("account", "Cancel a scheduled deletion")
+            .response(204, "Cancelled"))
+
+        .operation(Method::GET, "/admin/jobs", Operation::new("admin", "Background jobs")
+            .query::<JobsIndexParam>()
+            .response(200, "Counts and latest jobs")
+            .response(403, "Not an admin"))
+        .operation(Method::POST, "/admin/jobs/{id}/retry", Operation::new("admin", "Retry a dead job")
+            .path::<JobsReadPath>()
+            .response(200, "Queued job")
+            .response(403, "Not an admin"))
+
+        .operation(Method::GET, "/admin/invitations", Operation::new("admin", "Invitations, newest first")
+            .response(200, "Invitations with their status")
+            .response(403, "Not an admin"))
+        .operation(Method::POST, "/admin/invitations", Operation::new("admin", "Invite an email address")
+            .form::<InvitationsCreateParam>()
+            .response(201, "Invitation and its link, shown only now")
+            .response(403, "Not an admin")
+            .errors(422, "Invalid email or role, or the email is taken"))
+        .operation(Method::POST, "/admin/invitations/{id}/revoke", Operation::new("admin", "Revoke a pending invitation")
+            .path::<InvitationsReadPath>()
+            .response(200, "Revoked invitation")
+            .response(403, "Not an admin")
+            .response(404, "No pending invitation"))
+        .operation(Method::POST, "/admin/invitations/{id}/resend", Operation::new("admin", "Replace the link of an unused invitation")
+            .path::<InvitationsReadPath>()
+            .response(200, "Invitation and its new link")
+            .response(403, "Not an admin")
+            .response(404, "No unused invitation"))
+
+        .operation(Method::GET, "/events", Operation::new("events", "WebSocket of user changes")
+            .response(101, "Switching protocols"))
+
+        .operation(Method::GET, "/signin", Operation::new("sessions", "Sign-in form")
+            .public()
+            .response(200, "Form data"))
+        .operation(Method::POST, "/signin", Operation::new("sessions", "Sign in")
+            .public()
+            .form::<SessionsCreateParam>()
+            .response(201, "Signed in; sets the session cookie")
+            .errors(401, "Wrong email or password")
+            .errors(422, "Missing email or password")
+            .response(429, "Too many attempts"))
+        .operation(Method::POST, "/signout", Operation::new("sessions", "Sign out; `method` overrides POST")
+            .form::<SessionsDeleteParam>()
+            .response(204, "Signed out"))
+        .operation(Method::DELETE, "/signout", Operation::new("sessions", "Sign out")
+            .response(204, "Signed out"))
+        .to_json()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operations_are_described_from_extractor_types() {
+        let document = document();
+        let create = &document["paths"]["/users"]["post"];
+
+        let schema = &create["requestBody"]["content"][FORM]["schema"];
+        assert_eq!(schema["required"], json!(["invitation_token", "user_name", "user_password"]));
+        assert_eq!(schema["properties"]["user_name"], json!({"type": "string"}));
+        assert_eq!(create["security"], json!([]));
+
+        let show = &document["paths"]["/users/{id}"]["get"];
+        assert_eq!(show["parameters"][0], json!({"name": "id", "in": "path", "required": true, "schema": {"type": "integer", "format": "int32"}}));
+        assert_eq!(show["parameters"][1]["required"], json!(false));
+        assert!(show["responses"]["401"].is_object());
+    }
+}
@@ -0,0 +1,235 @@
+use serde::de::{self, DeserializeOwned, DeserializeSeed, Deserializer, IntoDeserializer, MapAccess, Visitor};
+use serde::de::value::{Error, SeqDeserializer, StrDeserializer};
+use serde_json::value::{Map, Value};
+
+use std::vec;
+
+// Schema of a flat struct, worked out from its `Deserialize` impl: the struct
+// is deserialized from a stand-in that notes the type each field asks for.
+// A field is required when leaving it out fails, which covers both `Option`
+// and `#[serde(default)]`.
+#[derive(Debug, PartialEq)]
+pub struct ObjectSchema {
+    pub properties: Map<String, Value>,
+    pub required: Vec<String>,
+}
+
+impl ObjectSchema {
+    pub fn of<T: DeserializeOwned>() -> Self {
+        let mut fields: &'static [&'static str] = &[];
+        let mut properties = Map::new();
+
+        T::deserialize(StructDeserializer{fields: &mut fields, properties: &mut properties, skip: None})
+            .expect("only flat structs can be described");
+
+        let required = fields
+            .iter()
+            .filter(|&&field| {
+                let mut ignored: &'static [&'static str] = &[];
+                let mut scratch = Map::new();
+                T::deserialize(StructDeserializer{fields: &mut ignored, properties: &mut scratch, skip: Some(field)}).is_err()
+            })
+            .map(|field| field.to_string())
+            .collect();
+
+        ObjectSchema{properties, required}
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "type":       "object",
+            "properties": self.properties,
+            "required":   self.required,
+        })
+    }
+}
+
+struct StructDeserializer<'a> {
+    fields: &'a mut &'static [&'static str],
+    properties: &'a mut Map<String, Value>,
+    skip: Option<&'a str>,
+}
+
+impl<'de, 'a> Deserializer<'de> for StructDeserializer<'a> {
+    type Error = Error;
+
+    fn deserialize_struct<V: Visitor<'de>>(self, _name: &'static str, fields: &'static [&'static str], visitor: V) -> Result<V::Value, Error> {
+        *self.fields = fields;
+        let skip = self.skip;
+
+        visitor.visit_map(Fields {
+            remaining: fields.iter().cloned().filter(|field| Some(*field) != skip).collect::<Vec<_>>().into_iter(),
+            current: None,
+            properties: self.properties,
+        })
+    }
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map enum
+        identifier ignored_any
+    }
+}
+
+struct Fields<'a> {
+    remaining: vec::IntoIter<&'static str>,
+    current: Option<&'static str>,
+    properties: &'a mut Map<String, Value>,
+}
+
+impl<'de, 'a> MapAccess<'de> for Fields<'a> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+        self.current = self.remaining.next();
+
+        match self.current {
+            Some(field) => {
+                let key: StrDeserializer<Error> = field.into_deserializer();
+                seed.deserialize(key).map(Some)
+            },
+            None        => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let mut schema = json!({});
+        let value = seed.deserialize(FieldDeserializer{schema: &mut schema})?;
+
+        if let Some(field) = self.current {
+            self.properties.insert(field.to_string(), schema);
+        }
+        Ok(value)
+    }
+}
+
+// Answers with a placeholder of whatever type the field asks for.
+struct FieldDeserializer<'a> {
+    schema: &'a mut Value,
+}
+
+impl<'a> FieldDeserializer<'a> {
+    fn typed(self, schema: Value) {
+        *self.schema = schema;
+    }
+}
+
+impl<'de, 'a> Deserializer<'de> for FieldDeserializer<'a> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.typed(json!({"type": "boolean"}));
+        visitor.visit_bool(false)
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.typed(json!({"type": "integer", "format": "int32"}));
+        visitor.visit_i32(0)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.typed(json!({"type": "integer", "format": "int64"}));
+        visitor.visit_i64(0)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_u64(visitor)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_u64(visitor)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_u64(visitor)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.typed(json!({"type": "integer", "minimum": 0}));
+        visitor.visit_u64(0)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_f64(visitor)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.typed(json!({"type": "number"}));
+        visitor.visit_f64(0.0)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.typed(json!({"type": "string", "maxLength": 1}));
+        visitor.visit_char(' ')
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.typed(json!({"type": "string"}));
+        visitor.visit_str("")
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let schema = self.schema;
+        let value = visitor.visit_some(FieldDeserializer{schema: &mut *schema})?;
+
+        if let Value::Object(ref mut schema) = *schema {
+            schema.insert("nullable".to_string(), Value::Bool(true));
+        }
+        Ok(value)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.typed(json!({"type": "array"}));
+        visitor.visit_seq(SeqDeserializer::<_, Error>::new(Vec::<String>::new().into_iter()))
+    }
+
+    forward_to_deserialize_any! {
+        bytes byte_buf unit unit_struct newtype_struct tuple tuple_struct map
+        struct enum identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct Param {
+        name: String,
+        page: Option<i64>,
+        #[serde(default)]
+        method: String,
+        size: u32,
+    }
+
+    #[test]
+    fn describes_fields_and_which_are_required() {
+        let schema = ObjectSchema::of::<Param>();
+
+        assert_eq!(schema.properties["name"], json!({"type": "string"}));
+        assert_eq!(schema.properties["page"], json!({"type": "integer", "format": "int64", "nullable": true}));
+        assert_eq!(schema.properties["size"], json!({"type": "integer", "minimum": 0}));
+        assert_eq!(schema.required, vec!["name".to_string(), "size".to_string()]);
+    }
+}
@@ -0,0 +1,228 @@
+use std::convert::TryFrom;
+use std::fmt;
+
+use argon2::{self, Argon2, Params, PasswordHash, PasswordVerifier};
+use argon2::password_hash::{self, SaltString};
+use bcrypt;
+use rand::prelude::*;
+
+use settings::{EnvVars};
+
+#[derive(Debug)]
+pub struct PasswordError(String);
+
+impl fmt::Display for PasswordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "password hashing failed: {}", self.0)
+    }
+}
+
+// One password hashing algorithm with fixed parameters.
+// Digests are self-describing (`$2b$12$...`, `$argon2id$v=19$m=...`), so the
+// algorithm and parameters a password was hashed with are read back from the digest.
+pub trait PasswordHasher: Send + Sync {
+    // True when `digest` was produced by this algorithm, whatever its parameters.
+    fn recognizes(&self, digest: &str) -> bool;
+    fn hash(&self, password: &str) -> Result<String, PasswordError>;
+    fn verify(&self, password: &str, digest: &str) -> bool;
+    // True when `digest` uses parameters at least as strong as this hasher's.
+    fn satisfied_by(&self, digest: &str) -> bool;
+}
+
+pub struct Bcrypt {
+    pub cost: u32,
+}
+
+impl Bcrypt {
+    fn cost_of(digest: &str) -> Option<u32> {
+        // $2b$12$<salt and hash>
+        digest.split('$').nth(2).and_then(|cost| cost.parse().ok())
+    }
+}
+
+impl PasswordHasher for Bcrypt {
+    fn recognizes(&self, digest: &str) -> bool {
+        digest.starts_with("$2")
+    }
+
+    fn hash(&self, password: &str) -> Result<String, PasswordError> {
+        bcrypt::hash(password, self.cost).map_err(|e| PasswordError(e.to_string()))
+    }
+
+    fn verify(&self, password: &str, digest: &str) -> bool {
+        bcrypt::verify(password, digest).unwrap_or(false)
+    }
+
+    fn satisfied_by(&self, digest: &str) -> bool {
+        match Bcrypt::cost_of(digest) {
+            Some(cost) => self.recognizes(digest) && cost >= self.cost,
+            None       => false,
+        }
+    }
+}
+
+pub struct Argon2id {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Argon2id {
+    fn argon2(&self) -> Result<Argon2<'static>, PasswordError> {
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, None)
+            .map_err(|e| PasswordError(e.to_string()))?;
+
+        Ok(Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params))
+    }
+}
+
+impl PasswordHasher for Argon2id {
+    fn recognizes(&self, digest: &str) -> bool {
+        digest.starts_with("$argon2id$")
+    }
+
+    fn hash(&self, password: &str) -> Result<String, PasswordError> {
+        use argon2::PasswordHasher as _;
+
+        let mut salt_bytes = [0u8; 16];
+        thread_rng().fill(&mut salt_bytes);
+        let salt = SaltString::encode_b64(&salt_bytes)
+            .map_err(|e| PasswordError(e.to_string()))?;
+
+        self.argon2()?
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|e| PasswordError(e.to_string()))
+    }
+
+    fn verify(&self, password: &str, digest: &str) -> bool {
+        // Verification uses the parameters stored in the digest, not ours.
+        match PasswordHash::new(digest) {
+            Ok(hash) => Argon2::default().verify_password(password.as_bytes(), &hash).is_ok(),
+            Err(_)   => false,
+        }
+    }
+
+    fn satisfied_by(&self, digest: &str) -> bool {
+        let params = PasswordHash::new(digest)
+            .map_err(|_| password_hash::Error::PhcStringField)
+            .and_then(|hash| Params::try_from(&hash));
+
+        match params {
+            Ok(params) => {
+                self.recognizes(digest)
+                    && params.m_cost() >= self.memory_kib
+                    && params.t_cost() >= self.iterations
+                    && params.p_cost() >= self.parallelism
+            },
+            Err(_) => false,
+        }
+    }
+}
+
+pub enum Verification {
+    Invalid,
+    Valid,
+    // The password matched, but the digest is weaker than the policy and should be replaced.
+    ValidNeedsRehash,
+}
+
+// New passwords are hashed with `current`; digests from any supported
+// algorithm still verify, so the policy can change without resetting users.
+pub struct PasswordPolicy {
+    current: Box<dyn PasswordHasher>,
+    supported: Vec<Box<dyn PasswordHasher>>,
+}
+
+impl PasswordPolicy {
+    pub fn new(current: Box<dyn PasswordHasher>) -> Self {
+        Self {
+            current,
+            supported: vec![
+                Box::new(Bcrypt{cost: bcrypt::DEFAULT_COST}),
+                Box::new(Argon2id{memory_kib: 0, iterations: 0, parallelism: 0}),
+            ],
+        }
+    }
+
+    // PASSWORD_HASHER selects `argon2id` (default) or `bcrypt`.
+    // BCRYPT_COST, ARGON2_MEMORY_KIB, ARGON2_ITERATIONS and ARGON2_PARALLELISM tune them.
+    pub fn from_vars(vars: &EnvVars) -> Self {
+        let current: Box<dyn PasswordHasher> = match vars.var("PASSWORD_HASHER") {
+            Some(ref name) if name == "bcrypt" => Box::new(Bcrypt{
+                cost: vars.or("BCRYPT_COST", 12),
+            }),
+            _ => Box::new(Argon2id{
+                memory_kib:  vars.or("ARGON2_MEMORY_KIB", 19_456),
+                iterations:  vars.or("ARGON2_ITERATIONS", 2),
+                parallelism: vars.or("ARGON2_PARALLELISM", 1),
+            }),
+        };
+
+        PasswordPolicy::new(current)
+    }
+
+    pub fn hash(&self, password: &str) -> Result<String, PasswordError> {
+        self.current.hash(password)
+    }
+
+    pub fn verify(&self, password: &str, digest: &str) -> Verification {
+        let valid = self.supported
+            .iter()
+            .find(|hasher| hasher.recognizes(digest))
+            .map(|hasher| hasher.verify(password, digest))
+            .unwrap_or(false);
+
+        if !valid {
+            Verification::Invalid
+        } else if self.current.satisfied_by(digest) {
+            Verification::Valid
+        } else {
+            Verification::ValidNeedsRehash
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn weak_argon2() -> Argon2id {
+        Argon2id{memory_kib: 1024, iterations: 1, parallelism: 1}
+    }
+
+    #[test]
+    fn digests_record_their_parameters() {
+        let bcrypt_digest = Bcrypt{cost: 4}.hash("password1").unwrap();
+        assert!(bcrypt_digest.starts_with("$2"));
+        assert_eq!(Bcrypt::cost_of(&bcrypt_digest), Some(4));
+
+        let argon2_digest = weak_argon2().hash("password1").unwrap();
+        assert!(argon2_digest.starts_with("$argon2id$v=19$m=1024,t=1,p=1$"));
+    }
+
+    #[test]
+    fn verify_accepts_any_supported_algorithm() {
+        let policy = PasswordPolicy::new(Box::new(weak_argon2()));
+        let bcrypt_digest = Bcrypt{cost: 4}.hash("password1").unwrap();
+        let argon2_digest = policy.hash("password1").unwrap();
+
+        assert!(matches!(policy.verify("password1", &argon2_digest), Verification::Valid));
+        assert!(matches!(policy.verify("password2", &argon2_digest), Verification::Invalid));
+        assert!(matches!(policy.verify("password1", &bcrypt_digest), Verification::ValidNeedsRehash));
+        assert!(matches!(policy.verify("password2", &bcrypt_digest), Verification::Invalid));
+        assert!(matches!(policy.verify("password1", "not a digest"), Verification::Invalid));
+    }
+
+    #[test]
+    fn weaker_parameters_need_rehash() {
+        let bcrypt_policy = PasswordPolicy::new(Box::new(Bcrypt{cost: 5}));
+        let low_cost = Bcrypt{cost: 4}.hash("password1").unwrap();
+        assert!(matches!(bcrypt_policy.verify("password1", &low_cost), Verification::ValidNeedsRehash));
+
+        let argon2_policy = PasswordPolicy::new(Box::new(Argon2id{memory_kib: 2048, iterations: 1, parallelism: 1}));
+        let low_memory = weak_argon2().hash("password1").unwrap();
+        assert!(matches!(argon2_policy.verify("password1", &low_memory), Verification::ValidNeedsRehash));
+        assert!(matches!(PasswordPolicy::new(Box::new(weak_argon2())).verify("password1", &low_memory), Verification::Valid));
+    }
+}
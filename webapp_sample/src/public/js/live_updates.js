@@ -0,0 +1,99 @@
+// Keeps the users list current and announces changes as toasts, using the
+// events the server publishes on /events. Loaded by users_index.hbs.
+(function () {
+  'use strict';
+
+  var list = document.querySelector('[data-live-users]');
+  var rowTemplate = document.getElementById('user-row');
+  var toasts = document.getElementById('toasts');
+  var TOAST_MS = 5000;
+  var MAX_RETRY_MS = 30000;
+
+  function row(id) {
+    return list.querySelector('tr[data-user-id="' + id + '"]');
+  }
+
+  function addRow(user) {
+    if (row(user.id)) {
+      return;
+    }
+
+    var tr = document.importNode(rowTemplate.content, true).querySelector('tr');
+    tr.setAttribute('data-user-id', user.id);
+    tr.querySelector('[data-field="id"]').textContent = user.id;
+    tr.querySelector('[data-field="name"]').textContent = user.name;
+    tr.querySelectorAll('[data-href]').forEach(function (link) {
+      link.href = link.getAttribute('data-href').replace('{id}', user.id);
+    });
+    tr.querySelectorAll('[data-action]').forEach(function (form) {
+      form.action = form.getAttribute('data-action').replace('{id}', user.id);
+    });
+    list.appendChild(tr);
+  }
+
+  function updateRow(user) {
+    var tr = row(user.id);
+    if (tr) {
+      tr.querySelector('[data-field="name"]').textContent = user.name;
+    }
+  }
+
+  function removeRow(user) {
+    var tr = row(user.id);
+    if (tr) {
+      tr.parentNode.removeChild(tr);
+    }
+  }
+
+  function toast(message) {
+    var alert = document.createElement('div');
+    alert.className = 'alert alert-info shadow-sm';
+    alert.setAttribute('role', 'status');
+    alert.textContent = message;
+    toasts.appendChild(alert);
+
+    setTimeout(function () {
+      toasts.removeChild(alert);
+    }, TOAST_MS);
+  }
+
+  var handlers = {
+    user_created: function (user) {
+      addRow(user);
+      toast(user.name + ' さんが登録されました');
+    },
+    user_updated: function (user) {
+      updateRow(user);
+      toast(user.name + ' さんの情報が更新されました');
+    },
+    user_deleted: function (user) {
+      removeRow(user);
+      toast(user.name + ' さんが削除されました');
+    }
+  };
+
+  function connect(retryMs) {
+    var scheme = location.protocol === 'https:' ? 'wss:' : 'ws:';
+    var socket = new WebSocket(scheme + '//' + location.host + '/events');
+
+    socket.onopen = function () {
+      retryMs = 1000;
+    };
+    socket.onmessage = function (message) {
+      var event = JSON.parse(message.data);
+      if (handlers[event.type]) {
+        handlers[event.type](event.user);
+      }
+    };
+    // Reconnect with backoff after restarts and network drops.
+    socket.onclose = function () {
+      setTimeout(function () {
+        connect(Math.min(retryMs * 2, MAX_RETRY_MS));
+      }, retryMs);
+    };
+  }
+
+  if (list && rowTemplate && toasts && 'WebSocket' in window) {
+    connect(1000);
+  }
+})();
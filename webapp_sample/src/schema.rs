@@ -1,3 +1,76 @@
+table! {
+    data_exports (id) {
+        id -> Integer,
+        user_id -> Integer,
+        status -> Text,
+        storage_key -> Nullable<Text>,
+        created_at -> Timestamp,
+        completed_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    invitations (id) {
+        id -> Integer,
+        email -> Text,
+        role -> Text,
+        token_digest -> Text,
+        invited_by -> Nullable<Integer>,
+        user_id -> Nullable<Integer>,
+        expires_at -> Timestamp,
+        accepted_at -> Nullable<Timestamp>,
+        revoked_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    jobs (id) {
+        id -> Integer,
+        kind -> Text,
+        payload -> Text,
+        status -> Text,
+        attempts -> Integer,
+        max_attempts -> Integer,
+        run_at -> Timestamp,
+        locked_at -> Nullable<Timestamp>,
+        last_error -> Nullable<Text>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    microposts (id) {
+        id -> Integer,
+        user_id -> Integer,
+        content -> Text,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    relationships (id) {
+        id -> Integer,
+        follower_id -> Integer,
+        followed_id -> Integer,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    sessions (id) {
+        id -> Text,
+        user_id -> Nullable<Integer>,
+        data -> Text,
+        created_at -> Timestamp,
+        last_seen_at -> Timestamp,
+    }
+}
+
 table! {
     users (id) {
         id -> Integer,
@@ -8,5 +81,23 @@ table! {
         session_digest -> Nullable<Text>,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        avatar_key -> Nullable<Text>,
+        lock_version -> Integer,
+        deletion_scheduled_at -> Nullable<Timestamp>,
+        admin -> Bool,
+        time_zone -> Text,
     }
 }
+
+joinable!(data_exports -> users (user_id));
+joinable!(microposts -> users (user_id));
+
+allow_tables_to_appear_in_same_query!(
+    data_exports,
+    invitations,
+    jobs,
+    microposts,
+    relationships,
+    sessions,
+    users,
+);
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono_tz::{Tz};
+
+use models::{UserSession};
+use settings::{env_or};
+
+// What `Authenticate` knows about a session once it is found valid.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ValidSession {
+    pub admin: bool,
+    pub time_zone: Tz,
+}
+
+struct Entry {
+    user_id: i32,
+    session: ValidSession,
+    validated_at: Instant,
+}
+
+// Sessions `Authenticate` validated recently, keyed by session id, so most
+// requests need neither the database nor a digest. `DbExecutor` forgets a
+// user's entries whenever it changes their session digest, admin flag or
+// time zone. Entries expire after `ttl`, which bounds how long a change made
+// by another process (e.g. `webapp_admin session revoke`) goes unnoticed.
+pub struct SessionCache {
+    entries: Mutex<HashMap<String, Entry>>,
+    capacity: usize,
+    ttl: Duration,
+}
+
+impl SessionCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        SessionCache {
+            entries: Mutex::new(HashMap::new()),
+            capacity,
+            ttl,
+        }
+    }
+
+    // SESSION_CACHE_SIZE (default 10000) and SESSION_CACHE_TTL_SECS (default 60).
+    pub fn from_env() -> Self {
+        SessionCache::new(
+            env_or("SESSION_CACHE_SIZE", 10_000),
+            Duration::from_secs(env_or("SESSION_CACHE_TTL_SECS", 60)),
+        )
+    }
+
+    pub fn get(&self, user_session: &UserSession) -> Option<ValidSession> {
+        let entries = self.entries.lock().unwrap();
+
+        entries
+            .get(&user_session.session_id)
+            .filter(|entry| entry.user_id == user_session.user_id && entry.validated_at.elapsed() < self.ttl)
+            .map(|entry| entry.session)
+    }
+
+    // When full, expired entries go first, then the oldest one.
+    pub fn insert(&self, user_session: &UserSession, session: ValidSession) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+
+        if entries.len() >= self.capacity && !entries.contains_key(&user_session.session_id) {
+            let ttl = self.ttl;
+            entries.retain(|_, entry| entry.validated_at.elapsed() < ttl);
+
+            if entries.len() >= self.capacity {
+                let oldest = entries
+                    .iter()
+                    .min_by_key(|&(_, entry)| entry.validated_at)
+                    .map(|(session_id, _)| session_id.clone());
+                if let Some(session_id) = oldest {
+                    entries.remove(&session_id);
+                }
+            }
+        }
+
+        entries.insert(user_session.session_id.clone(), Entry {
+            user_id: user_session.user_id,
+            session,
+            validated_at: Instant::now(),
+        });
+    }
+
+    pub fn forget(&self, user_session: &UserSession) {
+        self.entries.lock().unwrap().remove(&user_session.session_id);
+    }
+
+    pub fn forget_user(&self, user_id: i32) {
+        self.entries.lock().unwrap().retain(|_, entry| entry.user_id != user_id);
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn user_session(user_id: i32, session_id: &str) -> UserSession {
+        UserSession{user_id, session_id: session_id.to_string()}
+    }
+
+    #[test]
+    fn bounded_and_forgets_per_user() {
+        let cache = SessionCache::new(2, Duration::from_secs(60));
+        cache.insert(&user_session(1, "a"), ValidSession{admin: false, time_zone: Tz::UTC});
+        thread::sleep(Duration::from_millis(1));
+        cache.insert(&user_session(2, "b"), ValidSession{admin: false, time_zone: Tz::UTC});
+        cache.insert(&user_session(1, "c"), ValidSession{admin: false, time_zone: Tz::UTC});
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&user_session(1, "a")), None);
+        assert_eq!(cache.get(&user_session(1, "c")), Some(ValidSession{admin: false, time_zone: Tz::UTC}));
+        assert_eq!(cache.get(&user_session(2, "c")), None);
+
+        cache.forget_user(1);
+        assert_eq!(cache.get(&user_session(1, "c")), None);
+        assert!(cache.get(&user_session(2, "b")).is_some());
+
+        let expired = SessionCache::new(2, Duration::from_secs(0));
+        expired.insert(&user_session(1, "a"), ValidSession{admin: false, time_zone: Tz::UTC});
+        assert_eq!(expired.get(&user_session(1, "a")), None);
+    }
+}
@@ -0,0 +1,51 @@
+use bcrypt;
+use blake2::{Blake2b, Digest};
+use blake2::digest::consts::{U32};
+
+type Blake2b256 = Blake2b<U32>;
+
+// What `users.session_digest` holds for a session id. Session ids are 30
+// random alphanumerics, so a plain fast hash keeps them out of the table as
+// well as a stretched one would, without costing every request a bcrypt run.
+pub fn session_digest(session_id: &str) -> String {
+    Blake2b256::digest(session_id.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// Digests written before the switch are bcrypt; they still verify, and
+// `ValidateUserSession` replaces them on first use.
+pub fn is_legacy(digest: &str) -> bool {
+    digest.starts_with("$2")
+}
+
+pub fn verify_session_digest(session_id: &str, digest: &str) -> bool {
+    if is_legacy(digest) {
+        return bcrypt::verify(session_id, digest).unwrap_or(false);
+    }
+
+    let expected = session_digest(session_id);
+    expected.len() == digest.len()
+        && expected.bytes().zip(digest.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verifies_fast_and_legacy_digests() {
+        let digest = session_digest("abc123");
+        assert_eq!(digest.len(), 64);
+        assert!(!is_legacy(&digest));
+        assert!(verify_session_digest("abc123", &digest));
+        assert!(!verify_session_digest("abc124", &digest));
+        assert!(!verify_session_digest("abc123", &digest[1..]));
+
+        let legacy = bcrypt::hash("abc123", 4).unwrap();
+        assert!(is_legacy(&legacy));
+        assert!(verify_session_digest("abc123", &legacy));
+        assert!(!verify_session_digest("abc124", &legacy));
+    }
+}
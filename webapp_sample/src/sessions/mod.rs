@@ -0,0 +1,287 @@
+pub mod cache;
+pub mod digest;
+pub mod purger;
+
+use std::collections::HashMap;
+use std::iter;
+use std::rc::Rc;
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use futures::{future, Future};
+use rand::prelude::*;
+use rand::distributions::{Alphanumeric};
+use serde_json;
+
+use actix::prelude::*;
+use actix_web::{Error, HttpRequest, HttpResponse, Result};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::middleware::{Response};
+use actix_web::middleware::session::{SessionBackend, SessionImpl};
+
+use context::{Context};
+use db::{DbExecutor, sessions_message};
+use helpers::sessions_helper::{USER_SESSION_KEY};
+use middleware::{STATIC_PREFIX};
+use middleware::request_id::{RequestId};
+use models::{UserSession};
+use settings::{EnvVars, SharedSettings};
+
+const COOKIE_NAME: &str = "session_id";
+const SESSION_ID_LEN: usize = 40;
+// last_seen_at is refreshed at most this often for requests that change nothing.
+const TOUCH_INTERVAL_SECS: i64 = 60;
+
+#[derive(Clone, Copy, Debug)]
+pub struct SessionTimeouts {
+    pub idle: Duration,
+    pub absolute: Duration,
+}
+
+impl SessionTimeouts {
+    // SESSION_IDLE_TIMEOUT_SECS (default 2 hours) and
+    // SESSION_ABSOLUTE_TIMEOUT_SECS (default 24 hours).
+    pub fn from_vars(vars: &EnvVars) -> Self {
+        SessionTimeouts {
+            idle: Duration::seconds(vars.or("SESSION_IDLE_TIMEOUT_SECS", 2 * 60 * 60)),
+            absolute: Duration::seconds(vars.or("SESSION_ABSOLUTE_TIMEOUT_SECS", 24 * 60 * 60)),
+        }
+    }
+
+    // Sessions last seen at or before the first, or created at or before the second, have expired.
+    pub fn cutoffs(&self, now: NaiveDateTime) -> (NaiveDateTime, NaiveDateTime) {
+        (now - self.idle, now - self.absolute)
+    }
+}
+
+fn create_session_id() -> String {
+    let mut rng = thread_rng();
+    iter::repeat(())
+        .map(|()| rng.sample(Alphanumeric))
+        .take(SESSION_ID_LEN)
+        .collect()
+}
+
+fn valid_session_id(id: &str) -> bool {
+    id.len() == SESSION_ID_LEN && id.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+struct Config {
+    settings: SharedSettings,
+    secure: bool,
+}
+
+// Session backend for `SessionStorage` that keeps the data in the `sessions`
+// table. The cookie only carries an opaque random id.
+pub struct DbSessionBackend(Rc<Config>);
+
+impl DbSessionBackend {
+    pub fn new(settings: SharedSettings) -> Self {
+        DbSessionBackend(Rc::new(Config {
+            settings,
+            secure: false,
+        }))
+    }
+
+    pub fn secure(self, secure: bool) -> Self {
+        DbSessionBackend(Rc::new(Config {
+            settings: self.0.settings.clone(),
+            secure,
+        }))
+    }
+}
+
+impl SessionBackend<Context> for DbSessionBackend {
+    type Session = DbSession;
+    type ReadFuture = Box<dyn Future<Item = DbSession, Error = Error>>;
+
+    fn from_request(&self, req: &mut HttpRequest<Context>) -> Self::ReadFuture {
+        let RequestId(request_id) = RequestId::get(req);
+        let db = req.state().db.clone();
+        let config = self.0.clone();
+        let timeouts = config.settings.current().session_timeouts;
+
+        // Static files never look at the session, so they are served without reading it.
+        if req.path().starts_with(STATIC_PREFIX) {
+            return Box::new(future::ok(DbSession::new(db, request_id, config, timeouts)));
+        }
+
+        let id = match req.cookie(COOKIE_NAME) {
+            Some(ref cookie) if valid_session_id(cookie.value()) => cookie.value().to_string(),
+            _ => return Box::new(future::ok(DbSession::new(db, request_id, config, timeouts))),
+        };
+
+        let now = Utc::now().naive_utc();
+        let (idle_since, created_since) = timeouts.cutoffs(now);
+
+        Box::new(
+            db
+                .send(sessions_message::ReadSession{
+                    request_id: request_id.clone(),
+                    id,
+                    idle_since,
+                    created_since,
+                })
+                .from_err()
+                .and_then(|res| res)
+                .map(move |session| {
+                    let mut db_session = DbSession::new(db, request_id, config, timeouts);
+
+                    if let Some(session) = session {
+                        if let Ok(state) = serde_json::from_str::<HashMap<String, String>>(&session.data) {
+                            db_session.loaded_user = state.get(USER_SESSION_KEY).cloned();
+                            db_session.state = state;
+                            db_session.stale = now - session.last_seen_at > Duration::seconds(TOUCH_INTERVAL_SECS);
+                            db_session.id = Some(session.id);
+                        }
+                    }
+
+                    db_session
+                })
+        )
+    }
+}
+
+pub struct DbSession {
+    id: Option<String>,
+    state: HashMap<String, String>,
+    // USER_SESSION as loaded, to notice sign-in and sign-out.
+    loaded_user: Option<String>,
+    changed: bool,
+    stale: bool,
+    db: Addr<DbExecutor>,
+    request_id: String,
+    config: Rc<Config>,
+    timeouts: SessionTimeouts,
+}
+
+impl DbSession {
+    fn new(db: Addr<DbExecutor>, request_id: String, config: Rc<Config>, timeouts: SessionTimeouts) -> Self {
+        DbSession {
+            id: None,
+            state: HashMap::new(),
+            loaded_user: None,
+            changed: false,
+            stale: false,
+            db,
+            request_id,
+            config,
+            timeouts,
+        }
+    }
+
+    fn set_cookie(&self, resp: &mut HttpResponse, value: &str, max_age: i64) {
+        let cookie = format!(
+            "{}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Lax{}",
+            COOKIE_NAME,
+            value,
+            max_age,
+            if self.config.secure { "; Secure" } else { "" },
+        );
+
+        if let Ok(value) = HeaderValue::from_str(&cookie) {
+            resp.headers_mut().append(header::SET_COOKIE, value);
+        }
+    }
+}
+
+impl SessionImpl for DbSession {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.state.get(key).map(String::as_str)
+    }
+
+    fn set(&mut self, key: &str, value: String) {
+        self.changed = true;
+        self.state.insert(key.to_owned(), value);
+    }
+
+    fn remove(&mut self, key: &str) {
+        self.changed = true;
+        self.state.remove(key);
+    }
+
+    fn clear(&mut self) {
+        self.changed = true;
+        self.state.clear();
+    }
+
+    fn write(&self, mut resp: HttpResponse) -> Result<Response> {
+        if self.state.is_empty() {
+            return match self.id {
+                Some(ref id) => {
+                    self.set_cookie(&mut resp, "", 0);
+
+                    Ok(Response::Future(Box::new(
+                        self.db
+                            .send(sessions_message::DeleteSession{
+                                request_id: self.request_id.clone(),
+                                id: id.clone(),
+                            })
+                            .from_err()
+                            .and_then(|res| res)
+                            .map(move |_| resp)
+                    )))
+                },
+                None => Ok(Response::Done(resp)),
+            };
+        }
+
+        if !self.changed && !self.stale {
+            return Ok(Response::Done(resp));
+        }
+
+        // A new id whenever the signed in user changes, so an id planted before
+        // sign-in is worthless afterwards.
+        let rotate = self.id.is_none() || self.state.get(USER_SESSION_KEY) != self.loaded_user.as_ref();
+        let id = match self.id {
+            Some(ref id) if !rotate => id.clone(),
+            _                       => create_session_id(),
+        };
+        let user_id = self.state
+            .get(USER_SESSION_KEY)
+            .and_then(|value| serde_json::from_str::<UserSession>(value).ok())
+            .map(|user_session| user_session.user_id);
+        let data = serde_json::to_string(&self.state)?;
+
+        if rotate {
+            self.set_cookie(&mut resp, &id, self.timeouts.absolute.num_seconds());
+        }
+
+        Ok(Response::Future(Box::new(
+            self.db
+                .send(sessions_message::WriteSession{
+                    request_id: self.request_id.clone(),
+                    id,
+                    previous_id: if rotate { self.id.clone() } else { None },
+                    user_id,
+                    data,
+                })
+                .from_err()
+                .and_then(|res| res)
+                .map(move |_| resp)
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_ids_are_opaque_and_validated() {
+        let id = create_session_id();
+        assert!(valid_session_id(&id));
+        assert_ne!(id, create_session_id());
+        assert!(!valid_session_id("short"));
+        assert!(!valid_session_id(&format!("{}'", &id[1..])));
+    }
+
+    #[test]
+    fn cutoffs_subtract_timeouts() {
+        let timeouts = SessionTimeouts{idle: Duration::minutes(30), absolute: Duration::hours(24)};
+        let now = NaiveDateTime::from_timestamp(1_000_000, 0);
+        assert_eq!(
+            timeouts.cutoffs(now),
+            (NaiveDateTime::from_timestamp(1_000_000 - 1800, 0), NaiveDateTime::from_timestamp(1_000_000 - 86400, 0)),
+        );
+    }
+}
@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use uuid::Uuid;
+
+use actix::prelude::*;
+
+use db::{DbExecutor, sessions_message};
+use settings::{SharedSettings};
+
+// Deletes expired sessions every `interval`. Expired sessions are already
+// rejected when read; this only keeps the table from growing.
+pub struct SessionPurger {
+    pub db: Addr<DbExecutor>,
+    pub settings: SharedSettings,
+    pub interval: Duration,
+}
+
+impl Actor for SessionPurger {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(self.interval, |act, ctx| act.purge(ctx));
+    }
+}
+
+impl SessionPurger {
+    fn purge(&self, ctx: &mut Context<Self>) {
+        let request_id = Uuid::new_v4().to_string();
+        let (idle_since, created_since) = self.settings.current().session_timeouts.cutoffs(Utc::now().naive_utc());
+
+        self.db
+            .send(sessions_message::PurgeSessions{
+                request_id: request_id.clone(),
+                idle_since,
+                created_since,
+            })
+            .into_actor(self)
+            .map(move |res, _, _| {
+                if let Ok(purged) = res {
+                    info!("{}", json!({
+                        "event":      "sessions_purged",
+                        "request_id": request_id,
+                        "purged":     purged,
+                    }));
+                }
+            })
+            .map_err(|_, _, _| ())
+            .spawn(ctx);
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::io;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+use chrono::Duration;
+
+use passwords::{PasswordPolicy};
+use sessions::{SessionTimeouts};
+
+// Settings that can change while the server runs; SIGHUP re-reads them.
+// Listener address, thread counts and the shutdown timeout only apply at start.
+pub struct Settings {
+    pub password_policy: PasswordPolicy,
+    pub session_timeouts: SessionTimeouts,
+    // How long a deleted account can still be restored before it is purged.
+    pub deletion_grace: Duration,
+    // How long an invitation link can be used.
+    pub invitation_ttl: Duration,
+}
+
+impl Settings {
+    pub fn from_env() -> Self {
+        Settings::from_vars(&EnvVars::process())
+    }
+
+    pub fn from_vars(vars: &EnvVars) -> Self {
+        Settings {
+            password_policy: PasswordPolicy::from_vars(vars),
+            session_timeouts: SessionTimeouts::from_vars(vars),
+            deletion_grace: Duration::days(vars.or("ACCOUNT_DELETION_GRACE_DAYS", 14)),
+            invitation_ttl: Duration::days(vars.or("INVITATION_TTL_DAYS", 7)),
+        }
+    }
+}
+
+// The names of the variables the process was started with, taken before
+// `dotenv()` adds the ones from `.env`.
+#[derive(Clone, Default)]
+pub struct StartEnv(HashSet<String>);
+
+impl StartEnv {
+    pub fn capture() -> Self {
+        StartEnv(env::vars_os().filter_map(|(key, _)| key.into_string().ok()).collect())
+    }
+}
+
+// The process environment, with the assignments from an env file on top.
+// Reloads read `.env` into this rather than into the environment, which
+// other threads may be reading at the same time.
+#[derive(Default)]
+pub struct EnvVars {
+    overrides: HashMap<String, String>,
+}
+
+impl EnvVars {
+    pub fn process() -> Self {
+        EnvVars::default()
+    }
+
+    // Same precedence as `dotenv()` at start: variables the process was started
+    // with win, the file wins over what `dotenv()` took from an older version of it.
+    pub fn with_file(path: &str, start_env: &StartEnv) -> io::Result<Self> {
+        Ok(EnvVars::with_assignments(parse_env_file(&fs::read_to_string(path)?), start_env))
+    }
+
+    fn with_assignments(assignments: Vec<(String, String)>, start_env: &StartEnv) -> Self {
+        EnvVars {
+            overrides: assignments
+                .into_iter()
+                .filter(|(key, _)| !start_env.0.contains(key))
+                .collect(),
+        }
+    }
+
+    pub fn var(&self, key: &str) -> Option<String> {
+        self.overrides
+            .get(key)
+            .cloned()
+            .or_else(|| env::var(key).ok())
+    }
+
+    pub fn or<T: FromStr>(&self, key: &str, default: T) -> T {
+        self.var(key)
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default)
+    }
+}
+
+// Readers take a snapshot with `current()` and keep it for the rest of the
+// request, so a reload never changes settings halfway through one.
+#[derive(Clone)]
+pub struct SharedSettings(Arc<RwLock<Arc<Settings>>>);
+
+impl SharedSettings {
+    pub fn new(settings: Settings) -> Self {
+        SharedSettings(Arc::new(RwLock::new(Arc::new(settings))))
+    }
+
+    pub fn current(&self) -> Arc<Settings> {
+        self.0.read().unwrap().clone()
+    }
+
+    pub fn replace(&self, settings: Settings) {
+        *self.0.write().unwrap() = Arc::new(settings);
+    }
+}
+
+pub fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    EnvVars::process().or(key, default)
+}
+
+fn parse_env_file(content: &str) -> Vec<(String, String)> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.trim_start_matches("export "))
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next()?.trim();
+            let value = parts.next()?.trim();
+            let value = if value.len() >= 2 && (value.starts_with('"') && value.ends_with('"') || value.starts_with('\'') && value.ends_with('\'')) {
+                &value[1..value.len() - 1]
+            } else {
+                value
+            };
+
+            if key.is_empty() {
+                None
+            } else {
+                Some((key.to_string(), value.to_string()))
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_env_file_reads_assignments() {
+        let content = "# comment\n\nDATABASE_URL=test.db\nexport BCRYPT_COST = 12\nNAME=\"quoted value\"\nbroken line\n";
+
+        assert_eq!(parse_env_file(content), vec![
+            ("DATABASE_URL".to_string(), "test.db".to_string()),
+            ("BCRYPT_COST".to_string(), "12".to_string()),
+            ("NAME".to_string(), "quoted value".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn env_vars_prefer_the_start_environment_then_the_file() {
+        let start_env = StartEnv(vec!["PATH".to_string()].into_iter().collect());
+        let vars = EnvVars::with_assignments(parse_env_file("INVITATION_TTL_DAYS=3\nPATH=/nowhere\n"), &start_env);
+
+        assert_eq!(Settings::from_vars(&vars).invitation_ttl, Duration::days(3));
+        assert_eq!(vars.var("PATH"), env::var("PATH").ok());
+        assert!(StartEnv::capture().0.contains("PATH"));
+        assert_eq!(vars.var("WEBAPP_SAMPLE_UNSET"), None);
+        assert_eq!(vars.or("WEBAPP_SAMPLE_UNSET", 5), 5);
+        assert_eq!(EnvVars::process().var("PATH"), env::var("PATH").ok());
+    }
+}
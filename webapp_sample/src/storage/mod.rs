@@ -0,0 +1,102 @@
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+// Blob storage for user uploaded files.
+// Keys are relative, slash separated paths such as `avatars/<uuid>/<version>/128.png`,
+// so an S3 compatible backend can use them as object keys unchanged.
+pub trait Storage: Send + Sync {
+    fn put(&self, key: &str, data: &[u8]) -> io::Result<()>;
+    fn get(&self, key: &str) -> io::Result<Vec<u8>>;
+    fn delete_prefix(&self, prefix: &str) -> io::Result<()>;
+}
+
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    fn path(&self, key: &str) -> io::Result<PathBuf> {
+        let relative = Path::new(key);
+        let valid = relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+
+        if key.is_empty() || !valid {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid storage key"));
+        }
+
+        Ok(self.root.join(relative))
+    }
+}
+
+impl Storage for LocalStorage {
+    fn put(&self, key: &str, data: &[u8]) -> io::Result<()> {
+        let path = self.path(key)?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // Write to a temporary file first so readers never see a partial image.
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, data)?;
+        fs::rename(&tmp, &path)
+    }
+
+    fn get(&self, key: &str) -> io::Result<Vec<u8>> {
+        fs::read(self.path(key)?)
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> io::Result<()> {
+        let path = self.path(prefix)?;
+
+        match fs::remove_dir_all(&path) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            other                                             => other,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod memory {
+    use std::collections::HashMap;
+    use std::io;
+    use std::sync::Mutex;
+
+    use super::Storage;
+
+    // In-memory stand-in for tests.
+    #[derive(Default)]
+    pub struct MemoryStorage {
+        pub blobs: Mutex<HashMap<String, Vec<u8>>>,
+    }
+
+    impl Storage for MemoryStorage {
+        fn put(&self, key: &str, data: &[u8]) -> io::Result<()> {
+            self.blobs.lock().unwrap().insert(key.to_string(), data.to_vec());
+            Ok(())
+        }
+
+        fn get(&self, key: &str) -> io::Result<Vec<u8>> {
+            self.blobs
+                .lock()
+                .unwrap()
+                .get(key)
+                .cloned()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, key.to_string()))
+        }
+
+        fn delete_prefix(&self, prefix: &str) -> io::Result<()> {
+            let prefix = format!("{}/", prefix);
+            self.blobs.lock().unwrap().retain(|key, _| !key.starts_with(&prefix));
+            Ok(())
+        }
+    }
+}
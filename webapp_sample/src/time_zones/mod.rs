@@ -0,0 +1,155 @@
+use chrono::{DateTime, Duration, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use chrono_tz::{Tz, TZ_VARIANTS};
+use handlebars::{Context, Handlebars, Helper, HelperDef, HelperResult, Output, RenderContext, RenderError};
+use serde_json::{Value};
+
+use actix_web::{FromRequest, HttpRequest};
+
+use sessions::cache::{ValidSession};
+
+// For visitors, and for users whose stored zone is no longer known.
+pub const DEFAULT: Tz = Tz::UTC;
+
+// Older times are shown as a date rather than "n days ago".
+const RELATIVE_DAYS: i64 = 30;
+
+// An IANA name such as `Asia/Tokyo`.
+pub fn parse(name: &str) -> Option<Tz> {
+    name.parse().ok()
+}
+
+// Every zone a user can choose, for the profile form.
+pub fn names() -> impl Iterator<Item = &'static str> {
+    TZ_VARIANTS.iter().map(|zone| zone.name())
+}
+
+// The signed-in user's zone, as `Authenticate` found it; `DEFAULT` on public routes.
+#[derive(Clone, Copy, Debug)]
+pub struct ViewerTimeZone(pub Tz);
+
+impl<S> FromRequest<S> for ViewerTimeZone {
+    type Config = ();
+    type Result = Self;
+
+    fn from_request(req: &HttpRequest<S>, _: &Self::Config) -> Self::Result {
+        match req.extensions().get::<ValidSession>() {
+            Some(session) => ViewerTimeZone(session.time_zone),
+            None          => ViewerTimeZone(DEFAULT),
+        }
+    }
+}
+
+impl ViewerTimeZone {
+    pub fn name(self) -> &'static str {
+        self.0.name()
+    }
+
+    // `utc`, as read from the database, on the viewer's clock.
+    pub fn localize(self, utc: NaiveDateTime) -> DateTime<Tz> {
+        self.0.from_utc_datetime(&utc)
+    }
+
+    pub fn format(self, utc: NaiveDateTime) -> String {
+        self.localize(utc).format("%Y-%m-%d %H:%M %Z").to_string()
+    }
+}
+
+// "3分前" / "2時間後"; a date once it is more than `RELATIVE_DAYS` away.
+fn relative(time: DateTime<Tz>, now: DateTime<Utc>) -> String {
+    let elapsed = now.signed_duration_since(time);
+    let (span, suffix) = if elapsed >= Duration::zero() {
+        (elapsed, "前")
+    } else {
+        (-elapsed, "後")
+    };
+
+    if span < Duration::minutes(1) {
+        "たった今".to_string()
+    } else if span < Duration::hours(1) {
+        format!("{}分{}", span.num_minutes(), suffix)
+    } else if span < Duration::days(1) {
+        format!("{}時間{}", span.num_hours(), suffix)
+    } else if span < Duration::days(RELATIVE_DAYS) {
+        format!("{}日{}", span.num_days(), suffix)
+    } else {
+        time.format("%Y-%m-%d").to_string()
+    }
+}
+
+// `{{date micropost.created_at}}` writes the time in the viewer's zone, taken
+// from `time_zone` in the page data. `{{date micropost.created_at "relative"}}`
+// writes a `<time>` element saying how long ago it was, with the full time as
+// its title. Nothing is written for null.
+pub struct DateHelper;
+
+impl HelperDef for DateHelper {
+    fn call<'reg: 'rc, 'rc>(&self, h: &Helper<'reg, 'rc>, _: &'reg Handlebars, ctx: &'rc Context, _: &mut RenderContext<'reg>, out: &mut dyn Output) -> HelperResult {
+        let value = h.param(0)
+            .map(|param| param.value())
+            .ok_or_else(|| RenderError::new("date: expected a time"))?;
+        let utc = match *value {
+            Value::Null          => return Ok(()),
+            Value::String(ref s) => s.parse::<NaiveDateTime>()
+                .map_err(|_| RenderError::new(format!("date: not a time: {}", s)))?,
+            _                    => return Err(RenderError::new("date: expected a time")),
+        };
+
+        let zone = ViewerTimeZone(ctx
+            .data()
+            .get("time_zone")
+            .and_then(Value::as_str)
+            .and_then(parse)
+            .unwrap_or(DEFAULT));
+
+        match h.param(1).and_then(|param| param.value().as_str()) {
+            None             => out.write(&zone.format(utc))?,
+            Some("relative") => out.write(&format!(
+                "<time datetime=\"{}\" title=\"{}\">{}</time>",
+                DateTime::<Utc>::from_utc(utc, Utc).to_rfc3339_opts(SecondsFormat::Secs, true),
+                zone.format(utc),
+                relative(zone.localize(utc), Utc::now()),
+            ))?,
+            Some(style)      => return Err(RenderError::new(format!("date: unknown style: {}", style))),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json;
+
+    fn render(template: &str, data: Value) -> String {
+        let mut templates = Handlebars::new();
+        templates.register_helper("date", Box::new(DateHelper));
+        templates.render_template(template, &data).unwrap()
+    }
+
+    #[test]
+    fn date_renders_in_the_viewers_zone() {
+        let created_at = serde_json::to_value(NaiveDateTime::from_timestamp(1_792_402_200, 0)).unwrap();
+
+        assert_eq!(
+            render("{{#each items as |item|}}{{date item}}{{/each}}", json!({"items": [created_at], "time_zone": "Asia/Tokyo"})),
+            "2026-10-19 18:30 JST",
+        );
+        assert_eq!(render("{{date at}}", json!({"at": created_at})), "2026-10-19 09:30 UTC");
+        assert_eq!(render("{{date at}}", json!({"at": created_at, "time_zone": "Nowhere/City"})), "2026-10-19 09:30 UTC");
+        assert_eq!(render("{{date at}}", json!({"at": null})), "");
+        assert!(render("{{date at \"relative\"}}", json!({"at": created_at})).starts_with("<time datetime=\"2026-10-19T09:30:00Z\" title=\"2026-10-19 09:30 UTC\">"));
+    }
+
+    #[test]
+    fn relative_counts_the_largest_unit() {
+        let now = Utc.ymd(2026, 10, 19).and_hms(9, 30, 0);
+        let at = |utc: DateTime<Utc>| utc.with_timezone(&Tz::Asia__Tokyo);
+
+        assert_eq!(relative(at(now - Duration::seconds(20)), now), "たった今");
+        assert_eq!(relative(at(now - Duration::minutes(3)), now), "3分前");
+        assert_eq!(relative(at(now - Duration::hours(5)), now), "5時間前");
+        assert_eq!(relative(at(now + Duration::days(2)), now), "2日後");
+        assert_eq!(relative(at(now - Duration::days(40)), now), "2026-09-09");
+    }
+}
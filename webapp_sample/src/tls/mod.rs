@@ -0,0 +1,181 @@
+use std::env;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::sync::{Arc, RwLock};
+
+use rustls::{NoClientAuth, PrivateKey, ResolvesServerCert, ServerConfig, SignatureScheme};
+use rustls::internal::pemfile;
+use rustls::sign::{self, CertifiedKey};
+use webpki::{DNSNameRef};
+
+use actix_web::{HttpRequest, HttpResponse};
+use actix_web::http::{header, Method};
+
+// Where the PEM certificate chain and private key are read from.
+// HTTPS is on when both TLS_CERT_PATH and TLS_KEY_PATH are set.
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    pub cert_path: String,
+    pub key_path: String,
+}
+
+impl TlsConfig {
+    pub fn from_env() -> Option<Self> {
+        match (env::var("TLS_CERT_PATH"), env::var("TLS_KEY_PATH")) {
+            (Ok(cert_path), Ok(key_path)) => Some(TlsConfig{cert_path, key_path}),
+            _                             => None,
+        }
+    }
+}
+
+// Hands the current certificate to every handshake. `reload` reads the files
+// again, so a renewed certificate is used without a restart; connections
+// that are already open keep the old one.
+pub struct Certificates {
+    config: TlsConfig,
+    current: RwLock<CertifiedKey>,
+}
+
+impl Certificates {
+    pub fn load(config: TlsConfig) -> io::Result<Self> {
+        let current = read_certified_key(&config)?;
+
+        Ok(Certificates {
+            config,
+            current: RwLock::new(current),
+        })
+    }
+
+    // Keeps the old certificate if the new files cannot be read.
+    pub fn reload(&self) -> io::Result<()> {
+        let certified_key = read_certified_key(&self.config)?;
+        *self.current.write().unwrap() = certified_key;
+        Ok(())
+    }
+}
+
+impl ResolvesServerCert for Certificates {
+    fn resolve(&self, _server_name: Option<DNSNameRef>, _sigschemes: &[SignatureScheme]) -> Option<CertifiedKey> {
+        Some(self.current.read().unwrap().clone())
+    }
+}
+
+pub fn server_config(certificates: Arc<Certificates>) -> ServerConfig {
+    let mut config = ServerConfig::new(NoClientAuth::new());
+    config.cert_resolver = certificates;
+    config
+}
+
+fn read_certified_key(config: &TlsConfig) -> io::Result<CertifiedKey> {
+    let cert = pemfile::certs(&mut BufReader::new(File::open(&config.cert_path)?))
+        .map_err(|()| invalid_data(&config.cert_path, "unreadable certificate"))?;
+    if cert.is_empty() {
+        return Err(invalid_data(&config.cert_path, "no certificate"));
+    }
+
+    let key = read_private_key(&config.key_path)?;
+    let key = sign::any_supported_type(&key)
+        .map_err(|()| invalid_data(&config.key_path, "unsupported private key"))?;
+
+    Ok(CertifiedKey::new(cert, Arc::new(key)))
+}
+
+// PKCS#8 as `openssl req -newkey` writes it, or a traditional RSA key.
+fn read_private_key(path: &str) -> io::Result<PrivateKey> {
+    let mut keys = pemfile::pkcs8_private_keys(&mut BufReader::new(File::open(path)?))
+        .map_err(|()| invalid_data(path, "unreadable private key"))?;
+    if keys.is_empty() {
+        keys = pemfile::rsa_private_keys(&mut BufReader::new(File::open(path)?))
+            .map_err(|()| invalid_data(path, "unreadable private key"))?;
+    }
+
+    keys.into_iter()
+        .next()
+        .ok_or_else(|| invalid_data(path, "no private key"))
+}
+
+fn invalid_data(path: &str, message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", message, path))
+}
+
+// Sends plain HTTP requests to the same host and path on `https_port`.
+// GET and HEAD get a permanent redirect; other methods 308, which keeps the
+// method and body.
+pub fn redirect_to_https<S>(req: &HttpRequest<S>, https_port: u16) -> HttpResponse {
+    let host = req.connection_info().host().to_string();
+    let path = req.uri().path_and_query().map_or("/", |path| path.as_str());
+    let location = https_location(&host, path, https_port);
+
+    let mut resp = match *req.method() {
+        Method::GET | Method::HEAD => HttpResponse::MovedPermanently(),
+        _                          => HttpResponse::PermanentRedirect(),
+    };
+    resp.header(header::LOCATION, location).finish()
+}
+
+fn https_location(host: &str, path: &str, https_port: u16) -> String {
+    // Drop the port of the plain listener; IPv6 hosts keep their brackets.
+    let hostname = match host.rfind(':') {
+        Some(i) if !host[i..].contains(']') => &host[..i],
+        _                                   => host,
+    };
+
+    match https_port {
+        443  => format!("https://{}{}", hostname, path),
+        port => format!("https://{}:{}{}", hostname, port, path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use uuid::Uuid;
+
+    fn testdata(name: &str) -> String {
+        format!("./src/tls/testdata/{}", name)
+    }
+
+    fn served_cert(certificates: &Certificates) -> Vec<u8> {
+        let certified_key = certificates.resolve(None, &[]).unwrap();
+        certified_key.end_entity_cert().unwrap().0.clone()
+    }
+
+    #[test]
+    fn reload_switches_to_the_certificate_on_disk() {
+        let dir = env::temp_dir().join(format!("webapp_sample_tls_{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let config = TlsConfig {
+            cert_path: dir.join("cert.pem").to_string_lossy().into_owned(),
+            key_path: dir.join("key.pem").to_string_lossy().into_owned(),
+        };
+
+        fs::copy(testdata("localhost.crt"), &config.cert_path).unwrap();
+        fs::copy(testdata("localhost.key"), &config.key_path).unwrap();
+        let certificates = Certificates::load(config.clone()).unwrap();
+        let first = served_cert(&certificates);
+
+        fs::copy(testdata("other.crt"), &config.cert_path).unwrap();
+        fs::copy(testdata("other.key"), &config.key_path).unwrap();
+        certificates.reload().unwrap();
+        let second = served_cert(&certificates);
+        assert_ne!(first, second);
+
+        // A broken file leaves the certificate in use alone.
+        fs::write(&config.key_path, "not a key").unwrap();
+        assert!(certificates.reload().is_err());
+        assert_eq!(served_cert(&certificates), second);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn https_location_replaces_the_port() {
+        assert_eq!(https_location("example.com:8080", "/users?page=2", 443), "https://example.com/users?page=2");
+        assert_eq!(https_location("localhost:8080", "/", 8443), "https://localhost:8443/");
+        assert_eq!(https_location("[::1]:8080", "/signin", 8443), "https://[::1]:8443/signin");
+        assert_eq!(https_location("[::1]", "/", 443), "https://[::1]/");
+    }
+}
@@ -0,0 +1,68 @@
+#!/bin/sh
+# Downloads the front-end libraries referenced by src/views/layout.hbs and
+# src/views/api_docs.hbs into src/public and checks them against the
+# integrity hashes used in the templates, together with each library's
+# license. Run once on a connected machine and commit the files.
+set -eu
+
+cd "$(dirname "$0")/src/public"
+
+fetch() {
+    url=$1
+    dest=$2
+    integrity=$3
+
+    curl -sSfL -o "$dest" "$url"
+
+    actual="sha384-$(openssl dgst -sha384 -binary "$dest" | openssl base64 -A)"
+    if [ "$actual" != "$integrity" ]; then
+        echo "integrity mismatch for $dest: $actual" >&2
+        rm -f "$dest"
+        exit 1
+    fi
+    echo "$dest"
+}
+
+license() {
+    url=$1
+    dest=$2
+
+    curl -sSfL -o "$dest" "$url"
+    echo "$dest"
+}
+
+fetch https://stackpath.bootstrapcdn.com/bootstrap/4.1.3/css/bootstrap.min.css \
+    css/bootstrap-4.1.3.min.css \
+    sha384-MCw98/SFnGE8fJT3GXwEOngsV7Zt27NXFoaoApmYm81iuXoPkFOJwJ8ERdknLPMO
+
+license https://unpkg.com/bootstrap@4.1.3/LICENSE \
+    css/bootstrap-4.1.3.LICENSE
+
+fetch https://code.jquery.com/jquery-3.3.1.slim.min.js \
+    js/jquery-3.3.1.slim.min.js \
+    sha384-q8i/X+965DzO0rT7abK41JStQIAqVgRVzpbzo5smXKp4YfRvH+8abtTE1Pi6jizo
+
+license https://unpkg.com/jquery@3.3.1/LICENSE.txt \
+    js/jquery-3.3.1.LICENSE
+
+fetch https://cdnjs.cloudflare.com/ajax/libs/popper.js/1.14.3/umd/popper.min.js \
+    js/popper-1.14.3.min.js \
+    sha384-ZMP7rVo3mIykV+2+9J3UJ46jBk0WLaUAdn689aCwoqbBJiSnjAK/l8WvCWPIPm49
+
+license https://unpkg.com/popper.js@1.14.3/LICENSE.md \
+    js/popper-1.14.3.LICENSE
+
+fetch https://stackpath.bootstrapcdn.com/bootstrap/4.1.3/js/bootstrap.min.js \
+    js/bootstrap-4.1.3.min.js \
+    sha384-ChfqqxuZUCnJSK3+MXmPNIyE6ZbWh2IMqE241rYiqJxyMiZ6OW/JmZQ5stwEULTy
+
+license https://unpkg.com/bootstrap@4.1.3/LICENSE \
+    js/bootstrap-4.1.3.LICENSE
+
+fetch https://unpkg.com/swagger-ui-dist@5.17.14/swagger-ui-bundle.js \
+    swagger-ui/swagger-ui-bundle.js \
+    sha384-wmyclcVGX/WhUkdkATwhaK1X1JtiNrr2EoYJ+diV3vj4v6OC5yCeSu+yW13SYJep
+
+fetch https://unpkg.com/swagger-ui-dist@5.17.14/swagger-ui.css \
+    swagger-ui/swagger-ui.css \
+    sha384-wxLW6kwyHktdDGr6Pv1zgm/VGJh99lfUbzSn6HNHBENZlCN7W602k9VkGdxuFvPn